@@ -1,5 +1,7 @@
 use crate::input::{field_quote, ApiInputField};
-use crud_api_endpoint::{arg_config, endpoints, ApiInputConfig, Emap, Endpoint, VecStringWrapper};
+use crud_api_endpoint::{
+  arg_config, endpoints, input_map, ApiInputConfig, DataSerde, Emap, Endpoint, VecStringWrapper,
+};
 use proc_macro2::{Ident, Span, TokenStream};
 use quote::quote;
 
@@ -49,30 +51,171 @@ fn output_format(ep: &[Endpoint]) -> proc_macro2::TokenStream {
       quote!(Some(&[#(#format_list),*]))
     };
 
-    let output_arg_config = arg_config(
-      "output_format",
-      &ep
-        .iter()
-        .flat_map(|ep| {
-          if !ep.result_is_stream {
-            ep.config.to_owned()
-          } else {
-            vec![]
-          }
-        })
-        .collect::<Vec<ApiInputConfig>>(),
-    );
+    let config = ep
+      .iter()
+      .flat_map(|ep| {
+        if !ep.result_is_stream {
+          ep.config.to_owned()
+        } else {
+          vec![]
+        }
+      })
+      .collect::<Vec<ApiInputConfig>>();
+    let output_arg_config = arg_config("output_format", &config);
     let long = output_arg_config.long.unwrap();
     let short = output_arg_config.short.unwrap();
     let heading = output_arg_config.heading.unwrap();
 
+    let template_arg = |name| {
+      let field: ApiInputField = arg_config(name, &config).into();
+      field_quote(&field, None, None)
+    };
+    let template = template_arg("output_template");
+    let template_file = template_arg("output_template_file");
+
     quote!(let command = crud_api::clap_output_format_decl(command, #formats,
-								#long, #short,#heading); )
+								#long, #short,#heading);
+	   let command = crud_api::clap_output_template_decl(command, #template, #template_file); )
   } else {
     quote! {}
   }
 }
 
+/// Generate the `--fields`, `--filter`, `--sort` and `--query` arguments if needed.
+fn selection(ep: &[Endpoint]) -> proc_macro2::TokenStream {
+  if ep
+    .iter()
+    .any(|ep| !ep.result_is_stream && !ep.cli_no_output)
+  {
+    let config = ep
+      .iter()
+      .filter(|ep| !ep.result_is_stream)
+      .flat_map(|ep| ep.config.to_owned())
+      .collect::<Vec<ApiInputConfig>>();
+    // An argument of the query or of the payload can have the same name: the selection
+    // argument is renamed after its id (`--select-fields`, `--output-query`...).
+    let taken = input_longs(ep);
+    let arg = |name: &str| {
+      let mut config = arg_config(name, &config);
+      if config
+        .long
+        .as_ref()
+        .is_some_and(|long| taken.contains(long))
+      {
+        config.long = Some(name.replace('_', "-"));
+      }
+      let field: ApiInputField = config.into();
+      field_quote(&field, None, None)
+    };
+    let fields_arg = arg("select_fields");
+    let filter_arg = arg("select_filter");
+    let sort_arg = arg("select_sort");
+    let query_arg = arg("output_query");
+    // `--sort -updated_at`
+    quote!(let command = command
+	   .arg(#fields_arg)
+	   .arg(#filter_arg)
+	   .arg(#sort_arg.allow_hyphen_values(true))
+	   .arg(#query_arg);)
+  } else {
+    quote! {}
+  }
+}
+
+/// The long names of the arguments of the query and payload structs of the endpoints.
+fn input_longs(ep: &[Endpoint]) -> Vec<String> {
+  let inputs = input_map();
+  ep.iter()
+    .flat_map(|ep| [&ep.query_struct, &ep.payload_struct])
+    .flatten()
+    .filter_map(|input| inputs.get(input))
+    .flat_map(|input| match &input.data {
+      DataSerde::Struct(fields) => fields
+        .iter()
+        .filter_map(|field| field.long.as_ref().or(field.ident.as_ref()))
+        .map(|long| match &input.prefix {
+          Some(prefix) => format!("{prefix}-{long}").to_lowercase(),
+          None => long.to_string(),
+        })
+        .collect(),
+      DataSerde::Enum(_) => vec![],
+    })
+    .collect()
+}
+
+/// Generate the pagination arguments if needed.
+fn pagination(ep: &[Endpoint]) -> proc_macro2::TokenStream {
+  if ep
+    .iter()
+    .any(|ep| ep.result_multiple && ep.pagination.is_some())
+  {
+    let config = ep
+      .iter()
+      .flat_map(|ep| {
+        if ep.pagination.is_some() {
+          ep.config.to_owned()
+        } else {
+          vec![]
+        }
+      })
+      .collect::<Vec<ApiInputConfig>>();
+    let args: Vec<TokenStream> = [
+      "pagination_all",
+      "pagination_page",
+      "pagination_per_page",
+      "pagination_max_items",
+    ]
+    .iter()
+    .map(|arg| {
+      let field: ApiInputField = arg_config(arg, &config).into();
+      field_quote(&field, None, None)
+    })
+    .collect();
+    quote!(let command = command #(.arg(#args))*;)
+  } else {
+    quote! {}
+  }
+}
+
+/// Generate the `--continue`, `--sha256` and `--checksum-header` arguments of the downloads.
+fn download_args(ep: &[Endpoint]) -> proc_macro2::TokenStream {
+  let config = ep
+    .iter()
+    .filter(|ep| ep.result_is_stream)
+    .flat_map(|ep| ep.config.to_owned())
+    .collect::<Vec<ApiInputConfig>>();
+  let arg = |name| {
+    let field: ApiInputField = arg_config(name, &config).into();
+    field_quote(&field, None, None)
+  };
+  let continue_arg = arg("continue_download");
+  let sha256_arg = arg("sha256");
+  let checksum_header_arg = arg("checksum_header");
+  quote!(
+    .arg(#continue_arg.requires("output_file"))
+    .arg(#sha256_arg.conflicts_with("checksum_header"))
+    .arg(#checksum_header_arg)
+  )
+}
+
+/// Generate the `--body` argument of the raw payloads.
+fn raw_body(ep: &[Endpoint]) -> proc_macro2::TokenStream {
+  let raw: Vec<&Endpoint> = ep
+    .iter()
+    .filter(|ep| ep.payload_encoding.as_deref() == Some("raw"))
+    .collect();
+  if raw.is_empty() {
+    return quote! {};
+  }
+  let config = raw
+    .iter()
+    .flat_map(|ep| ep.config.to_owned())
+    .collect::<Vec<ApiInputConfig>>();
+  let field: ApiInputField = arg_config("body_file", &config).into();
+  let arg = field_quote(&field, None, None);
+  quote!(let command = command.arg(#arg);)
+}
+
 #[rustfmt::skip::macros(quote)]
 fn subcommand_rec(endpoints_map: &Emap) -> (proc_macro2::TokenStream, bool) {
   let mut output_file_flag = true;
@@ -101,13 +244,16 @@ fn subcommand_rec(endpoints_map: &Emap) -> (proc_macro2::TokenStream, bool) {
             .into();
             output_file_flag = false;
             let arg = field_quote(&field, None, None);
-            quote!{.arg(#arg)}
+            let download_args = download_args(ep);
+            quote!{.arg(#arg) #download_args}
           } else {
             quote!()
           };
 
           let output_format = if ep.iter().any(|ep| ep.cli_force_output_format) {
-            output_format(ep)
+            let output_format = output_format(ep);
+            let selection = selection(ep);
+            quote!{#output_format #selection}
           } else {
             quote!{}
           };
@@ -214,12 +360,16 @@ fn subcommand_rec(endpoints_map: &Emap) -> (proc_macro2::TokenStream, bool) {
             .into();
             output_file_flag = false;
             let arg = field_quote(&field, None, None);
-            quote!{ let command = command.arg(#arg);  /* #segment */}
+            let download_args = download_args(ep);
+            quote!{ let command = command.arg(#arg) #download_args;  /* #segment */}
           } else {
             quote!( )
           };
 
           let output_format = output_format(ep);
+          let selection = selection(ep);
+          let pagination = pagination(ep);
+          let raw_body = raw_body(ep);
 
           if subcommands.is_empty() && args.is_empty() {
             quote! {.subcommand({let command = clap::Command::new(#segment)
@@ -228,6 +378,9 @@ fn subcommand_rec(endpoints_map: &Emap) -> (proc_macro2::TokenStream, bool) {
 				 #visible_aliases #long_flag_aliases #aliases #short_flag_aliases;
 				 #(#query_args)*
 				 #output_format
+				 #selection
+				 #pagination
+				 #raw_body
 				 #output_stream
 				 command})}
           } else {
@@ -245,6 +398,9 @@ fn subcommand_rec(endpoints_map: &Emap) -> (proc_macro2::TokenStream, bool) {
 		#(#args)*
 		#(#query_args)*
 		#output_format
+		#selection
+		#pagination
+		#raw_body
 		#output_stream
 		#subcommands
 		command
@@ -267,8 +423,10 @@ pub(crate) fn subcommands() -> proc_macro2::TokenStream {
 
 #[cfg(test)]
 mod tests {
-  use crate::gen_clap_declarations::{is_var, strip_var, subcommand_rec};
-  use crud_api_endpoint::{Emap, EpNode};
+  use crate::gen_clap_declarations::{is_var, selection, strip_var, subcommand_rec};
+  use crud_api_endpoint::{
+    store_input, ApiInputFieldSerde, ApiInputSerde, DataSerde, Emap, Endpoint, EpNode,
+  };
   use std::collections::HashMap;
 
   #[test]
@@ -304,4 +462,40 @@ mod tests {
     );
     assert!(o);
   }
+
+  #[test]
+  fn test_selection_rename() {
+    let field = |ident: &str| ApiInputFieldSerde {
+      ident: Some(ident.into()),
+      ty: "Option<String>".into(),
+      long: None,
+      short: None,
+      no_short: Some(true),
+      heading: None,
+      help: None,
+      long_help: None,
+      possible_values: None,
+      required: None,
+      num_args: None,
+    };
+    store_input(
+      "IssueQuery".into(),
+      ApiInputSerde {
+        ident: "IssueQuery".into(),
+        data: DataSerde::Struct(vec![field("sort"), field("state")]),
+        no_input_file: true,
+        heading: None,
+        prefix: None,
+        config: vec![],
+      },
+    );
+    let ep = Endpoint {
+      query_struct: Some("IssueQuery".into()),
+      ..Default::default()
+    };
+    let args = selection(&[ep]).to_string();
+    assert!(args.contains(". long (\"select-sort\")"));
+    assert!(args.contains(". long (\"fields\")"));
+    assert!(!args.contains(". long (\"sort\")"));
+  }
 }
@@ -1,6 +1,11 @@
-use crate::gen_clap_declarations::strip_var;
-use crud_api_endpoint::{endpoints, Emap, Endpoint};
+use crate::{
+  gen_clap_declarations::strip_var,
+  input::{is_upload_file, ApiInput},
+};
+use crud_api_endpoint::{endpoints, input_map, Emap, Endpoint, Pagination};
+use darling::ast::Data;
 use proc_macro2::{Ident, Span, TokenStream};
+use proc_macro_error::abort_call_site;
 use quote::quote;
 
 #[rustfmt::skip::macros(quote)]
@@ -12,8 +17,10 @@ fn match_endpoint(
   let (paylay_decl, payload) = if let Some(payload_struct) = &ep.payload_struct {
     let payload_type = Ident::new(payload_struct, Span::call_site());
     let error_context = format!("Can't read payload for '{payload_struct}'");
+    let upload_files = upload_files(ep, payload_struct);
     (
       quote! {
+	  #upload_files
           let payload = <#payload_type>::from_clap_matches(#arg_ident)
               .context(#error_context)?;
           log::trace!("Payload: {:#?}",payload);
@@ -52,16 +59,19 @@ fn match_endpoint(
     quote! {#result}
   };
 
-  let output_format = if ep.cli_no_output {
-    quote!(None)
+  let (output_format, selection) = if ep.cli_no_output {
+    (quote!(None), quote!(crud_api::Selection::default()))
   } else {
-    quote!(crud_api::clap_match_output_format(#arg_ident))
+    (
+      quote!(crud_api::clap_match_output_format(#arg_ident)?),
+      quote!(crud_api::clap_match_selection(#arg_ident)?),
+    )
   };
 
   let result_output = if ep.result_multiple {
-    quote! {#result :: output_multiple(&result, #output_format )?;}
+    quote! {#result :: output_multiple(&result, #output_format, &#selection)?;}
   } else {
-    quote! {result.output(#output_format)?;}
+    quote! {result.output(#output_format, &#selection)?;}
   };
   let method = Ident::new(&ep.method, Span::call_site());
   let status = Ident::new(&ep.result_ok_status, Span::call_site());
@@ -127,26 +137,116 @@ fn match_endpoint(
     quote!(None::<std::marker::PhantomData<crud_api::DummyTryFrom>>)
   };
 
+  let pagination = match &ep.pagination {
+    Some(pagination) if ep.result_multiple => {
+      let kind = match pagination.kind.as_str() {
+        "page" => quote!(Page),
+        "link" => quote!(Link),
+        "cursor" => quote!(Cursor),
+        kind => abort_call_site!(
+          "Unknown pagination kind '{}'", kind;
+          help = "Use one of 'page', 'link' or 'cursor'"
+        ),
+      };
+      let Pagination {
+        page_param,
+        per_page_param,
+        cursor_param,
+        cursor_path,
+        items_path,
+        ..
+      } = pagination;
+      quote!(.paginate(crud_api::pagination::Pagination {
+	  kind: crud_api::pagination::PaginationKind::#kind,
+	  page_param: #page_param,
+	  per_page_param: #per_page_param,
+	  cursor_param: #cursor_param,
+	  cursor_path: #cursor_path,
+	  items_path: #items_path,
+      }, crud_api::clap_match_pagination(#arg_ident)))
+    }
+    _ => quote!(),
+  };
+
+  let retry = retry_policy(
+    ep.timeout,
+    ep.retries,
+    ep.retry_on.as_deref(),
+    ep.retry_non_idempotent,
+  )
+  .map(|policy| quote!(.retry_policy(#policy)));
+  let cache_ttl = ep.cache_ttl.map(|ttl| quote!(.cache_ttl(#ttl)));
+  let response_format = ep.response_format.as_deref().map(response_format);
+  let payload_encoding = payload_encoding(ep, arg_ident);
+
   let query_and_print = if ep.result_is_stream {
-    quote!(crud_api::http::HTTPApi::new(format!(#urif,base_url #ids),
+    quote!(crud_api::http::HTTPApi::new(&http_client,
+				     format!(#urif,base_url #ids),
 				     hyper::Method::#method,
 				     hyper::StatusCode::#status,
 				     #ko_status_map,
 				     #auth,
 				     &extra_headers)
+	   #retry
+	   #payload_encoding
+	   .resume_download(#arg_ident
+			    .try_get_one::<bool>("continue_download")
+			    .ok()
+			    .flatten()
+			    .copied()
+			    .unwrap_or_default())
+	   .checksum(#arg_ident
+		     .try_get_one::<String>("sha256")
+		     .ok()
+		     .flatten()
+		     .cloned(),
+		     #arg_ident
+		     .try_get_one::<String>("checksum_header")
+		     .ok()
+		     .flatten()
+		     .cloned())
 	   .stream(#payload,
 		   #query_args,
 		   #arg_ident.get_one::<String>("output_file").cloned()).await?;
     )
+  } else if ep.result_multiple
+    && ep.transform_from.is_none()
+    && ep.extra_action.is_none()
+    && !ep.cli_no_output
+  {
+    // The results are printed while they're received.
+    quote!(
+	let mut rows = crud_api::Rows::<#result>::new(#output_format, #selection)?;
+	crud_api::http::HTTPApi::new(&http_client,
+				     format!(#urif,base_url #ids),
+				      hyper::Method::#method,
+				      hyper::StatusCode::#status,
+				      #ko_status_map,
+				      #auth,
+				      &extra_headers)
+	    #pagination
+	    #retry
+	    #cache_ttl
+	    #response_format
+	    #payload_encoding
+	    .query_rows(#payload, #query_args, |row| rows.push(row)).await?;
+	rows.finish()?;
+    )
   } else {
     quote!(
         let result:#result_type =
-	    crud_api::http::HTTPApi::new(format!(#urif,base_url #ids),
+	    crud_api::http::HTTPApi::new(&http_client,
+				     format!(#urif,base_url #ids),
 				      hyper::Method::#method,
 				      hyper::StatusCode::#status,
 				      #ko_status_map,
 				      #auth,
 				      &extra_headers)
+	    #pagination
+	    #retry
+	    #cache_ttl
+	    #response_format
+	    #payload_encoding
 	    .query(#payload, #query_args, #transform_type).await?;
 	#extra_action
         #result_output
@@ -161,6 +261,164 @@ fn match_endpoint(
     }
 }
 
+/// Generate the `response_format` of the request.
+fn response_format(format: &str) -> TokenStream {
+  let variant = match format {
+    "json" => quote!(Json),
+    "xml" => return quote!(.response_format(crud_api::__xml_response_format!())),
+    "text" => quote!(Text),
+    "form" => quote!(Form),
+    "ndjson" => quote!(Ndjson),
+    "json-seq" => quote!(JsonSeq),
+    _ => abort_call_site!(
+      "Invalid response_format '{}'", format;
+      help = "response_format is one of \"json\", \"xml\", \"text\", \"form\", \"ndjson\" or \"json-seq\""
+    ),
+  };
+  quote!(.response_format(crud_api::decode::ResponseFormat::#variant))
+}
+
+/// The `UploadFile` fields of a payload are only sent in the multipart forms. The endpoint
+/// is refused if its payload is known, else the command checks the payload when it runs.
+fn upload_files(ep: &Endpoint, payload_struct: &str) -> TokenStream {
+  if ep.payload_encoding.as_deref() == Some("multipart") {
+    return quote!();
+  }
+  let error =
+    format!("The UploadFile fields of {payload_struct} require the multipart payload_encoding");
+  match input_map().get(payload_struct) {
+    Some(input) => {
+      let input: ApiInput = input.clone().into();
+      if let Data::Struct(fields) = &input.data {
+        if fields.iter().any(|field| is_upload_file(&field.ty)) {
+          abort_call_site!(
+            error;
+            help = "Add payload_encoding = \"multipart\" to the endpoint"
+          );
+        }
+      }
+      quote!()
+    }
+    None => {
+      let payload_type = Ident::new(payload_struct, Span::call_site());
+      quote!(
+      if !<#payload_type as crud_api::ApiInput>::file_fields().is_empty() {
+          miette::bail!(#error);
+      }
+        )
+    }
+  }
+}
+
+/// Generate the `payload_encoding` of the request.
+fn payload_encoding(ep: &Endpoint, arg_ident: &Ident) -> Option<TokenStream> {
+  let encoding = ep.payload_encoding.as_deref();
+  if ep.file_fields.is_some() && encoding != Some("multipart") {
+    abort_call_site!(
+      "file_fields requires the multipart payload_encoding";
+      help = "Add payload_encoding = \"multipart\" to the endpoint"
+    );
+  }
+  if ep.content_type.is_some() && encoding != Some("raw") {
+    abort_call_site!(
+      "content_type requires the raw payload_encoding";
+      help = "Add payload_encoding = \"raw\" to the endpoint"
+    );
+  }
+  match encoding? {
+    "json" => None,
+    "form" => Some(quote!(.payload_encoding(crud_api::body::PayloadEncoding::Form))),
+    "multipart" => {
+      let files: Vec<&str> = ep
+        .file_fields
+        .as_deref()
+        .map(|files| files.split(',').map(str::trim).collect())
+        .unwrap_or_default();
+      // The `UploadFile` fields of the payload are files too.
+      let payload_files = ep.payload_struct.as_ref().map(|payload_struct| {
+        let payload_type = Ident::new(payload_struct, Span::call_site());
+        quote!(<#payload_type as crud_api::ApiInput>::file_fields(),)
+      });
+      Some(
+        quote!(.payload_encoding(crud_api::body::PayloadEncoding::Multipart {
+          files: [#payload_files vec![#(#files),*]].concat(),
+        })),
+      )
+    }
+    "raw" => {
+      if ep.payload_struct.is_some() {
+        abort_call_site!(
+          "A raw payload_encoding doesn't use the payload_struct";
+          help = "The body is read from the file of the --body argument"
+        );
+      }
+      let content_type = ep
+        .content_type
+        .as_deref()
+        .unwrap_or("application/octet-stream");
+      Some(
+        quote!(.payload_encoding(crud_api::body::PayloadEncoding::Raw {
+          file: #arg_ident
+            .get_one::<crud_api::UploadFile>("body_file")
+            .map(|file| file.path().to_string())
+            .unwrap_or_default(),
+          content_type: #content_type,
+        })),
+      )
+    }
+    encoding => abort_call_site!(
+      "Invalid payload_encoding '{}'", encoding;
+      help = "payload_encoding is one of \"json\", \"form\", \"multipart\" or \"raw\""
+    ),
+  }
+}
+
+/// Generate a `crud_api::retry::RetryPolicy` if one of its values is set.
+pub(crate) fn retry_policy(
+  timeout: Option<u64>,
+  retries: Option<u32>,
+  retry_on: Option<&str>,
+  retry_non_idempotent: Option<bool>,
+) -> Option<TokenStream> {
+  if timeout.is_none() && retries.is_none() && retry_on.is_none() && retry_non_idempotent.is_none() {
+    return None;
+  }
+  if timeout == Some(0) {
+    abort_call_site!(
+      "Invalid timeout 0";
+      help = "The timeout is a positive number of seconds"
+    );
+  }
+  let timeout = option_quote(timeout);
+  let retries = option_quote(retries);
+  let retry_on = option_quote(retry_on.map(|retry_on| {
+    let statuses = retry_on
+      .split(',')
+      .map(|status| match status.trim().parse::<u16>() {
+        Ok(status) if (100..600).contains(&status) => status,
+        _ => abort_call_site!(
+          "Invalid HTTP status '{}' in retry_on", status;
+          help = "retry_on is a comma separated list of HTTP statuses: \"502,503,429\""
+        ),
+      });
+    quote!(vec![#(#statuses),*])
+  }));
+  let retry_non_idempotent = option_quote(retry_non_idempotent);
+  Some(quote!(crud_api::retry::RetryPolicy {
+    timeout: #timeout,
+    retries: #retries,
+    retry_on: #retry_on,
+    retry_non_idempotent: #retry_non_idempotent,
+  }))
+}
+
+fn option_quote<T: quote::ToTokens>(value: Option<T>) -> TokenStream {
+  match value {
+    Some(value) => quote!(Some(#value)),
+    None => quote!(None),
+  }
+}
+
 #[rustfmt::skip::macros(quote)]
 fn argmatches_rec(
   endpoints_map: &Emap,
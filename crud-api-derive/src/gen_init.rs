@@ -33,11 +33,15 @@ pub(crate) fn settings(api: &ApiRun) -> proc_macro2::TokenStream {
     quote! {"APP"}
   };
 
+  let (default_keys, default_values): (Vec<&str>, Vec<&str>) =
+    api.auth.settings().into_iter().unzip();
+
   quote! {
-  let settings = crud_api::settings::settings(#settings_qualifier,
+  let settings = crud_api::settings::settings_with_defaults(#settings_qualifier,
       #settings_organisation,
       #settings_name,
-      #settings_env_prefix)?;
+      #settings_env_prefix,
+      &[#((#default_keys, #default_values)),*])?;
 
   }
 }
@@ -72,8 +76,53 @@ pub(crate) fn init_clap(api: &ApiRun) -> proc_macro2::TokenStream {
     commands = commands.author(clap::crate_description!());}
   };
 
-  let profile = setting_clap_decl("profile", "profile", "Profile to use. default: no profile");
+  let profile = setting_clap_decl(
+    "profile",
+    "profile",
+    "Profile to use. default: the `default_profile` setting",
+  );
+  let arg_config = setting_clap_decl(
+    "config_file",
+    "config",
+    "Settings file, read over the other settings files",
+  );
   let arg_base = setting_clap_decl("base_url", "base-url", "Override the base url");
+  let arg_proxy = setting_clap_decl(
+    "proxy",
+    "proxy",
+    "HTTP proxy url. default: HTTP_PROXY, HTTPS_PROXY environment variables",
+  );
+  let arg_ca_cert = setting_clap_decl(
+    "ca_cert",
+    "ca-cert",
+    "PEM file of additional certificate authorities",
+  );
+  let arg_system_certs = setting_clap_flag(
+    "system_certs",
+    "system-certs",
+    "Trust the certificates of the system store",
+  );
+  let arg_client_cert = setting_clap_decl(
+    "client_cert",
+    "client-cert",
+    "PEM file of the client certificate",
+  );
+  let arg_client_key = setting_clap_decl(
+    "client_key",
+    "client-key",
+    "PEM file of the client private key",
+  );
+  let arg_insecure = setting_clap_flag(
+    "insecure",
+    "insecure",
+    "Don't verify the server certificate. Use with caution",
+  );
+  let arg_timeout = setting_clap_decl("timeout", "timeout", "Timeout of the requests in seconds");
+  let arg_retries = setting_clap_decl(
+    "retries",
+    "retries",
+    "Number of retries of the failed requests",
+  );
   quote! {
       let mut commands = crud_api::cli::init_clap();
       #app_name
@@ -81,15 +130,35 @@ pub(crate) fn init_clap(api: &ApiRun) -> proc_macro2::TokenStream {
       #app_version
       #app_about
       #profile
+      #arg_config
       #arg_base
+      #arg_proxy
+      #arg_ca_cert
+      #arg_system_certs
+      #arg_client_cert
+      #arg_client_key
+      #arg_insecure
+      #arg_timeout
+      #arg_retries
+      commands = crud_api::debug::debug_args(commands);
+      commands = crud_api::cache::cache_args(commands);
   }
 }
 
 fn setting_clap_decl(ident: &str, long: &str, help: &str) -> TokenStream {
+  setting_clap_arg(ident, "String", long, help)
+}
+
+/// Boolean setting: the argument is a flag.
+fn setting_clap_flag(ident: &str, long: &str, help: &str) -> TokenStream {
+  setting_clap_arg(ident, "Option<bool>", long, help)
+}
+
+fn setting_clap_arg(ident: &str, ty: &str, long: &str, help: &str) -> TokenStream {
   let base = field_quote(
     &ApiInputField {
       ident: Some(Ident::new(ident, Span::call_site())),
-      ty: parse_str("String").unwrap(),
+      ty: parse_str(ty).unwrap(),
       long: Some(long.to_string()),
       short: None,
       no_short: Some(true),
@@ -2,7 +2,7 @@ mod enums;
 mod serde;
 mod structs;
 
-pub(crate) use self::structs::{field_quote, ApiInputField};
+pub(crate) use self::structs::{field_quote, is_upload_file, ApiInputField};
 use self::{
   enums::{derive_enum_command_match, derive_enum_decl_command, ApiInputVariant},
   structs::{derive_struct_decl, derive_struct_match},
@@ -79,6 +79,27 @@ pub(crate) fn api_input_derive(ast: &DeriveInput) -> TokenStream {
     }
   };
 
+  let file_fields = match &input.data {
+    Data::Struct(fields) => {
+      let files: Vec<String> = fields
+        .fields
+        .iter()
+        .filter(|f| is_upload_file(&f.ty))
+        .filter_map(|f| f.ident.as_ref().map(Ident::to_string))
+        .collect();
+      if files.is_empty() {
+        quote!{}
+      } else {
+        quote!{
+	    fn file_fields() -> Vec<&'static str> {
+		vec![#(#files),*]
+	    }
+	}
+      }
+    }
+    Data::Enum(_) => quote!{},
+  };
+
   let out = quote! {
   impl ::crud_api::ApiInput for #ident {
       fn clap(app: clap::Command,
@@ -96,6 +117,7 @@ pub(crate) fn api_input_derive(ast: &DeriveInput) -> TokenStream {
       fn from_clap_matches(matches: &clap::ArgMatches) -> miette::Result<Self>{
 	  Ok(#get_input_from_file_or_clap)
       }
+      #file_fields
   }
 };
 
@@ -219,6 +219,12 @@ pub(crate) fn field_quote(
     quote!(.num_args(clap::builder::ValueRange::SINGLE))
   };
 
+  let file = if is_upload_file(&field.ty) {
+    quote!(.value_hint(clap::ValueHint::FilePath).value_name("FILE"))
+  } else {
+    quote!()
+  };
+
   let name = if let Some(prefix) = prefix {
     format!("{prefix}-{raw_name}").to_lowercase()
   } else {
@@ -230,7 +236,7 @@ pub(crate) fn field_quote(
 	  .value_parser(#value_parser)
           .action(#arg_action)
 	  .required(#required)
-	  #long #short #help #long_help #heading #num_args
+	  #long #short #help #long_help #heading #num_args #file
   }
 }
 #[rustfmt::skip::macros(quote)]
@@ -268,6 +274,16 @@ fn field_matched_value(field: &ApiInputField, prefix: Option<String>) -> TokenSt
   }
 }
 
+/// The field is a `crud_api::UploadFile`.
+pub(crate) fn is_upload_file(ty: &Type) -> bool {
+  if let Type::Path(s) = strip_type(ty) {
+    if let Some(x) = s.path.segments.last() {
+      return x.ident.eq("UploadFile");
+    }
+  }
+  false
+}
+
 fn is_vec(ty: &Type) -> bool {
   if let Type::Path(s) = ty {
     if let Some(x) = s.path.segments.first() {
@@ -331,7 +347,7 @@ fn strip_type(ty: &Type) -> &Type {
 
 #[cfg(test)]
 mod tests {
-  use crate::input::structs::{is_option_vec, is_vec, strip_type};
+  use crate::input::structs::{is_option_vec, is_upload_file, is_vec, strip_type};
 
   use super::is_option;
   use syn::{parse_str, Type};
@@ -368,6 +384,16 @@ mod tests {
     assert!(is_option_vec(&ty));
   }
 
+  #[test]
+  fn is_upload_file_test() {
+    let ty: Type = parse_str("String").unwrap();
+    assert!(!is_upload_file(&ty));
+    let ty: Type = parse_str("UploadFile").unwrap();
+    assert!(is_upload_file(&ty));
+    let ty: Type = parse_str("Option<crud_api::UploadFile>").unwrap();
+    assert!(is_upload_file(&ty));
+  }
+
   #[test]
   fn type_strip_test() {
     let ty_string: Type = parse_str("String").unwrap();
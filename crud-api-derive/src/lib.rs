@@ -12,13 +12,14 @@ mod gen_init;
 mod input;
 
 use api::api;
-use crud_api_endpoint::ApiRun;
+use crud_api_endpoint::{ApiLogin, ApiRun};
 use darling::FromDeriveInput;
 use gen_clap_declarations::subcommands;
-use gen_clap_matches::argmatches;
+use gen_clap_matches::{argmatches, retry_policy};
 use gen_init::{init_clap, settings};
 use input::api_input_derive;
 use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span};
 use proc_macro_error::proc_macro_error;
 use quote::quote;
 use syn::{parse, DeriveInput};
@@ -49,6 +50,54 @@ pub fn api_run_macro_derive(input: TokenStream) -> TokenStream {
     })
     .collect();
 
+  let retry = retry_policy(
+    api.timeout,
+    api.retries,
+    api.retry_on.as_deref(),
+    api.retry_non_idempotent,
+  )
+  .map(|policy| quote!(.with_retry_policy(#policy)));
+
+  let (login_decl, login_commands, login_matches) = match &api.login {
+    Some(login) => {
+      let ApiLogin {
+        route,
+        method,
+        user_field,
+        password_field,
+        token_field,
+        result_ok_status,
+        ..
+      } = login;
+      let status = Ident::new(result_ok_status, Span::call_site());
+      let identity_route = match &login.identity_route {
+        Some(route) => quote!(Some(#route)),
+        None => quote!(None),
+      };
+      (
+        quote! {
+	    let login = crud_api::login::Login {
+		route: #route,
+		method: #method,
+		user_field: #user_field,
+		password_field: #password_field,
+		token_field: #token_field,
+		status: crud_api::http::StatusCode::#status,
+		identity_route: #identity_route,
+	    };
+	},
+        quote!(commands = crud_api::login::clap_login(commands, &login);),
+        quote! {
+	    Some(("login", login_matches)) =>
+		crud_api::login::login(&http_client, &base_url, &login, login_matches, &auth, &extra_headers).await?,
+	    Some(("whoami", _)) =>
+		crud_api::login::whoami(&http_client, &base_url, &login, &auth, &extra_headers).await?,
+	},
+      )
+    }
+    None => (quote!(), quote!(), quote!()),
+  };
+
   let out = quote! {
       impl #name {
 	 async fn run() -> miette::Result<()> {
@@ -59,9 +108,24 @@ pub fn api_run_macro_derive(input: TokenStream) -> TokenStream {
 	     #settings
 	     #init_clap
 	     commands = auth.clap_auth(commands);
+	     #login_decl
+	     #login_commands
 	     #subcommands
+	     commands = crud_api::config_command::default_profile(commands, &settings);
 
 	     let matches = crud_api::cli::get_matches(&commands)?;
+	     // These commands don't send requests: they work even when the settings of the
+	     // HTTP client are wrong, so the settings can be fixed.
+	     match matches.subcommand() {
+		 Some(("completion", completions)) => {
+		     crud_api::completions::generate_completions(completions, &mut commands);
+		     return Ok(());
+		 }
+		 Some(("config", config)) =>
+		     return crud_api::config_command::run_config(config, &matches, &settings),
+		 Some(("cache", cache)) => return crud_api::cache::run_cache(cache, &settings),
+		 _ => {}
+	     }
 	     let base_url = if let Ok(url) =
 		 crud_api::settings::get_settings(&settings, &matches, "base_url") {
 		     url
@@ -69,14 +133,27 @@ pub fn api_run_macro_derive(input: TokenStream) -> TokenStream {
 		 #base_url.to_string()
 	     };
 	     auth.clap_matches(&matches,&mut commands,&settings);
-	     match matches.subcommand() {
-		  #matches
-		  Some(("completion", completions)) =>
-		      crud_api::completions::generate_completions(completions, &mut commands),
-		  Some((_,_))=> commands.print_help().into_diagnostic()?,
-		  None => commands.print_help().into_diagnostic()?,
-	      }
-	      Ok(())
+	     let http_client = crud_api::http::HttpClient::new(&settings, &matches)?#retry
+		 .with_dry_run(crud_api::debug::clap_match_dry_run(&matches))
+		 .with_verbosity(crud_api::debug::clap_match_verbosity(&matches))
+		 .with_cache(crud_api::cache::clap_match_cache(&settings, &matches)?);
+	     auth.set_client(std::sync::Arc::new(http_client.clone()));
+	     let result = async {
+		 match matches.subcommand() {
+		     #matches
+		     #login_matches
+		     Some(("login", _)) => auth.login(crud_api::cli::read_secret()?).await?,
+		     Some(("logout", _)) => auth.logout().await?,
+		     Some((_,_))=> commands.print_help().into_diagnostic()?,
+		     None => commands.print_help().into_diagnostic()?,
+		 }
+		 Ok::<(), miette::Report>(())
+	     }.await;
+	     match result {
+		 // The dry run stops at the first request.
+		 Err(e) if crud_api::debug::is_dry_run(&e) => Ok(()),
+		 result => result,
+	     }
 	  }
       }
   }
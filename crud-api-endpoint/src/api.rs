@@ -115,10 +115,7 @@ pub fn table_impl<T: Into<ApiField> + Clone>(
           };
 
           let formated_value = if let Some(FieldFormat::Date { format }) = field.table_format {
-            quote!(#unformated_value.parse::<chrono::DateTime<chrono::Utc>>()
-		    .into_diagnostic().wrap_err("Can't parse Date")?
-		    .format(#format).to_string()
-	    )
+            quote!(crud_api::format_date(&#unformated_value, #format)?)
           } else {
             unformated_value
           };
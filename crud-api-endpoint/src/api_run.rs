@@ -53,6 +53,109 @@ pub struct ApiInformation {
   pub env_prefix: Option<String>,
 }
 
+/// Authentification block for `#[derive(ApiRun)]`.
+///
+/// Its values are the default settings read by the `CrudAuth`
+/// implementation. They can be overridden by the settings file.
+///
+/// # Example
+/// ```rust
+/// # use crud_api::ApiRun;
+/// # use crud_auth::CrudAuth;
+/// # use crud_auth_no_auth::Auth;
+/// # use miette::IntoDiagnostic;
+/// # #[derive(ApiRun)]
+/// #[api(
+///   infos(base_url = "https://api.example.com"),
+///   auth(api_key_header = "X-Api-Token")
+/// )]
+/// # struct ExampleApi;
+/// ```
+#[derive(Debug, Default, FromMeta)]
+pub struct ApiAuthentification {
+  /// Header of the API key. Read by `crud-auth-api-key`. Default: `X-API-Key`.
+  pub api_key_header: Option<String>,
+  /// Query parameter of the API key, instead of a header. Read by `crud-auth-api-key`.
+  pub api_key_query: Option<String>,
+}
+
+impl ApiAuthentification {
+  /// The settings and their values.
+  pub fn settings(&self) -> Vec<(&str, &str)> {
+    [
+      ("api_key_header", &self.api_key_header),
+      ("api_key_query", &self.api_key_query),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| value.as_deref().map(|value| (key, value)))
+    .collect()
+  }
+}
+
+/// Login block for `#[derive(ApiRun)]`.
+///
+/// It generates the `login`, `logout` and `whoami` commands. `login` prompts the user
+/// name and the password, sends them to the login endpoint and gives the token of the
+/// response to the `CrudAuth` implementation, which saves it for the profile. Only the token
+/// authentifications (`crud-auth-bearer`, `crud-auth-api-key`) can save it.
+///
+/// # Example
+/// ```rust
+/// # use crud_api::ApiRun;
+/// # use crud_auth::CrudAuth;
+/// # use crud_auth_no_auth::Auth;
+/// # use miette::IntoDiagnostic;
+/// # #[derive(ApiRun)]
+/// #[api(
+///   infos(base_url = "https://api.example.com"),
+///   login(route = "/auth/login", token_field = "data.token", identity_route = "/me")
+/// )]
+/// # struct ExampleApi;
+/// ```
+#[derive(Debug, FromMeta)]
+pub struct ApiLogin {
+  /// Route of the login endpoint.
+  pub route: String,
+  /// Method of the login endpoint. Default: `POST`.
+  #[darling(default = "default_login_method")]
+  pub method: String,
+  /// Field of the user name in the JSON payload. Default: `username`.
+  #[darling(default = "default_user_field")]
+  pub user_field: String,
+  /// Field of the password in the JSON payload. Default: `password`.
+  #[darling(default = "default_password_field")]
+  pub password_field: String,
+  /// Dotted path of the token in the response (ie. `data.access_token`). Default: `token`.
+  #[darling(default = "default_token_field")]
+  pub token_field: String,
+  /// Expected status of the login response. Default: `OK`.
+  #[darling(default = "default_login_status")]
+  pub result_ok_status: String,
+  /// Route of the endpoint describing the logged user, called by `whoami`.
+  /// `whoami` isn't generated if it's omitted.
+  pub identity_route: Option<String>,
+}
+
+fn default_login_method() -> String {
+  "POST".to_string()
+}
+
+fn default_login_status() -> String {
+  "OK".to_string()
+}
+
+fn default_user_field() -> String {
+  "username".to_string()
+}
+
+fn default_password_field() -> String {
+  "password".to_string()
+}
+
+fn default_token_field() -> String {
+  "token".to_string()
+}
+
 /// Attribute used by `#[derive(ApiRun)]`.
 ///
 /// It declare a new cli application:
@@ -85,6 +188,12 @@ pub struct ApiRun {
   /// ```
   #[darling(default)]
   pub infos: ApiInformation,
+  /// Authentification block of the application. See [ApiAuthentification].
+  #[darling(default)]
+  pub auth: ApiAuthentification,
+  /// Login block of the application. See [ApiLogin].
+  #[darling(default)]
+  pub login: Option<ApiLogin>,
   /// Name of the struct derived by `ApiRun`.
   /// Used to implentent the `run` function.
 
@@ -92,6 +201,22 @@ pub struct ApiRun {
   #[darling(default)]
   #[darling(multiple)]
   pub extra_header: Vec<Header>,
+  /// Timeout of the requests in seconds. Default: 30 seconds.
+  /// See [Endpoint::timeout](crate::Endpoint::timeout).
+  #[darling(default)]
+  pub timeout: Option<u64>,
+  /// Number of retries of the failed requests. Default: no retry.
+  /// See [Endpoint::retries](crate::Endpoint::retries).
+  #[darling(default)]
+  pub retries: Option<u32>,
+  /// Comma separated list of the HTTP statuses to retry.
+  /// See [Endpoint::retry_on](crate::Endpoint::retry_on).
+  #[darling(default)]
+  pub retry_on: Option<String>,
+  /// Retry the non idempotent methods (`POST`, `PATCH`).
+  /// See [Endpoint::retry_non_idempotent](crate::Endpoint::retry_non_idempotent).
+  #[darling(default)]
+  pub retry_non_idempotent: Option<bool>,
   #[doc(hidden)]
   pub ident: Ident,
   //  attrs: Vec<syn::Attribute>,
@@ -41,6 +41,45 @@ lazy_static! {
         ..Default::default()
       },
     );
+    m.insert(
+      "continue_download".into(),
+      ApiInputConfig {
+        arg_name: Some("continue_download".into()),
+        ty: Some("Option<bool>".into()),
+        long: Some("continue".into()),
+        no_short: Some(true),
+        help: Some("Resume the download of the output file (not of an output directory)".into()),
+        heading: Some("Options".into()),
+        required: Some(false),
+        ..Default::default()
+      },
+    );
+    m.insert(
+      "sha256".into(),
+      ApiInputConfig {
+        arg_name: Some("sha256".into()),
+        ty: Some("Option<String>".into()),
+        long: Some("sha256".into()),
+        no_short: Some(true),
+        help: Some("Verify the SHA-256 checksum of the download".into()),
+        heading: Some("Options".into()),
+        required: Some(false),
+        ..Default::default()
+      },
+    );
+    m.insert(
+      "checksum_header".into(),
+      ApiInputConfig {
+        arg_name: Some("checksum_header".into()),
+        ty: Some("Option<String>".into()),
+        long: Some("checksum-header".into()),
+        no_short: Some(true),
+        help: Some("Verify the download with the SHA-256 checksum of this response header".into()),
+        heading: Some("Options".into()),
+        required: Some(false),
+        ..Default::default()
+      },
+    );
     m.insert(
       "input_file".into(),
       ApiInputConfig {
@@ -56,6 +95,19 @@ lazy_static! {
         ..Default::default()
       },
     );
+    m.insert(
+      "body_file".into(),
+      ApiInputConfig {
+        arg_name: Some("body_file".into()),
+        ty: Some("crud_api::UploadFile".into()),
+        long: Some("body".into()),
+        no_short: Some(true),
+        help: Some("File sent as the request body ('-' for stdin)".into()),
+        heading: Some("Payload".into()),
+        required: Some(true),
+        ..Default::default()
+      },
+    );
     m.insert(
       "input_template".into(),
       ApiInputConfig {
@@ -83,6 +135,142 @@ lazy_static! {
         // possible_values: vec![],
         ..Default::default()
       },
+    );
+    m.insert(
+      "pagination_all".into(),
+      ApiInputConfig {
+        arg_name: Some("pagination_all".into()),
+        ty: Some("Option<bool>".into()),
+        long: Some("all".into()),
+        no_short: Some(true),
+        help: Some("Fetch all the pages".into()),
+        heading: Some("Pagination".into()),
+        required: Some(false),
+        ..Default::default()
+      },
+    );
+    m.insert(
+      "pagination_page".into(),
+      ApiInputConfig {
+        arg_name: Some("pagination_page".into()),
+        ty: Some("Option<u64>".into()),
+        long: Some("page".into()),
+        no_short: Some(true),
+        help: Some("Page to fetch".into()),
+        heading: Some("Pagination".into()),
+        required: Some(false),
+        ..Default::default()
+      },
+    );
+    m.insert(
+      "pagination_per_page".into(),
+      ApiInputConfig {
+        arg_name: Some("pagination_per_page".into()),
+        ty: Some("Option<u64>".into()),
+        long: Some("per-page".into()),
+        no_short: Some(true),
+        help: Some("Number of results per page".into()),
+        heading: Some("Pagination".into()),
+        required: Some(false),
+        ..Default::default()
+      },
+    );
+    m.insert(
+      "pagination_max_items".into(),
+      ApiInputConfig {
+        arg_name: Some("pagination_max_items".into()),
+        ty: Some("Option<usize>".into()),
+        long: Some("max-items".into()),
+        no_short: Some(true),
+        help: Some("Fetch the pages until this number of results".into()),
+        heading: Some("Pagination".into()),
+        required: Some(false),
+        ..Default::default()
+      },
+    );
+    m.insert(
+      "select_fields".into(),
+      ApiInputConfig {
+        arg_name: Some("select_fields".into()),
+        ty: Some("Option<String>".into()),
+        long: Some("fields".into()),
+        no_short: Some(true),
+        help: Some("Comma separated list of the fields to output".into()),
+        heading: Some("Selection".into()),
+        required: Some(false),
+        ..Default::default()
+      },
+    );
+    m.insert(
+      "select_filter".into(),
+      ApiInputConfig {
+        arg_name: Some("select_filter".into()),
+        ty: Some("Option<Vec<String>>".into()),
+        long: Some("filter".into()),
+        no_short: Some(true),
+        help: Some("Keep the results matching this expression (ex: 'state==opened')".into()),
+        long_help: Some(
+          "Keep the results matching this expression: <field><operator><value>. \
+           The operators are ==, !=, <, <=, >, >= and ~= (contains). \
+           The nested fields are separated by dots. The filters can be repeated."
+            .into(),
+        ),
+        heading: Some("Selection".into()),
+        required: Some(false),
+        ..Default::default()
+      },
+    );
+    m.insert(
+      "select_sort".into(),
+      ApiInputConfig {
+        arg_name: Some("select_sort".into()),
+        ty: Some("Option<String>".into()),
+        long: Some("sort".into()),
+        no_short: Some(true),
+        help: Some("Comma separated list of the fields to sort by (descending with '-')".into()),
+        heading: Some("Selection".into()),
+        required: Some(false),
+        ..Default::default()
+      },
+    );
+    m.insert(
+      "output_template".into(),
+      ApiInputConfig {
+        arg_name: Some("output_template".into()),
+        ty: Some("Option<String>".into()),
+        long: Some("output-template".into()),
+        no_short: Some(true),
+        help: Some("Render each result with this Handlebars template (ex: '{{id}}\\t{{title}}')".into()),
+        heading: Some("Options".into()),
+        required: Some(false),
+        ..Default::default()
+      },
+    );
+    m.insert(
+      "output_template_file".into(),
+      ApiInputConfig {
+        arg_name: Some("output_template_file".into()),
+        ty: Some("Option<String>".into()),
+        long: Some("output-template-file".into()),
+        no_short: Some(true),
+        help: Some("Render each result with the Handlebars template of this file".into()),
+        heading: Some("Options".into()),
+        required: Some(false),
+        ..Default::default()
+      },
+    );
+    m.insert(
+      "output_query".into(),
+      ApiInputConfig {
+        arg_name: Some("output_query".into()),
+        ty: Some("Option<String>".into()),
+        long: Some("query".into()),
+        no_short: Some(true),
+        help: Some("JMESPath query applied to the output (ex: '[].{id: id, author: author.name}')".into()),
+        heading: Some("Selection".into()),
+        required: Some(false),
+        ..Default::default()
+      },
     );
       m
   };
@@ -9,7 +9,7 @@ mod input;
 mod types;
 
 pub use api::{table_impl, Api, ApiField, ApiVariant, FieldFormat};
-pub use api_run::{ApiInformation, ApiRun};
+pub use api_run::{ApiAuthentification, ApiInformation, ApiLogin, ApiRun};
 pub use config::{arg_config, ApiInputConfig};
 use darling::FromMeta;
 use derive_builder::Builder;
@@ -55,10 +55,118 @@ pub struct Endpoint {
   #[darling(rename = "multiple_results")]
   pub result_multiple: bool,
   /// returns a stream of bytes for this endpoint
-  /// This flag generates the `--output` arguments.
+  /// This flag generates the `--output`, `--continue`, `--sha256` and `--checksum-header` arguments.
+  /// When `--output` is a directory, the file is named by the `Content-Disposition` header.
   /// This flag disables the `--format` arguments.
   #[darling(rename = "stream")]
   pub result_is_stream: bool,
+  /// Pagination of a `multiple_results` endpoint.
+  /// This attribute generates the `--all`, `--page`, `--per-page` and `--max-items` arguments.
+  ///
+  /// Examples:
+  /// ```text
+  /// endpoint(
+  ///   route = "/issues",
+  ///   cli_route = "/issues",
+  ///   multiple_results,
+  ///   pagination(kind = "link"),
+  /// ),
+  /// ```
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub pagination: Option<Pagination>,
+  /// Timeout of the request in seconds. Default: 30 seconds.
+  /// It can be overridden by the `--timeout` argument or the `timeout` setting.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub timeout: Option<u64>,
+  /// Number of retries when the request fails. Default: no retry.
+  /// It can be overridden by the `--retries` argument or the `retries` setting.
+  ///
+  /// Only the idempotent methods (`GET`, `PUT`, `DELETE`...) are retried, unless
+  /// `retry_non_idempotent` is set.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub retries: Option<u32>,
+  /// Comma separated list of the HTTP statuses to retry. Default: `"429,502,503,504"`.
+  ///
+  /// Examples:
+  /// ```text
+  /// endpoint(
+  ///   route = "/jobs",
+  ///   cli_route = "/jobs",
+  ///   retries = 3,
+  ///   retry_on = "502,503,429",
+  /// ),
+  /// ```
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub retry_on: Option<String>,
+  /// Retry the non idempotent methods (`POST`, `PATCH`).
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub retry_non_idempotent: Option<bool>,
+  /// Cache the responses of this `GET` endpoint for `cache_ttl` seconds.
+  ///
+  /// A fresh response is read from the cache without sending the request. An expired one
+  /// is revalidated with its `ETag` or `Last-Modified` header. See the `cache` settings.
+  ///
+  /// Examples:
+  /// ```text
+  /// endpoint(
+  ///   route = "/projects",
+  ///   cli_route = "/projects",
+  ///   multiple_results,
+  ///   cache_ttl = 300,
+  /// ),
+  /// ```
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub cache_ttl: Option<u64>,
+  /// Format of the response body: `"json"`, `"xml"`, `"text"`, `"form"`, `"ndjson"` or
+  /// `"json-seq"`. Default: guessed from the `Content-Type` of the response, or JSON.
+  ///
+  /// The `"xml"` format requires the `xml` feature of crud-api, else the build fails. With `"text"`, the
+  /// `result_struct` is a `String`. The `"ndjson"` and `"json-seq"` results of a
+  /// `multiple_results` endpoint are decoded while they're received.
+  ///
+  /// Examples:
+  /// ```text
+  /// endpoint(
+  ///   route = "/health",
+  ///   cli_route = "/health",
+  ///   result_struct = "String",
+  ///   response_format = "text",
+  /// ),
+  /// ```
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub response_format: Option<String>,
+  /// Encoding of the payload: `"json"` (default), `"form"`, `"multipart"` or `"raw"`.
+  ///
+  /// With `"multipart"`, the `file_fields` of the payload are paths of files sent as file
+  /// parts. With `"raw"`, the endpoint has no `payload_struct`: the file of the `--body`
+  /// argument (`-` for stdin) is sent as-is with the `content_type` of the endpoint.
+  ///
+  /// Examples:
+  /// ```text
+  /// endpoint(
+  ///   route = "/avatars",
+  ///   cli_route = "/avatars/upload",
+  ///   method = "POST",
+  ///   payload_struct = "Avatar",
+  ///   payload_encoding = "multipart",
+  ///   file_fields = "image",
+  /// ),
+  /// endpoint(
+  ///   route = "/imports",
+  ///   cli_route = "/imports/create",
+  ///   method = "POST",
+  ///   payload_encoding = "raw",
+  ///   content_type = "text/csv",
+  /// ),
+  /// ```
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub payload_encoding: Option<String>,
+  /// Comma separated list of the payload fields sent as files by the `"multipart"` encoding.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub file_fields: Option<String>,
+  /// Content type of the `"raw"` payload. Default: `"application/octet-stream"`.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub content_type: Option<String>,
   /// Add extra header to this endpoint.
   #[darling(default)]
   #[darling(multiple)]
@@ -180,6 +288,41 @@ pub struct Header {
   pub value: String,
 }
 
+/// Pagination of an endpoint.
+#[derive(Debug, Clone, FromMeta, Serialize, Deserialize)]
+#[darling(default)]
+pub struct Pagination {
+  /// How the next page is found:
+  /// - `page`: increment the `page_param` query parameter until a page is empty or incomplete.
+  /// - `link`: follow the `Link: <...>; rel="next"` response header.
+  /// - `cursor`: read the next cursor in the body at `cursor_path` and send it in `cursor_param`.
+  pub kind: String,
+  /// Query parameter of the page number.
+  pub page_param: String,
+  /// Query parameter of the page size.
+  pub per_page_param: String,
+  /// Query parameter of the cursor.
+  pub cursor_param: String,
+  /// Path of the next cursor in the response body. The fields are separated by dots.
+  pub cursor_path: String,
+  /// Path of the results list in the response body. The fields are separated by dots.
+  /// If empty, the body is the list. The `transform_from` type is deserialized from this list.
+  pub items_path: String,
+}
+
+impl Default for Pagination {
+  fn default() -> Self {
+    Self {
+      kind: "page".into(),
+      page_param: "page".into(),
+      per_page_param: "per_page".into(),
+      cursor_param: "cursor".into(),
+      cursor_path: "next_cursor".into(),
+      items_path: Default::default(),
+    }
+  }
+}
+
 impl Default for Endpoint {
   fn default() -> Self {
     Self {
@@ -193,6 +336,16 @@ impl Default for Endpoint {
       result_struct: Default::default(),
       result_multiple: Default::default(),
       result_is_stream: false,
+      pagination: Default::default(),
+      timeout: Default::default(),
+      retries: Default::default(),
+      retry_on: Default::default(),
+      retry_non_idempotent: Default::default(),
+      cache_ttl: Default::default(),
+      response_format: Default::default(),
+      payload_encoding: Default::default(),
+      file_fields: Default::default(),
+      content_type: Default::default(),
       extra_header: Default::default(),
       extra_action: Default::default(),
       no_auth: false,
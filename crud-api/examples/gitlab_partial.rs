@@ -1,20 +1,30 @@
+#![allow(clippy::duplicated_attributes)]
+
 use crud_api::{Api, ApiInput, ApiRun, Query};
 use crud_auth::CrudAuth;
 use crud_auth_bearer::Auth;
-use miette::{Context, IntoDiagnostic, Result};
+use miette::Result;
 use serde::{Deserialize, Serialize};
 use serde_repr::Serialize_repr;
 use std::fmt::Debug;
 
 #[derive(Api, Debug, Default, Deserialize, Serialize)]
 #[api(
-  endpoint(route = "/issues", multiple_results, cli_route = "/issues",),
+  endpoint(
+    route = "/issues",
+    multiple_results,
+    pagination(kind = "link"),
+    retries = 2,
+    retry_on = "502,503,429",
+    cli_route = "/issues",
+  ),
   endpoint(route = "/issues/{id}", cli_route = "/issues/{id}")
 )]
 #[api(
   endpoint(
     route = "/projects/{id}/issues",
     multiple_results,
+    pagination(kind = "link"),
     cli_route = "/projects/{id}/issues",
   ),
   endpoint(
@@ -73,6 +83,7 @@ struct IssueCreatePayload {
   organisation = "djedi",
   env_prefix = "GITLAB"
 ))]
+#[api(timeout = 60)]
 struct R {}
 
 #[tokio::main]
@@ -1,3 +1,5 @@
+#![allow(clippy::duplicated_attributes)]
+
 use crud_api::{Api, ApiInput, ApiRun, EmptyResponse, Query};
 use crud_auth::CrudAuth;
 use crud_auth_no_auth::Auth;
@@ -0,0 +1,393 @@
+//! Bodies of the requests.
+//!
+//! The payload is encoded in JSON, as an URL-encoded form or as a multipart form. A raw body
+//! is sent as-is from a file or stdin. The files are streamed: they are not read in memory.
+
+use crate::progress::progress_bar;
+use futures_util::{stream, Stream, StreamExt, TryStreamExt};
+use http_body_util::{BodyExt, Full, StreamBody};
+use hyper::body::{Bytes, Frame};
+use indicatif::ProgressBar;
+use log::trace;
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+use serde::{
+  de::{MapAccess, Visitor},
+  Deserialize, Deserializer, Serialize,
+};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+  fmt::{self, Debug, Display},
+  fs, io,
+  path::{Path, PathBuf},
+  pin::Pin,
+  time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{fs::File, io::stdin};
+use tokio_util::io::ReaderStream;
+
+pub(crate) type RequestBody = http_body_util::combinators::BoxBody<Bytes, io::Error>;
+
+/// A body already in memory.
+pub(crate) fn full(bytes: impl Into<Bytes>) -> RequestBody {
+  Full::new(bytes.into())
+    .map_err(|never| match never {})
+    .boxed()
+}
+
+type ChunkStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send + Sync>>;
+
+/// Encoding of the payload of an endpoint.
+#[derive(Debug, Clone, Default)]
+pub enum PayloadEncoding<'a> {
+  #[default]
+  Json,
+  /// `application/x-www-form-urlencoded`
+  Form,
+  /// `multipart/form-data`. The `files` fields are paths of files sent as file parts, after
+  /// the other fields.
+  Multipart { files: Vec<&'a str> },
+  /// The content of `file` (`-` for stdin) is sent as-is.
+  Raw { file: String, content_type: &'a str },
+}
+
+/// The body of a request.
+#[derive(Debug, Clone)]
+pub(crate) struct Body {
+  content_type: String,
+  chunks: Vec<Chunk>,
+}
+
+#[derive(Debug, Clone)]
+enum Chunk {
+  Bytes(Bytes),
+  File(PathBuf),
+  Stdin,
+}
+
+impl Chunk {
+  fn file(path: &str) -> Chunk {
+    if path == "-" {
+      Chunk::Stdin
+    } else {
+      Chunk::File(PathBuf::from(path))
+    }
+  }
+}
+
+impl Body {
+  /// Encode the payload.
+  pub(crate) fn encode<P: Serialize + Debug>(
+    payload: &Option<P>,
+    encoding: &PayloadEncoding,
+  ) -> Result<Body> {
+    let body = match encoding {
+      PayloadEncoding::Json => Body {
+        content_type: "application/json; charset=UTF-8".to_string(),
+        chunks: match payload {
+          Some(payload) => vec![Chunk::Bytes(Bytes::from(
+            serde_json::to_vec(payload)
+              .into_diagnostic()
+              .context("Error during payload serialization")?,
+          ))],
+          None => vec![],
+        },
+      },
+      PayloadEncoding::Form => Body {
+        content_type: "application/x-www-form-urlencoded".to_string(),
+        chunks: match payload {
+          Some(payload) => vec![Chunk::Bytes(Bytes::from(
+            serde_qs::to_string(payload)
+              .into_diagnostic()
+              .context("Error during payload serialization")?,
+          ))],
+          None => vec![],
+        },
+      },
+      PayloadEncoding::Multipart { files } => multipart(payload, files)?,
+      PayloadEncoding::Raw { file, content_type } => Body {
+        content_type: content_type.to_string(),
+        chunks: vec![Chunk::file(file)],
+      },
+    };
+    trace!("Payload: {body}");
+    Ok(body)
+  }
+
+  pub(crate) fn content_type(&self) -> &str {
+    &self.content_type
+  }
+
+  /// The body can be sent again: it's not read from stdin.
+  pub(crate) fn is_replayable(&self) -> bool {
+    !self
+      .chunks
+      .iter()
+      .any(|chunk| matches!(chunk, Chunk::Stdin))
+  }
+
+  /// Length of a streamed body, if it's known.
+  pub(crate) fn stream_len(&self) -> Option<u64> {
+    if self
+      .chunks
+      .iter()
+      .all(|chunk| matches!(chunk, Chunk::Bytes(_)))
+    {
+      return None;
+    }
+    self
+      .chunks
+      .iter()
+      .map(|chunk| match chunk {
+        Chunk::Bytes(bytes) => Some(bytes.len() as u64),
+        Chunk::File(path) => fs::metadata(path).ok().map(|m| m.len()),
+        Chunk::Stdin => None,
+      })
+      .sum()
+  }
+
+  /// The body of a new request. The files are opened. A progress bar shows the upload of
+  /// the streamed bodies.
+  pub(crate) async fn request_body(&self) -> Result<RequestBody> {
+    if let [] | [Chunk::Bytes(_)] = self.chunks.as_slice() {
+      let bytes = match self.chunks.first() {
+        Some(Chunk::Bytes(bytes)) => bytes.clone(),
+        _ => Bytes::new(),
+      };
+      return Ok(full(bytes));
+    }
+    let mut streams: Vec<ChunkStream> = vec![];
+    for chunk in &self.chunks {
+      streams.push(match chunk {
+        Chunk::Bytes(bytes) => Box::pin(stream::once(std::future::ready(Ok(bytes.clone())))),
+        Chunk::File(path) => Box::pin(ReaderStream::new(
+          File::open(path)
+            .await
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Can't read {}", path.display()))?,
+        )),
+        Chunk::Stdin => Box::pin(ReaderStream::new(stdin())),
+      });
+    }
+    // The progress is shown when the body is sent.
+    let len = self.stream_len();
+    let mut bar = None;
+    let stream = stream::iter(streams).flatten().map_ok(move |bytes| {
+      bar
+        .get_or_insert_with(|| {
+          progress_bar(len, "Uploading...").unwrap_or_else(|_| ProgressBar::hidden())
+        })
+        .inc(bytes.len() as u64);
+      Frame::data(bytes)
+    });
+    Ok(BodyExt::boxed(StreamBody::new(stream)))
+  }
+}
+
+impl Display for Body {
+  /// The body as text. The files are not read.
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    for chunk in &self.chunks {
+      match chunk {
+        Chunk::Bytes(bytes) => write!(f, "{}", String::from_utf8_lossy(bytes))?,
+        Chunk::File(path) => write!(f, "<{}>", path.display())?,
+        Chunk::Stdin => write!(f, "<stdin>")?,
+      }
+    }
+    Ok(())
+  }
+}
+
+/// The fields of a JSON object, in their order.
+struct Fields(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for Fields {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Fields, D::Error> {
+    struct FieldsVisitor;
+
+    impl<'de> Visitor<'de> for FieldsVisitor {
+      type Value = Fields;
+
+      fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a struct")
+      }
+
+      fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Fields, A::Error> {
+        let mut fields = vec![];
+        while let Some(field) = map.next_entry()? {
+          fields.push(field);
+        }
+        Ok(Fields(fields))
+      }
+    }
+
+    deserializer.deserialize_map(FieldsVisitor)
+  }
+}
+
+/// Encode the fields of the payload as the parts of a multipart form, in the order of the
+/// struct.
+fn multipart<P: Serialize>(payload: &Option<P>, files: &[&str]) -> Result<Body> {
+  let fields = match payload {
+    // A `Value` would sort the fields.
+    Some(payload) => {
+      let json = serde_json::to_vec(payload)
+        .into_diagnostic()
+        .context("Error during payload serialization")?;
+      serde_json::from_slice::<Fields>(&json)
+        .map_err(|_| miette!("A multipart payload should be a struct"))?
+        .0
+    }
+    None => vec![],
+  };
+  let boundary = boundary();
+  let mut chunks = vec![];
+  // The files are sent after the other fields.
+  let (file_fields, fields): (Vec<_>, Vec<_>) = fields
+    .into_iter()
+    .partition(|(name, _)| files.contains(&name.as_str()));
+  for (name, value) in fields.into_iter().chain(file_fields) {
+    let values = match value {
+      Value::Array(values) => values,
+      value => vec![value],
+    };
+    for value in values {
+      let text = match value {
+        Value::Null => continue,
+        Value::String(text) => text,
+        value => value.to_string(),
+      };
+      if files.contains(&name.as_str()) {
+        let filename = match Path::new(&text).file_name() {
+          Some(filename) if text != "-" => filename.to_string_lossy().into_owned(),
+          _ => "stdin".to_string(),
+        };
+        chunks.push(Chunk::Bytes(Bytes::from(format!(
+          "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\
+           Content-Type: application/octet-stream\r\n\r\n",
+          quote(&name),
+          quote(&filename)
+        ))));
+        chunks.push(Chunk::file(&text));
+        chunks.push(Chunk::Bytes(Bytes::from_static(b"\r\n")));
+      } else {
+        chunks.push(Chunk::Bytes(Bytes::from(format!(
+          "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{text}\r\n",
+          quote(&name)
+        ))));
+      }
+    }
+  }
+  chunks.push(Chunk::Bytes(Bytes::from(format!("--{boundary}--\r\n"))));
+  Ok(Body {
+    content_type: format!("multipart/form-data; boundary={boundary}"),
+    chunks,
+  })
+}
+
+fn boundary() -> String {
+  let mut hasher = Sha256::new();
+  hasher.update(
+    SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_nanos()
+      .to_le_bytes(),
+  );
+  hasher.update(std::process::id().to_le_bytes());
+  let hash: String = hasher
+    .finalize()
+    .iter()
+    .take(12)
+    .map(|byte| format!("{byte:02x}"))
+    .collect();
+  format!("crud-api-{hash}")
+}
+
+/// Escape a name of the `Content-Disposition` header.
+fn quote(name: &str) -> String {
+  name.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Body, PayloadEncoding};
+  use http_body_util::BodyExt;
+  use serde::Serialize;
+
+  #[derive(Debug, Serialize)]
+  struct Post {
+    title: String,
+    tags: Vec<String>,
+    draft: Option<bool>,
+    attachment: String,
+  }
+
+  fn post(attachment: &str) -> Option<Post> {
+    Some(Post {
+      title: "Hello world".to_string(),
+      tags: vec!["a".to_string(), "b".to_string()],
+      draft: None,
+      attachment: attachment.to_string(),
+    })
+  }
+
+  #[test]
+  fn test_form() {
+    let body = Body::encode(&post("notes.txt"), &PayloadEncoding::Form).unwrap();
+    assert_eq!(body.content_type(), "application/x-www-form-urlencoded");
+    assert_eq!(
+      body.to_string(),
+      "title=Hello+world&tags[0]=a&tags[1]=b&attachment=notes.txt"
+    );
+    assert!(body.is_replayable());
+    assert_eq!(body.stream_len(), None);
+  }
+
+  #[tokio::test]
+  async fn test_multipart() {
+    let path = std::env::temp_dir().join(format!("crud-api-body-{}", std::process::id()));
+    std::fs::write(&path, "file content").unwrap();
+    let body = Body::encode(
+      &post(&path.to_string_lossy()),
+      &PayloadEncoding::Multipart {
+        files: vec!["attachment"],
+      },
+    )
+    .unwrap();
+    let boundary = body
+      .content_type()
+      .strip_prefix("multipart/form-data; boundary=")
+      .unwrap()
+      .to_string();
+    let filename = path.file_name().unwrap().to_string_lossy();
+    let expected = format!(
+      "--{boundary}\r\nContent-Disposition: form-data; name=\"title\"\r\n\r\nHello world\r\n\
+       --{boundary}\r\nContent-Disposition: form-data; name=\"tags\"\r\n\r\na\r\n\
+       --{boundary}\r\nContent-Disposition: form-data; name=\"tags\"\r\n\r\nb\r\n\
+       --{boundary}\r\nContent-Disposition: form-data; name=\"attachment\"; filename=\"{filename}\"\r\n\
+       Content-Type: application/octet-stream\r\n\r\nfile content\r\n\
+       --{boundary}--\r\n"
+    );
+    assert_eq!(body.stream_len(), Some(expected.len() as u64));
+    let sent = body.request_body().await.unwrap().collect().await.unwrap();
+    assert_eq!(String::from_utf8_lossy(&sent.to_bytes()), expected);
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn test_raw() {
+    let body = Body::encode(
+      &None::<()>,
+      &PayloadEncoding::Raw {
+        file: "-".to_string(),
+        content_type: "text/csv",
+      },
+    )
+    .unwrap();
+    assert_eq!(body.content_type(), "text/csv");
+    assert_eq!(body.to_string(), "<stdin>");
+    assert!(!body.is_replayable());
+    assert_eq!(body.stream_len(), None);
+  }
+}
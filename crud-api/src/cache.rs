@@ -0,0 +1,487 @@
+//! On-disk cache of the `GET` responses.
+//!
+//! The responses are stored by method, url and user in the cache directory of the
+//! application. A response is fresh during its TTL: it's read from the cache without sending
+//! the request. Then it's revalidated with its `ETag` or `Last-Modified` header: a
+//! `304 Not Modified` response renews it.
+//!
+//! The responses with `Cache-Control: no-store` or `private`, or `Vary: *`, are not stored.
+//! The responses with `Cache-Control: no-cache` are revalidated each time. A response is only
+//! reused for the requests with the same values of the headers listed in its `Vary` header.
+
+use crate::settings::{get_flag_settings, get_settings};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use config::Config;
+use crud_auth::credentials::write_private;
+use hyper::{
+  body::Bytes,
+  header::{
+    HeaderName, HeaderValue, CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED,
+    VARY,
+  },
+  HeaderMap,
+};
+use log::debug;
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Directory of the responses in the cache directory.
+const RESPONSES_DIR: &str = "responses";
+
+#[derive(Debug, Clone, PartialEq)]
+enum CacheMode {
+  /// Read and write the cache.
+  Use,
+  /// Don't read the cache, but store the new responses (`--refresh`).
+  Refresh,
+}
+
+/// The response cache of the application.
+#[derive(Debug, Clone)]
+pub struct Cache {
+  dir: PathBuf,
+  mode: CacheMode,
+  /// TTL of the endpoints without `cache_ttl`, when the `cache` setting is enabled.
+  default_ttl: Option<u64>,
+}
+
+/// A cached response.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct CacheEntry {
+  method: String,
+  uri: String,
+  etag: Option<String>,
+  last_modified: Option<String>,
+  /// Time of the last validation, in seconds since the epoch.
+  stored_at: u64,
+  ttl: u64,
+  /// Values of the request headers listed in the `Vary` header of the response.
+  #[serde(default)]
+  vary: Vec<(String, Option<String>)>,
+  headers: Vec<(String, String)>,
+  body: String,
+}
+
+pub fn cache_args(app: Command) -> Command {
+  app
+    .arg(
+      Arg::new("no_cache")
+        .long("no-cache")
+        .help("Don't read or store the responses in the cache")
+        .help_heading("Cache")
+        .global(true)
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("refresh")
+        .long("refresh")
+        .help("Send the request even if the cached response is fresh, and cache the new response")
+        .help_heading("Cache")
+        .global(true)
+        .conflicts_with("no_cache")
+        .action(ArgAction::SetTrue),
+    )
+}
+
+pub fn cache_subcommand(app: Command) -> Command {
+  app.subcommand(
+    Command::new("cache")
+      .about("Manage the response cache")
+      .subcommand_required(true)
+      .subcommand(Command::new("clear").about("Remove the cached responses"))
+      .subcommand(Command::new("stats").about("Show the size of the cache")),
+  )
+}
+
+/// The cache configured by the `cache` and `cache_ttl` settings and the `--no-cache` and
+/// `--refresh` arguments.
+pub fn clap_match_cache(settings: &Config, matches: &ArgMatches) -> Result<Option<Cache>> {
+  let flag = |id: &str| matches.try_get_one::<bool>(id).ok().flatten() == Some(&true);
+  if flag("no_cache") {
+    return Ok(None);
+  }
+  let Some(dir) = cache_dir(settings) else {
+    return Ok(None);
+  };
+  let default_ttl = if get_flag_settings(settings, matches, "cache").unwrap_or_default() {
+    Some(
+      get_settings(settings, matches, "cache_ttl")
+        .ok()
+        .map(|ttl| ttl.parse())
+        .transpose()
+        .into_diagnostic()
+        .wrap_err("The cache_ttl should be a number of seconds")?
+        .unwrap_or_default(),
+    )
+  } else {
+    None
+  };
+  Ok(Some(Cache {
+    dir,
+    mode: if flag("refresh") {
+      CacheMode::Refresh
+    } else {
+      CacheMode::Use
+    },
+    default_ttl,
+  }))
+}
+
+/// Run the `cache` subcommand.
+pub fn run_cache(matches: &ArgMatches, settings: &Config) -> Result<()> {
+  let dir = cache_dir(settings).ok_or_else(|| miette!("No cache directory"))?;
+  match matches.subcommand() {
+    Some(("clear", _)) => {
+      if dir.exists() {
+        fs::remove_dir_all(&dir)
+          .into_diagnostic()
+          .wrap_err_with(|| format!("Can't remove {}", dir.display()))?;
+      }
+    }
+    Some(("stats", _)) => {
+      let stats = stats(&dir)?;
+      println!("path: {}", dir.display());
+      println!("entries: {}", stats.entries);
+      println!("fresh: {}", stats.fresh);
+      println!("size: {} bytes", stats.size);
+    }
+    _ => unreachable!("subcommand_required"),
+  }
+  Ok(())
+}
+
+fn cache_dir(settings: &Config) -> Option<PathBuf> {
+  settings
+    .get_string("cache_path")
+    .ok()
+    .map(|path| Path::new(&path).join(RESPONSES_DIR))
+}
+
+impl Cache {
+  /// TTL of the responses of an endpoint, if they are cached.
+  pub(crate) fn ttl(&self, endpoint_ttl: Option<u64>) -> Option<u64> {
+    endpoint_ttl.or(self.default_ttl)
+  }
+
+  /// Key of a request. The identity of the user is hashed with the method and the url: the
+  /// other headers of the request don't change the key.
+  pub(crate) fn key(method: &str, uri: &str, identity: Option<&str>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_bytes());
+    hasher.update(b" ");
+    hasher.update(uri.as_bytes());
+    if let Some(identity) = identity {
+      hasher.update(b"\n");
+      hasher.update(Sha256::digest(identity.as_bytes()));
+    }
+    hasher
+      .finalize()
+      .iter()
+      .map(|byte| format!("{byte:02x}"))
+      .collect()
+  }
+
+  fn path(&self, key: &str) -> PathBuf {
+    self.dir.join(format!("{key}.json"))
+  }
+
+  pub(crate) fn get(&self, key: &str) -> Option<CacheEntry> {
+    if self.mode == CacheMode::Refresh {
+      return None;
+    }
+    let entry = fs::read(self.path(key)).ok()?;
+    serde_json::from_slice(&entry).ok()
+  }
+
+  pub(crate) fn store(&self, key: &str, entry: &CacheEntry) -> Result<()> {
+    let path = self.path(key);
+    debug!("Cache {} {} in {}", entry.method, entry.uri, path.display());
+    write_private(&path, &serde_json::to_vec(entry).into_diagnostic()?)
+  }
+
+  /// Remove the response that can't be reused anymore.
+  pub(crate) fn remove(&self, key: &str) {
+    fs::remove_file(self.path(key)).ok();
+  }
+}
+
+impl CacheEntry {
+  /// The entry of the response to a request with the `request` headers. The response isn't
+  /// cached if it can't be reused.
+  pub(crate) fn new(
+    method: &str,
+    uri: &str,
+    request: &HeaderMap,
+    headers: &HeaderMap,
+    body: &Bytes,
+    mut ttl: u64,
+  ) -> Option<CacheEntry> {
+    let header = |name| {
+      headers
+        .get(name)
+        .and_then(|value: &HeaderValue| value.to_str().ok())
+        .map(str::to_string)
+    };
+    let cache_control = list(headers, CACHE_CONTROL);
+    if cache_control
+      .iter()
+      .any(|directive| directive == "no-store" || directive == "private")
+    {
+      return None;
+    }
+    if cache_control
+      .iter()
+      .any(|directive| directive == "no-cache")
+    {
+      ttl = 0;
+    }
+    let vary = list(headers, VARY);
+    if vary.iter().any(|name| name == "*") {
+      return None;
+    }
+    let etag = header(ETAG);
+    let last_modified = header(LAST_MODIFIED);
+    if ttl == 0 && etag.is_none() && last_modified.is_none() {
+      return None;
+    }
+    Some(CacheEntry {
+      method: method.to_string(),
+      uri: uri.to_string(),
+      etag,
+      last_modified,
+      stored_at: now(),
+      ttl,
+      vary: vary
+        .into_iter()
+        .map(|name| {
+          let value = request
+            .get(&name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+          (name, value)
+        })
+        .collect(),
+      headers: headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect(),
+      body: String::from_utf8(body.to_vec()).ok()?,
+    })
+  }
+
+  /// The response can be reused for a request with the `request` headers.
+  pub(crate) fn matches(&self, request: &HeaderMap) -> bool {
+    self.vary.iter().all(|(name, value)| {
+      request.get(name).and_then(|value| value.to_str().ok()) == value.as_deref()
+    })
+  }
+
+  pub(crate) fn is_fresh(&self) -> bool {
+    now() < self.stored_at + self.ttl
+  }
+
+  /// The response has been revalidated.
+  pub(crate) fn renew(&mut self, ttl: u64) {
+    self.stored_at = now();
+    self.ttl = ttl;
+  }
+
+  /// The headers of a conditional request.
+  pub(crate) fn validators(&self) -> Vec<(HeaderName, &str)> {
+    let mut validators = vec![];
+    if let Some(etag) = &self.etag {
+      validators.push((IF_NONE_MATCH, etag.as_str()));
+    }
+    if let Some(last_modified) = &self.last_modified {
+      validators.push((IF_MODIFIED_SINCE, last_modified.as_str()));
+    }
+    validators
+  }
+
+  pub(crate) fn response(&self) -> (HeaderMap, Bytes) {
+    let headers = self
+      .headers
+      .iter()
+      .filter_map(|(name, value)| {
+        Some((
+          HeaderName::from_bytes(name.as_bytes()).ok()?,
+          HeaderValue::from_str(value).ok()?,
+        ))
+      })
+      .collect();
+    (headers, Bytes::from(self.body.clone()))
+  }
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Stats {
+  entries: usize,
+  fresh: usize,
+  size: u64,
+}
+
+fn stats(dir: &Path) -> Result<Stats> {
+  let mut stats = Stats::default();
+  let Ok(files) = fs::read_dir(dir) else {
+    return Ok(stats);
+  };
+  for file in files {
+    let path = file.into_diagnostic()?.path();
+    if path.extension().is_some_and(|ext| ext == "json") {
+      stats.entries += 1;
+      stats.size += fs::metadata(&path).map(|m| m.len()).unwrap_or_default();
+      let fresh = fs::read(&path)
+        .ok()
+        .and_then(|entry| serde_json::from_slice::<CacheEntry>(&entry).ok())
+        .is_some_and(|entry| entry.is_fresh());
+      if fresh {
+        stats.fresh += 1;
+      }
+    }
+  }
+  Ok(stats)
+}
+
+/// The lowercase items of a comma-separated header, without their value (`max-age=60`).
+fn list(headers: &HeaderMap, name: HeaderName) -> Vec<String> {
+  headers
+    .get_all(name)
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .flat_map(|value| value.split(','))
+    .map(|item| {
+      item
+        .split('=')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase()
+    })
+    .filter(|item| !item.is_empty())
+    .collect()
+}
+
+fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|now| now.as_secs())
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{stats, Cache, CacheEntry, CacheMode, Stats};
+  use hyper::{
+    body::Bytes,
+    header::{ACCEPT, CACHE_CONTROL, ETAG, IF_NONE_MATCH, VARY},
+    HeaderMap,
+  };
+
+  #[test]
+  fn test_key() {
+    let key = Cache::key("GET", "http://api/posts", Some("alice"));
+    assert_eq!(key.len(), 64);
+    assert_eq!(key, Cache::key("GET", "http://api/posts", Some("alice")));
+    assert_ne!(key, Cache::key("GET", "http://api/posts", Some("bob")));
+    assert_ne!(key, Cache::key("GET", "http://api/posts", None));
+    assert_ne!(
+      key,
+      Cache::key("GET", "http://api/posts?page=2", Some("alice"))
+    );
+    assert_ne!(key, Cache::key("HEAD", "http://api/posts", Some("alice")));
+  }
+
+  #[test]
+  fn test_entry() {
+    let body = Bytes::from("[]");
+    let none = HeaderMap::new();
+    assert!(CacheEntry::new("GET", "http://api", &none, &none, &body, 0).is_none());
+
+    let mut headers = HeaderMap::new();
+    headers.insert(ETAG, "\"v1\"".parse().unwrap());
+    let mut entry = CacheEntry::new("GET", "http://api", &none, &headers, &body, 0).unwrap();
+    assert!(!entry.is_fresh());
+    assert_eq!(entry.validators()[0].0, IF_NONE_MATCH);
+    assert_eq!(entry.validators()[0].1, "\"v1\"");
+    entry.renew(60);
+    assert!(entry.is_fresh());
+    assert_eq!(entry.response(), (headers, body));
+  }
+
+  #[test]
+  fn test_cache_control() {
+    let body = Bytes::from("[]");
+    let none = HeaderMap::new();
+    let entry = |name, value: &str| {
+      let mut headers = HeaderMap::new();
+      headers.insert(ETAG, "\"v1\"".parse().unwrap());
+      headers.insert(name, value.parse().unwrap());
+      CacheEntry::new("GET", "http://api", &none, &headers, &body, 60)
+    };
+    assert!(entry(CACHE_CONTROL, "no-store").is_none());
+    assert!(entry(CACHE_CONTROL, "max-age=60, Private").is_none());
+    assert!(entry(VARY, "*").is_none());
+    assert!(entry(CACHE_CONTROL, "max-age=60").unwrap().is_fresh());
+    // Revalidated each time.
+    let no_cache = entry(CACHE_CONTROL, "no-cache").unwrap();
+    assert!(!no_cache.is_fresh());
+    assert_eq!(no_cache.validators().len(), 1);
+  }
+
+  #[test]
+  fn test_vary() {
+    let mut json = HeaderMap::new();
+    json.insert(ACCEPT, "application/json".parse().unwrap());
+    let mut yaml = HeaderMap::new();
+    yaml.insert(ACCEPT, "application/yaml".parse().unwrap());
+    let mut headers = HeaderMap::new();
+    headers.insert(VARY, "Accept, Accept-Language".parse().unwrap());
+    let entry =
+      CacheEntry::new("GET", "http://api", &json, &headers, &Bytes::from("{}"), 60).unwrap();
+    assert!(entry.matches(&json));
+    assert!(!entry.matches(&yaml));
+    assert!(!entry.matches(&HeaderMap::new()));
+  }
+
+  #[test]
+  fn test_store() {
+    let dir = std::env::temp_dir().join(format!("crud-api-cache-{}", std::process::id()));
+    let cache = Cache {
+      dir: dir.clone(),
+      mode: CacheMode::Use,
+      default_ttl: None,
+    };
+    let entry = CacheEntry::new(
+      "GET",
+      "http://api",
+      &HeaderMap::new(),
+      &HeaderMap::new(),
+      &Bytes::from("{}"),
+      60,
+    )
+    .unwrap();
+    cache.store("key", &entry).unwrap();
+    assert!(cache.get("key").unwrap().is_fresh());
+    assert!(cache.get("other").is_none());
+    assert_eq!(
+      stats(&dir).unwrap(),
+      Stats {
+        entries: 1,
+        fresh: 1,
+        size: std::fs::metadata(dir.join("key.json")).unwrap().len(),
+      }
+    );
+    let refresh = Cache {
+      mode: CacheMode::Refresh,
+      ..cache
+    };
+    assert!(refresh.get("key").is_none());
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -1,6 +1,10 @@
-use crate::{completions::completions_subcommand, error::ClapError};
+use crate::{
+  cache::cache_subcommand, completions::completions_subcommand, config_command::config_subcommand,
+  error::ClapError,
+};
 use clap::{crate_name, ArgMatches, Command};
-use miette::Result;
+use miette::{IntoDiagnostic, Result};
+use std::io::{stdin, IsTerminal, Read};
 
 pub fn init_clap() -> Command {
   let mut command = Command::new(crate_name!())
@@ -16,6 +20,8 @@ pub fn init_clap() -> Command {
     )
     .subcommand_precedence_over_arg(true);
   command = completions_subcommand(command);
+  command = config_subcommand(command);
+  command = cache_subcommand(command);
   command
 }
 
@@ -25,3 +31,14 @@ pub fn get_matches(commands: &Command) -> Result<ArgMatches> {
     e.into()
   })
 }
+
+/// The secret given to `login` on the standard input, if it's not a terminal.
+pub fn read_secret() -> Result<Option<String>> {
+  if stdin().is_terminal() {
+    return Ok(None);
+  }
+  let mut secret = String::new();
+  stdin().read_to_string(&mut secret).into_diagnostic()?;
+  let secret = secret.trim();
+  Ok((!secret.is_empty()).then(|| secret.to_string()))
+}
@@ -0,0 +1,425 @@
+use crate::{
+  body::{full, RequestBody},
+  cache::Cache,
+  debug::{DryRun, Verbosity},
+  proxy::Proxies,
+  retry::RetryPolicy,
+  settings::get_settings,
+  tls,
+};
+use async_trait::async_trait;
+use clap::ArgMatches;
+use config::Config;
+use crud_auth::AuthClient;
+use http_body_util::BodyExt;
+use hyper::{
+  body::Incoming,
+  rt::{Read, ReadBufCursor, Write},
+  Request, Response, Uri,
+};
+use hyper_util::{
+  client::legacy::{
+    connect::{Connected, Connection},
+    Client,
+  },
+  rt::{TokioExecutor, TokioIo, TokioTimer},
+};
+use log::trace;
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+use std::{
+  future::Future,
+  io,
+  net::SocketAddr,
+  pin::Pin,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+  },
+  task::{Context, Poll},
+  time::{Duration, Instant},
+};
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt},
+  net::{lookup_host, TcpStream},
+};
+use tokio_rustls::{client::TlsStream, TlsConnector};
+use tower_service::Service;
+
+/// HTTP client shared by all the requests of the application.
+///
+/// The connections are pooled by authority and kept alive between the requests.
+/// When the `http2` feature is enabled, HTTP/2 is negotiated with ALPN.
+#[derive(Clone)]
+pub struct HttpClient {
+  client: Client<Connector, RequestBody>,
+  proxies: Arc<Proxies>,
+  /// Timeout and retries from the arguments and the settings.
+  retry: RetryPolicy,
+  /// Timeout and retries of the application.
+  default_retry: RetryPolicy,
+  dry_run: Option<DryRun>,
+  verbosity: Verbosity,
+  cache: Option<Cache>,
+}
+
+impl HttpClient {
+  /// Create the client from the settings and the command line arguments.
+  pub fn new(settings: &Config, matches: &ArgMatches) -> Result<HttpClient> {
+    let proxies = Arc::new(Proxies::new(get_settings(settings, matches, "proxy").ok())?);
+
+    let mut config = tls::client_config(settings, matches)?;
+    #[cfg(feature = "http2")]
+    {
+      config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    }
+    #[cfg(not(feature = "http2"))]
+    {
+      config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    }
+
+    let connector = Connector {
+      tls: TlsConnector::from(Arc::new(config)),
+      proxies: proxies.clone(),
+    };
+    let client = Client::builder(TokioExecutor::new())
+      .pool_timer(TokioTimer::new())
+      .pool_idle_timeout(Duration::from_secs(90))
+      .build(connector);
+    Ok(HttpClient {
+      client,
+      proxies,
+      retry: RetryPolicy::from_settings(settings, matches)?,
+      default_retry: RetryPolicy::default(),
+      dry_run: None,
+      verbosity: Verbosity::default(),
+      cache: None,
+    })
+  }
+
+  /// Set the timeout and retries of the application.
+  pub fn with_retry_policy(mut self, policy: RetryPolicy) -> HttpClient {
+    self.default_retry = policy;
+    self
+  }
+
+  /// Print the requests instead of sending them.
+  pub fn with_dry_run(mut self, dry_run: Option<DryRun>) -> HttpClient {
+    self.dry_run = dry_run;
+    self
+  }
+
+  pub(crate) fn dry_run(&self) -> Option<&DryRun> {
+    self.dry_run.as_ref()
+  }
+
+  /// Log the requests and the responses on stderr.
+  pub fn with_verbosity(mut self, verbosity: Verbosity) -> HttpClient {
+    self.verbosity = verbosity;
+    self
+  }
+
+  pub(crate) fn verbosity(&self) -> &Verbosity {
+    &self.verbosity
+  }
+
+  /// Cache the responses.
+  pub fn with_cache(mut self, cache: Option<Cache>) -> HttpClient {
+    self.cache = cache;
+    self
+  }
+
+  pub(crate) fn cache(&self) -> Option<&Cache> {
+    self.cache.as_ref()
+  }
+
+  /// Timeout and retries of an endpoint.
+  pub(crate) fn retry_policy(&self, endpoint: &RetryPolicy) -> RetryPolicy {
+    self.retry.or(endpoint).or(&self.default_retry)
+  }
+
+  pub(crate) async fn request(
+    &self,
+    mut request: Request<RequestBody>,
+  ) -> Result<Response<Incoming>> {
+    // Plain HTTP requests are forwarded by the proxy: they carry the proxy credentials.
+    if request.uri().scheme() != Some(&http::uri::Scheme::HTTPS) {
+      if let Some(authorization) = self
+        .proxies
+        .for_uri(request.uri())
+        .and_then(|proxy| proxy.authorization.as_ref())
+      {
+        request.headers_mut().insert(
+          hyper::header::PROXY_AUTHORIZATION,
+          authorization.parse().into_diagnostic()?,
+        );
+      }
+    }
+    self.client.request(request).await.into_diagnostic()
+  }
+}
+
+/// The requests of the authentication (ie. the OAuth2 token requests) go through the proxy,
+/// with the TLS settings and the timeout of the application.
+#[async_trait]
+impl AuthClient for HttpClient {
+  async fn send(&self, request: Request<Vec<u8>>) -> Result<Response<Vec<u8>>> {
+    let uri = request.uri().to_string();
+    let timeout = self.retry_policy(&RetryPolicy::default()).timeout();
+    let exchange = async {
+      let response = self.request(request.map(full)).await?;
+      let (parts, body) = response.into_parts();
+      let body = body.collect().await.into_diagnostic()?.to_bytes();
+      Ok::<_, miette::Report>(Response::from_parts(parts, body.to_vec()))
+    };
+    tokio::time::timeout(timeout, exchange)
+      .await
+      .map_err(|_| miette!("Timeout after {}s", timeout.as_secs()))?
+      .wrap_err_with(|| format!("Can't reach {uri}"))
+  }
+}
+
+/// Open the TCP and TLS connections for the pool.
+#[derive(Clone)]
+pub(crate) struct Connector {
+  tls: TlsConnector,
+  proxies: Arc<Proxies>,
+}
+
+impl Connector {
+  async fn connect(self, uri: Uri) -> io::Result<Stream> {
+    let uri_host = uri
+      .host()
+      .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "uri has no host"))?;
+    let host = uri_host
+      .trim_start_matches('[')
+      .trim_end_matches(']')
+      .to_string();
+    let is_https = uri.scheme() == Some(&http::uri::Scheme::HTTPS);
+    let port = uri.port_u16().unwrap_or(if is_https { 443 } else { 80 });
+    let mut timings = Timings::default();
+
+    let stream = if let Some(proxy) = self.proxies.for_uri(&uri) {
+      trace!(
+        "Connecting to {host}:{port} through {}:{}",
+        proxy.host,
+        proxy.port
+      );
+      let mut stream = tcp_connect(&proxy.host, proxy.port, &mut timings).await?;
+      if !is_https {
+        return Ok(Stream::new(Inner::Proxied(TokioIo::new(stream)), timings));
+      }
+      tunnel(
+        &mut stream,
+        &format!("{uri_host}:{port}"),
+        proxy.authorization.as_deref(),
+      )
+      .await?;
+      stream
+    } else {
+      trace!("Connecting to {host}:{port}");
+      tcp_connect(&host, port, &mut timings).await?
+    };
+
+    if is_https {
+      let domain = pki_types::ServerName::try_from(host)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+      let start = Instant::now();
+      let stream = self.tls.connect(domain, stream).await?;
+      timings.tls = Some(start.elapsed());
+      Ok(Stream::new(
+        Inner::Tls(Box::new(TokioIo::new(stream))),
+        timings,
+      ))
+    } else {
+      Ok(Stream::new(Inner::Plain(TokioIo::new(stream)), timings))
+    }
+  }
+}
+
+/// Resolve `host` and open a TCP connection.
+async fn tcp_connect(host: &str, port: u16, timings: &mut Timings) -> io::Result<TcpStream> {
+  let start = Instant::now();
+  let addresses: Vec<SocketAddr> = lookup_host((host, port)).await?.collect();
+  timings.dns = start.elapsed();
+  let start = Instant::now();
+  let stream = TcpStream::connect(addresses.as_slice()).await?;
+  timings.connect = start.elapsed();
+  stream.set_nodelay(true)?;
+  Ok(stream)
+}
+
+/// Open a tunnel to `authority` with the `CONNECT` method.
+async fn tunnel(
+  stream: &mut TcpStream,
+  authority: &str,
+  authorization: Option<&str>,
+) -> io::Result<()> {
+  let mut request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+  if let Some(authorization) = authorization {
+    request.push_str(&format!("Proxy-Authorization: {authorization}\r\n"));
+  }
+  request.push_str("\r\n");
+  stream.write_all(request.as_bytes()).await?;
+
+  let mut response = Vec::new();
+  let mut buffer = [0; 1024];
+  while !response.windows(4).any(|w| w == b"\r\n\r\n") {
+    let read = stream.read(&mut buffer).await?;
+    if read == 0 {
+      return Err(io::Error::new(
+        io::ErrorKind::UnexpectedEof,
+        "The proxy closed the connection",
+      ));
+    }
+    if response.len() > 16 * 1024 {
+      return Err(io::Error::other("The proxy response is too long"));
+    }
+    response.extend_from_slice(&buffer[..read]);
+  }
+
+  let response = String::from_utf8_lossy(&response);
+  let status_line = response.lines().next().unwrap_or_default();
+  match status_line.split_whitespace().nth(1) {
+    Some(status) if status.starts_with('2') => Ok(()),
+    _ => Err(io::Error::other(format!(
+      "The proxy refused to connect to {authority}: {status_line}"
+    ))),
+  }
+}
+
+impl Service<Uri> for Connector {
+  type Response = Stream;
+  type Error = io::Error;
+  type Future = Pin<Box<dyn Future<Output = io::Result<Stream>> + Send>>;
+
+  fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn call(&mut self, uri: Uri) -> Self::Future {
+    Box::pin(self.clone().connect(uri))
+  }
+}
+
+/// Durations of the connection steps.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Timings {
+  pub dns: Duration,
+  pub connect: Duration,
+  pub tls: Option<Duration>,
+}
+
+/// Timings of a connection, set in the extensions of its responses.
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionTimings {
+  timings: Timings,
+  reused: Arc<AtomicBool>,
+}
+
+impl ConnectionTimings {
+  /// The timings of the connection, or `None` if the connection was already used.
+  pub(crate) fn take(&self) -> Option<&Timings> {
+    if self.reused.swap(true, Ordering::Relaxed) {
+      None
+    } else {
+      Some(&self.timings)
+    }
+  }
+}
+
+/// A connection, encrypted or not.
+pub(crate) struct Stream {
+  inner: Inner,
+  timings: ConnectionTimings,
+}
+
+impl Stream {
+  fn new(inner: Inner, timings: Timings) -> Stream {
+    Stream {
+      inner,
+      timings: ConnectionTimings {
+        timings,
+        reused: Default::default(),
+      },
+    }
+  }
+}
+
+enum Inner {
+  Plain(TokioIo<TcpStream>),
+  /// A connection to a proxy that forwards the requests.
+  Proxied(TokioIo<TcpStream>),
+  Tls(Box<TokioIo<TlsStream<TcpStream>>>),
+}
+
+impl Connection for Stream {
+  fn connected(&self) -> Connected {
+    let connected = Connected::new().extra(self.timings.clone());
+    match &self.inner {
+      Inner::Tls(stream) if stream.inner().get_ref().1.alpn_protocol() == Some(b"h2") => {
+        connected.negotiated_h2()
+      }
+      Inner::Proxied(_) => connected.proxy(true),
+      _ => connected,
+    }
+  }
+}
+impl Read for Stream {
+  fn poll_read(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    buf: ReadBufCursor<'_>,
+  ) -> Poll<io::Result<()>> {
+    match &mut self.get_mut().inner {
+      Inner::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+      Inner::Proxied(stream) => Pin::new(stream).poll_read(cx, buf),
+      Inner::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+    }
+  }
+}
+
+impl Write for Stream {
+  fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+    match &mut self.get_mut().inner {
+      Inner::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+      Inner::Proxied(stream) => Pin::new(stream).poll_write(cx, buf),
+      Inner::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+    }
+  }
+
+  fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    match &mut self.get_mut().inner {
+      Inner::Plain(stream) => Pin::new(stream).poll_flush(cx),
+      Inner::Proxied(stream) => Pin::new(stream).poll_flush(cx),
+      Inner::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+    }
+  }
+
+  fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+    match &mut self.get_mut().inner {
+      Inner::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+      Inner::Proxied(stream) => Pin::new(stream).poll_shutdown(cx),
+      Inner::Tls(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+    }
+  }
+
+  fn is_write_vectored(&self) -> bool {
+    match &self.inner {
+      Inner::Plain(stream) => stream.is_write_vectored(),
+      Inner::Proxied(stream) => stream.is_write_vectored(),
+      Inner::Tls(stream) => stream.is_write_vectored(),
+    }
+  }
+
+  fn poll_write_vectored(
+    self: Pin<&mut Self>,
+    cx: &mut Context<'_>,
+    bufs: &[io::IoSlice<'_>],
+  ) -> Poll<io::Result<usize>> {
+    match &mut self.get_mut().inner {
+      Inner::Plain(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+      Inner::Proxied(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+      Inner::Tls(stream) => Pin::new(stream.as_mut()).poll_write_vectored(cx, bufs),
+    }
+  }
+}
@@ -0,0 +1,594 @@
+use crate::debug::redact_setting;
+use clap::{parser::ValueSource, Arg, ArgAction, ArgMatches, Command};
+use config::{Config, Source, ValueKind};
+use hyper::Uri;
+use miette::{bail, miette, IntoDiagnostic, Result, WrapErr};
+use std::{
+  fs,
+  io::{ErrorKind, Write},
+  num::NonZeroU64,
+  path::{Path, PathBuf},
+  process,
+};
+use toml_edit::{value, DocumentMut, Item, Table, Value};
+
+/// Type of the value of a known setting.
+enum Kind {
+  Url,
+  Bool,
+  Integer,
+  /// A positive number of seconds.
+  Seconds,
+  Text,
+}
+
+/// The settings read by crud-api. The other keys are stored as strings.
+const KNOWN_SETTINGS: &[(&str, Kind)] = &[
+  ("base_url", Kind::Url),
+  ("proxy", Kind::Url),
+  ("ca_cert", Kind::Text),
+  ("system_certs", Kind::Bool),
+  ("client_cert", Kind::Text),
+  ("client_key", Kind::Text),
+  ("insecure", Kind::Bool),
+  ("timeout", Kind::Seconds),
+  ("retries", Kind::Integer),
+  ("cache", Kind::Bool),
+  ("cache_ttl", Kind::Integer),
+  ("default_profile", Kind::Text),
+];
+
+pub fn config_subcommand(app: Command) -> Command {
+  let key = || Arg::new("key").required(true).help("Name of the setting");
+  let name = || Arg::new("name").required(true).help("Name of the profile");
+  app.subcommand(
+    Command::new("config")
+      .about("Manage the settings and the profiles")
+      .long_about(
+        "Manage the settings and the profiles.\n\
+         `get`, `set` and `unset` apply to the profile selected by `--profile`, else to the top level settings.",
+      )
+      .subcommand_required(true)
+      .subcommand(
+        Command::new("get")
+          .about("Print the value of a setting")
+          .arg(key()),
+      )
+      .subcommand(
+        Command::new("set")
+          .about("Set a setting")
+          .arg(key())
+          .arg(Arg::new("value").required(true).help("Value of the setting")),
+      )
+      .subcommand(Command::new("unset").about("Remove a setting").arg(key()))
+      .subcommand(Command::new("list").about("List the settings of the user file"))
+      .subcommand(
+        Command::new("show")
+          .about("Show the effective settings, merged from all the sources")
+          .arg(
+            Arg::new("origin")
+              .long("origin")
+              .action(ArgAction::SetTrue)
+              .help("Show where each value comes from"),
+          ),
+      )
+      .subcommand(Command::new("path").about("Print the path of the user settings file"))
+      .subcommand(Command::new("edit").about("Open the user settings file in $VISUAL or $EDITOR"))
+      .subcommand(
+        Command::new("profile")
+          .about("Manage the profiles")
+          .subcommand_required(true)
+          .subcommand(Command::new("add").about("Add a profile").arg(name()))
+          .subcommand(Command::new("remove").about("Remove a profile").arg(name()))
+          .subcommand(Command::new("list").about("List the profiles"))
+          .subcommand(
+            Command::new("use")
+              .about("Use the profile when `--profile` is omitted")
+              .arg(name()),
+          ),
+      ),
+  )
+}
+
+/// Use the `default_profile` setting when `--profile` is omitted.
+pub fn default_profile(app: Command, settings: &Config) -> Command {
+  match settings.get_string("default_profile") {
+    Ok(profile) if !profile.is_empty() => app.mut_arg("profile", |arg| arg.default_value(profile)),
+    _ => app,
+  }
+}
+
+/// Run the `config` subcommand.
+pub fn run_config(config: &ArgMatches, matches: &ArgMatches, settings: &Config) -> Result<()> {
+  let path = settings_path(settings)?;
+  // The profile given on the command line. The default profile isn't edited implicitly.
+  let profile = matches
+    .get_one::<String>("profile")
+    .filter(|_| matches.value_source("profile") == Some(ValueSource::CommandLine))
+    .map(String::as_str);
+  // The secret settings are redacted unless `--show-secrets` is given.
+  let show_secrets = matches.try_get_one::<bool>("show_secrets").ok().flatten() == Some(&true);
+  match config.subcommand() {
+    Some(("get", args)) => {
+      let key = key(args)?;
+      let selected = matches.get_one::<String>("profile");
+      let value = selected
+        .and_then(|profile| {
+          settings
+            .get::<config::Value>(&format!("profile.{profile}.{key}"))
+            .ok()
+        })
+        .or_else(|| settings.get::<config::Value>(key).ok())
+        .ok_or_else(|| miette!("Setting `{key}` not found"))?;
+      println!("{value}");
+    }
+    Some(("set", args)) => {
+      let mut doc = read(&path)?;
+      let value = args.get_one::<String>("value").unwrap();
+      set(&mut doc, profile, key(args)?, value)?;
+      write(&path, &doc)?;
+    }
+    Some(("unset", args)) => {
+      let mut doc = read(&path)?;
+      let key = key(args)?;
+      if !unset(&mut doc, profile, key) {
+        bail!("Setting `{key}` not found");
+      }
+      write(&path, &doc)?;
+    }
+    Some(("list", _)) => {
+      for (key, value) in entries(read(&path)?.as_table(), "") {
+        println!("{key} = {}", redact_setting(&key, value, show_secrets));
+      }
+    }
+    Some(("show", args)) => {
+      let origin = args.get_flag("origin");
+      for (key, value) in effective_entries(settings)? {
+        let shown = redact_setting(&key, &value, show_secrets);
+        if origin {
+          println!("{key} = {shown}  # {}", origin_label(value.origin()));
+        } else {
+          println!("{key} = {shown}");
+        }
+      }
+    }
+    Some(("path", _)) => println!("{}", path.display()),
+    Some(("edit", _)) => edit(&path)?,
+    Some(("profile", args)) => match args.subcommand() {
+      Some(("add", args)) => {
+        let mut doc = read(&path)?;
+        add_profile(&mut doc, name(args))?;
+        write(&path, &doc)?;
+      }
+      Some(("remove", args)) => {
+        let mut doc = read(&path)?;
+        remove_profile(&mut doc, name(args))?;
+        write(&path, &doc)?;
+      }
+      Some(("list", _)) => {
+        let doc = read(&path)?;
+        let default = doc.get("default_profile").and_then(Item::as_str);
+        for profile in profiles(&doc) {
+          let mark = if Some(profile.as_str()) == default {
+            "*"
+          } else {
+            " "
+          };
+          println!("{mark} {profile}");
+        }
+      }
+      Some(("use", args)) => {
+        let mut doc = read(&path)?;
+        set(&mut doc, None, "default_profile", name(args))?;
+        write(&path, &doc)?;
+      }
+      _ => unreachable!("subcommand_required"),
+    },
+    _ => unreachable!("subcommand_required"),
+  }
+  Ok(())
+}
+
+fn settings_path(settings: &Config) -> Result<PathBuf> {
+  settings
+    .get_string("configuration_path")
+    .map(PathBuf::from)
+    .map_err(|_| miette!("No settings directory"))
+}
+
+fn key(args: &ArgMatches) -> Result<&str> {
+  let key = args.get_one::<String>("key").unwrap();
+  if key.is_empty()
+    || !key
+      .chars()
+      .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+  {
+    bail!(
+      help = "Use `--profile` to change the settings of a profile",
+      "Invalid setting name `{key}`"
+    );
+  }
+  if key == "profile" {
+    bail!(
+      help = "Use `config profile` to manage the profiles",
+      "`profile` isn't a setting"
+    );
+  }
+  Ok(key)
+}
+
+fn name(args: &ArgMatches) -> &str {
+  args.get_one::<String>("name").unwrap()
+}
+
+/// Read the settings file. A missing file is an empty document.
+fn read(path: &Path) -> Result<DocumentMut> {
+  let content = match fs::read_to_string(path) {
+    Ok(content) => content,
+    Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+    Err(error) => {
+      return Err(error)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Can't read {}", path.display()))
+    }
+  };
+  content
+    .parse::<DocumentMut>()
+    .into_diagnostic()
+    .wrap_err_with(|| format!("Invalid settings file {}", path.display()))
+}
+
+fn write(path: &Path, doc: &DocumentMut) -> Result<()> {
+  if let Some(dir) = path.parent() {
+    fs::create_dir_all(dir)
+      .into_diagnostic()
+      .wrap_err_with(|| format!("Can't create the directory {}", dir.display()))?;
+  }
+  // The file is replaced at once: an interrupted write doesn't truncate the settings.
+  let mut tmp = path.as_os_str().to_owned();
+  tmp.push(".tmp");
+  let tmp = PathBuf::from(tmp);
+  // The settings can hold secrets: the file keeps its permissions, and a new file is only
+  // readable by the user.
+  let permissions = fs::metadata(path)
+    .map(|metadata| metadata.permissions())
+    .ok();
+  let _ = fs::remove_file(&tmp);
+  let mut options = fs::OpenOptions::new();
+  options.write(true).create_new(true);
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::OpenOptionsExt;
+    options.mode(0o600);
+  }
+  options
+    .open(&tmp)
+    .and_then(|mut file| {
+      file.write_all(doc.to_string().as_bytes())?;
+      match permissions {
+        Some(permissions) => file.set_permissions(permissions),
+        None => Ok(()),
+      }
+    })
+    .and_then(|_| fs::rename(&tmp, path))
+    .into_diagnostic()
+    .wrap_err_with(|| format!("Can't write {}", path.display()))
+    .inspect_err(|_| {
+      let _ = fs::remove_file(&tmp);
+    })
+}
+
+/// Check the value of the known settings and convert it to its TOML type.
+fn parse_value(key: &str, raw: &str) -> Result<Value> {
+  let kind = KNOWN_SETTINGS
+    .iter()
+    .find_map(|(name, kind)| (*name == key).then_some(kind))
+    .unwrap_or(&Kind::Text);
+  match kind {
+    Kind::Url => match raw.parse::<Uri>() {
+      Ok(uri) if uri.scheme().is_some() && uri.host().is_some() => Ok(raw.into()),
+      _ => bail!(
+        help = "Use an absolute url: `https://api.example.com`",
+        "Invalid url `{raw}` for `{key}`"
+      ),
+    },
+    Kind::Bool => raw
+      .parse::<bool>()
+      .map(Value::from)
+      .map_err(|_| miette!("`{key}` should be `true` or `false`")),
+    Kind::Integer => raw
+      .parse::<u32>()
+      .map(|n| Value::from(i64::from(n)))
+      .map_err(|_| miette!("`{key}` should be a positive integer")),
+    // The same check as the HTTP client.
+    Kind::Seconds => raw
+      .parse::<NonZeroU64>()
+      .ok()
+      .and_then(|n| i64::try_from(n.get()).ok())
+      .map(Value::from)
+      .ok_or_else(|| miette!("`{key}` should be a positive number of seconds")),
+    Kind::Text => Ok(raw.into()),
+  }
+}
+
+/// The table of the profile, or the top level table.
+fn table_mut<'a>(doc: &'a mut DocumentMut, profile: Option<&str>) -> Result<&'a mut Table> {
+  match profile {
+    Some(profile) => doc
+      .get_mut("profile")
+      .and_then(Item::as_table_mut)
+      .and_then(|profiles| profiles.get_mut(profile))
+      .and_then(Item::as_table_mut)
+      .ok_or_else(|| {
+        miette!(
+          help = format!("Add it with `config profile add {profile}`"),
+          "Unknown profile `{profile}`"
+        )
+      }),
+    None => Ok(doc.as_table_mut()),
+  }
+}
+
+fn set(doc: &mut DocumentMut, profile: Option<&str>, key: &str, raw: &str) -> Result<()> {
+  let parsed = parse_value(key, raw)?;
+  if key == "default_profile" && !profiles(doc).iter().any(|profile| profile == raw) {
+    bail!(
+      help = format!("Add it with `config profile add {raw}`"),
+      "Unknown profile `{raw}`"
+    );
+  }
+  let table = table_mut(doc, profile)?;
+  match table.get_mut(key).and_then(Item::as_value_mut) {
+    // Keep the comments and the formatting of the value.
+    Some(current) => {
+      let decor = current.decor().clone();
+      *current = parsed;
+      *current.decor_mut() = decor;
+    }
+    None => {
+      table.insert(key, value(parsed));
+    }
+  }
+  Ok(())
+}
+
+fn unset(doc: &mut DocumentMut, profile: Option<&str>, key: &str) -> bool {
+  table_mut(doc, profile)
+    .map(|table| table.remove(key).is_some())
+    .unwrap_or(false)
+}
+
+fn profiles(doc: &DocumentMut) -> Vec<String> {
+  doc
+    .get("profile")
+    .and_then(Item::as_table)
+    .map(|profiles| {
+      profiles
+        .iter()
+        .filter(|(_, item)| item.is_table())
+        .map(|(name, _)| name.to_string())
+        .collect()
+    })
+    .unwrap_or_default()
+}
+
+fn add_profile(doc: &mut DocumentMut, name: &str) -> Result<()> {
+  let profiles = doc
+    .entry("profile")
+    .or_insert_with(|| {
+      let mut table = Table::new();
+      table.set_implicit(true);
+      Item::Table(table)
+    })
+    .as_table_mut()
+    .ok_or_else(|| miette!("`profile` should be a table"))?;
+  if profiles.contains_key(name) {
+    bail!("The profile `{name}` already exists");
+  }
+  profiles.insert(name, Item::Table(Table::new()));
+  Ok(())
+}
+
+fn remove_profile(doc: &mut DocumentMut, name: &str) -> Result<()> {
+  let removed = doc
+    .get_mut("profile")
+    .and_then(Item::as_table_mut)
+    .and_then(|profiles| profiles.remove(name));
+  if removed.is_none() {
+    bail!("Unknown profile `{name}`");
+  }
+  if doc.get("default_profile").and_then(Item::as_str) == Some(name) {
+    doc.remove("default_profile");
+  }
+  Ok(())
+}
+
+/// The values of the table with their dotted keys.
+fn entries(table: &Table, prefix: &str) -> Vec<(String, String)> {
+  table
+    .iter()
+    .flat_map(|(key, item)| match item {
+      Item::Table(table) => entries(table, &format!("{prefix}{key}.")),
+      Item::Value(Value::InlineTable(table)) => {
+        entries(&table.clone().into_table(), &format!("{prefix}{key}."))
+      }
+      Item::Value(value) => vec![(
+        format!("{prefix}{key}"),
+        value.to_string().trim().to_string(),
+      )],
+      _ => vec![],
+    })
+    .collect()
+}
+
+/// The effective values with their dotted keys.
+fn effective_entries(settings: &Config) -> Result<Vec<(String, config::Value)>> {
+  fn flatten(
+    prefix: &str,
+    table: config::Map<String, config::Value>,
+  ) -> Vec<(String, config::Value)> {
+    let mut entries: Vec<_> = table
+      .into_iter()
+      .flat_map(|(key, value)| match value.kind {
+        ValueKind::Table(table) => flatten(&format!("{prefix}{key}."), table),
+        _ => vec![(format!("{prefix}{key}"), value)],
+      })
+      .collect();
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+    entries
+  }
+  Ok(flatten("", settings.collect().into_diagnostic()?))
+}
+
+/// Where a value comes from. The files are shown with their absolute path:
+/// `config` gives them relatively to the current directory.
+fn origin_label(origin: Option<&str>) -> String {
+  match origin {
+    None => "default".to_string(),
+    Some("the environment") => "environment".to_string(),
+    Some(file) => Path::new(file)
+      .canonicalize()
+      .map(|path| path.display().to_string())
+      .unwrap_or_else(|_| file.to_string()),
+  }
+}
+
+fn edit(path: &Path) -> Result<()> {
+  let editor = std::env::var("VISUAL")
+    .or_else(|_| std::env::var("EDITOR"))
+    .unwrap_or_else(|_| {
+      if cfg!(target_os = "windows") {
+        "notepad".to_string()
+      } else {
+        "vi".to_string()
+      }
+    });
+  if !path.exists() {
+    write(path, &DocumentMut::new())?;
+  }
+  // The editor may have arguments (`code --wait`). On Unix, the path is given to the shell as
+  // `$1`, so it's never parsed as a command.
+  let status = if cfg!(target_os = "windows") {
+    process::Command::new("cmd")
+      .args(["/C", &editor])
+      .arg(path)
+      .status()
+  } else {
+    process::Command::new("sh")
+      .args(["-c", &format!("{editor} \"$1\""), "sh"])
+      .arg(path)
+      .status()
+  }
+  .into_diagnostic()
+  .wrap_err_with(|| format!("Can't run the editor `{editor}`"))?;
+  if !status.success() {
+    bail!("The editor `{editor}` failed: {status}");
+  }
+  read(path).map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{add_profile, entries, profiles, read, remove_profile, set, unset, write};
+  use toml_edit::DocumentMut;
+
+  const SETTINGS: &str = r#"# Production API
+base_url = "https://api.example.com" # default
+
+[profile.dev]
+# Local server
+base_url = "http://localhost:8080"
+"#;
+
+  #[test]
+  fn test_set_keeps_comments() {
+    let mut doc = SETTINGS.parse::<DocumentMut>().unwrap();
+    set(&mut doc, None, "base_url", "https://new.example.com").unwrap();
+    set(&mut doc, Some("dev"), "timeout", "5").unwrap();
+    set(&mut doc, None, "default_profile", "dev").unwrap();
+    assert_eq!(
+      doc.to_string(),
+      r#"# Production API
+base_url = "https://new.example.com" # default
+default_profile = "dev"
+
+[profile.dev]
+# Local server
+base_url = "http://localhost:8080"
+timeout = 5
+"#
+    );
+  }
+
+  #[test]
+  fn test_set_validates_known_keys() {
+    let mut doc = SETTINGS.parse::<DocumentMut>().unwrap();
+    assert!(set(&mut doc, None, "base_url", "not an url").is_err());
+    assert!(set(&mut doc, None, "base_url", "/relative").is_err());
+    assert!(set(&mut doc, None, "insecure", "yes").is_err());
+    assert!(set(&mut doc, None, "retries", "-1").is_err());
+    assert!(set(&mut doc, None, "timeout", "0").is_err());
+    assert!(set(&mut doc, None, "cache", "1").is_err());
+    assert!(set(&mut doc, None, "cache_ttl", "1m").is_err());
+    assert!(set(&mut doc, None, "default_profile", "prod").is_err());
+    assert!(set(&mut doc, Some("prod"), "base_url", "https://prod").is_err());
+    set(&mut doc, None, "insecure", "true").unwrap();
+    set(&mut doc, None, "retries", "0").unwrap();
+    set(&mut doc, None, "timeout", "10").unwrap();
+    set(&mut doc, None, "cache", "true").unwrap();
+    set(&mut doc, None, "cache_ttl", "60").unwrap();
+    set(&mut doc, None, "oauth2_client_id", "42").unwrap();
+    assert_eq!(doc["insecure"].as_bool(), Some(true));
+    assert_eq!(doc["timeout"].as_integer(), Some(10));
+    assert_eq!(doc["cache"].as_bool(), Some(true));
+    assert_eq!(doc["cache_ttl"].as_integer(), Some(60));
+    assert_eq!(doc["oauth2_client_id"].as_str(), Some("42"));
+  }
+
+  #[test]
+  fn test_profiles() {
+    let mut doc = SETTINGS.parse::<DocumentMut>().unwrap();
+    add_profile(&mut doc, "prod").unwrap();
+    assert!(add_profile(&mut doc, "dev").is_err());
+    assert_eq!(profiles(&doc), vec!["dev", "prod"]);
+    set(&mut doc, None, "default_profile", "dev").unwrap();
+    remove_profile(&mut doc, "dev").unwrap();
+    assert!(remove_profile(&mut doc, "dev").is_err());
+    assert_eq!(profiles(&doc), vec!["prod"]);
+    assert!(doc.get("default_profile").is_none());
+    assert!(unset(&mut doc, None, "base_url"));
+    assert!(!unset(&mut doc, None, "base_url"));
+    assert_eq!(entries(doc.as_table(), ""), vec![]);
+    set(&mut doc, Some("prod"), "retries", "3").unwrap();
+    assert_eq!(
+      entries(doc.as_table(), ""),
+      vec![("profile.prod.retries".to_string(), "3".to_string())]
+    );
+  }
+
+  #[test]
+  fn test_write() {
+    let dir = std::env::temp_dir().join(format!("crud-api-config-{}", std::process::id()));
+    let path = dir.join("settings.toml");
+    assert_eq!(read(&path).unwrap().to_string(), "");
+    let doc = SETTINGS.parse::<DocumentMut>().unwrap();
+    write(&path, &doc).unwrap();
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      let mode = |path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+      assert_eq!(mode(&path), 0o600);
+      std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+      write(&path, &doc).unwrap();
+      assert_eq!(mode(&path), 0o640);
+    }
+    write(&path, &doc).unwrap();
+    assert_eq!(read(&path).unwrap().to_string(), SETTINGS);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    // Only a missing file is empty: the other settings aren't lost.
+    std::fs::write(&path, "base_url = ").unwrap();
+    assert!(read(&path).is_err());
+    assert!(read(&dir).is_err());
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+}
@@ -0,0 +1,607 @@
+use crate::{client::ConnectionTimings, error::ApiError};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use hyper::{body::Incoming, header::HeaderName, HeaderMap, Request, Response, Uri};
+use std::{fmt::Display, time::Duration};
+
+/// Headers that contain secrets.
+const SECRET_HEADERS: [&str; 7] = [
+  "authorization",
+  "proxy-authorization",
+  "cookie",
+  "set-cookie",
+  "x-api-key",
+  "private-token",
+  "x-auth-token",
+];
+/// Fields of the bodies that contain secrets (the case is ignored).
+const SECRET_FIELDS: [&str; 11] = [
+  "password",
+  "passwd",
+  "secret",
+  "client_secret",
+  "token",
+  "access_token",
+  "refresh_token",
+  "id_token",
+  "api_key",
+  "apikey",
+  "private_token",
+];
+const REDACTED: &str = "<redacted>";
+
+/// Parts of a request set by the authentication. They are redacted.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Secrets {
+  pub headers: Vec<HeaderName>,
+  /// Names of the query parameters.
+  pub query: Vec<String>,
+  /// Fields of the body (ie. the password of a login).
+  pub fields: Vec<String>,
+}
+
+impl Secrets {
+  /// The secrets of `request`: its headers and the query parameters that are not in `query`,
+  /// the query before the authentication.
+  pub(crate) fn added(query: Option<&str>, request: &Request<()>) -> Secrets {
+    let before: Vec<&str> = query.unwrap_or_default().split('&').collect();
+    Secrets {
+      headers: request.headers().keys().cloned().collect(),
+      query: request
+        .uri()
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|param| !before.contains(param))
+        .map(|param| param.split('=').next().unwrap_or_default().to_string())
+        .collect(),
+      fields: vec![],
+    }
+  }
+}
+
+/// Print the requests instead of sending them.
+#[derive(Debug, Clone, Default)]
+pub struct DryRun {
+  /// Print the request as a `curl` command.
+  pub curl: bool,
+  /// Print the secrets of the request.
+  pub show_secrets: bool,
+}
+
+/// Log the requests and the responses on stderr.
+#[derive(Debug, Clone, Default)]
+pub struct Verbosity {
+  /// - 1: request line, response status and timings.
+  /// - 2: and the headers.
+  /// - 3: and the bodies.
+  pub level: u8,
+  /// Print the secrets of the requests.
+  pub show_secrets: bool,
+}
+
+pub fn debug_args(app: Command) -> Command {
+  app
+    .arg(
+      Arg::new("verbose")
+        .short('v')
+        .long("verbose")
+        .help("Log the HTTP exchanges on stderr (-vv: with the headers, -vvv: with the bodies)")
+        .help_heading("Debug")
+        .global(true)
+        .action(ArgAction::Count),
+    )
+    .arg(
+      Arg::new("dry_run")
+        .long("dry-run")
+        .help("Print the request instead of sending it")
+        .help_heading("Debug")
+        .global(true)
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("curl")
+        .long("curl")
+        .help("Print the request as a curl command instead of sending it")
+        .help_heading("Debug")
+        .global(true)
+        .action(ArgAction::SetTrue),
+    )
+    .arg(
+      Arg::new("show_secrets")
+        .long("show-secrets")
+        .help("Don't redact the secrets of the printed requests and settings")
+        .help_heading("Debug")
+        .global(true)
+        .action(ArgAction::SetTrue),
+    )
+}
+
+/// The command stopped after printing its request (`--dry-run`).
+pub fn is_dry_run(error: &miette::Report) -> bool {
+  matches!(error.downcast_ref(), Some(ApiError::DryRun))
+}
+
+pub fn clap_match_dry_run(matches: &ArgMatches) -> Option<DryRun> {
+  let flag = |id: &str| matches.try_get_one::<bool>(id).ok().flatten() == Some(&true);
+  if flag("dry_run") || flag("curl") {
+    Some(DryRun {
+      curl: flag("curl"),
+      show_secrets: flag("show_secrets"),
+    })
+  } else {
+    None
+  }
+}
+
+pub fn clap_match_verbosity(matches: &ArgMatches) -> Verbosity {
+  Verbosity {
+    level: matches
+      .try_get_one::<u8>("verbose")
+      .ok()
+      .flatten()
+      .copied()
+      .unwrap_or_default(),
+    show_secrets: matches.try_get_one::<bool>("show_secrets").ok().flatten() == Some(&true),
+  }
+}
+
+impl DryRun {
+  /// Render the request. `secrets` are the parts set by the authentication.
+  pub(crate) fn render<B>(&self, request: &Request<B>, body: &str, secrets: &Secrets) -> String {
+    let uri = redact_uri(request.uri(), self.show_secrets, secrets);
+    let headers = redact(request.headers(), self.show_secrets, &secrets.headers);
+    let body = &redact_body(body, self.show_secrets, &secrets.fields);
+
+    if self.curl {
+      let mut command = format!("curl -X {} {}", request.method(), shell_quote(&uri));
+      for (key, value) in headers {
+        command.push_str(&format!(
+          " \\\n  -H {}",
+          shell_quote(&format!("{key}: {value}"))
+        ));
+      }
+      if !body.is_empty() {
+        command.push_str(&format!(" \\\n  --data-raw {}", shell_quote(body)));
+      }
+      command
+    } else {
+      let mut text = format!("{} {uri}\n", request.method());
+      for (key, value) in headers {
+        text.push_str(&format!("{key}: {value}\n"));
+      }
+      if !body.is_empty() {
+        let body = serde_json::from_str::<serde_json::Value>(body)
+          .and_then(|json| serde_json::to_string_pretty(&json))
+          .unwrap_or_else(|_| body.to_string());
+        text.push_str(&format!("\n{body}\n"));
+      }
+      text.trim_end().to_string()
+    }
+  }
+}
+
+impl Verbosity {
+  pub(crate) fn request<B>(&self, request: &Request<B>, body: &str, secrets: &Secrets) {
+    log(self.request_log(request, body, secrets));
+  }
+
+  fn request_log<B>(&self, request: &Request<B>, body: &str, secrets: &Secrets) -> String {
+    if self.level == 0 {
+      return String::new();
+    }
+    let mut log = format!(
+      "> {} {}\n",
+      request.method(),
+      redact_uri(request.uri(), self.show_secrets, secrets)
+    );
+    if self.level >= 2 {
+      for (key, value) in redact(request.headers(), self.show_secrets, &secrets.headers) {
+        log.push_str(&format!("> {key}: {value}\n"));
+      }
+    }
+    if self.level >= 3 && !body.is_empty() {
+      log.push_str(&format!(
+        ">\n{}\n",
+        redact_body(body, self.show_secrets, &secrets.fields)
+      ));
+    }
+    log
+  }
+
+  /// Log the response. `elapsed` is the time since the request was sent.
+  pub(crate) fn response(&self, response: &Response<Incoming>, elapsed: Duration) {
+    log(self.response_log(response, elapsed));
+  }
+
+  fn response_log<B>(&self, response: &Response<B>, elapsed: Duration) -> String {
+    if self.level == 0 {
+      return String::new();
+    }
+    let mut log = format!("< {:?} {}\n", response.version(), response.status());
+    if self.level >= 2 {
+      for (key, value) in redact(response.headers(), self.show_secrets, &[]) {
+        log.push_str(&format!("< {key}: {value}\n"));
+      }
+    }
+    match response
+      .extensions()
+      .get::<ConnectionTimings>()
+      .and_then(ConnectionTimings::take)
+    {
+      Some(timings) => {
+        let tls = timings.tls.unwrap_or_default();
+        let tls_timing = timings
+          .tls
+          .map(|tls| format!(", tls: {tls:.1?}"))
+          .unwrap_or_default();
+        log.push_str(&format!(
+          "* dns: {:.1?}, connect: {:.1?}{tls_timing}, ttfb: {:.1?}, total: {:.1?}\n",
+          timings.dns,
+          timings.connect,
+          elapsed.saturating_sub(timings.dns + timings.connect + tls),
+          elapsed
+        ));
+      }
+      None => log.push_str(&format!("* connection reused, ttfb: {elapsed:.1?}\n")),
+    }
+    log
+  }
+
+  pub(crate) fn note(&self, message: &str) {
+    if self.level > 0 {
+      eprintln!("* {message}");
+    }
+  }
+
+  /// Log a response body. Its secret fields are redacted (ie. the token of a login).
+  pub(crate) fn body(&self, body: &[u8]) {
+    log(self.body_log(body));
+  }
+
+  fn body_log(&self, body: &[u8]) -> String {
+    if self.level >= 3 && !body.is_empty() {
+      let body = String::from_utf8_lossy(body);
+      format!("<\n{}\n", redact_body(&body, self.show_secrets, &[]))
+    } else {
+      String::new()
+    }
+  }
+}
+
+fn log(log: String) {
+  if !log.is_empty() {
+    eprint!("{log}");
+  }
+}
+
+/// The headers with the secrets redacted.
+fn redact(
+  headers: &HeaderMap,
+  show_secrets: bool,
+  auth_headers: &[HeaderName],
+) -> Vec<(String, String)> {
+  headers
+    .iter()
+    .map(|(key, value)| {
+      let value = if !show_secrets && is_secret(key, auth_headers) {
+        REDACTED.to_string()
+      } else {
+        String::from_utf8_lossy(value.as_bytes()).to_string()
+      };
+      (key.to_string(), value)
+    })
+    .collect()
+}
+
+/// The uri with the secret query parameters redacted.
+fn redact_uri(uri: &Uri, show_secrets: bool, secrets: &Secrets) -> String {
+  let uri = uri.to_string();
+  match uri.split_once('?') {
+    Some((path, query)) if !show_secrets && !secrets.query.is_empty() => {
+      let query: Vec<String> = query
+        .split('&')
+        .map(|param| match param.split_once('=') {
+          Some((key, _)) if secrets.query.iter().any(|secret| secret == key) => {
+            format!("{key}={REDACTED}")
+          }
+          _ => param.to_string(),
+        })
+        .collect();
+      format!("{path}?{}", query.join("&"))
+    }
+    _ => uri,
+  }
+}
+
+/// The body with the secret fields redacted: the [`SECRET_FIELDS`] and the `fields` of the
+/// JSON objects (nested ones included), or of the parameters of a form. The body is
+/// unchanged when it has no secret.
+pub(crate) fn redact_body(body: &str, show_secrets: bool, fields: &[String]) -> String {
+  if show_secrets {
+    return body.to_string();
+  }
+  if let Ok(mut json) = serde_json::from_str::<serde_json::Value>(body) {
+    return if redact_json(&mut json, fields) {
+      json.to_string()
+    } else {
+      body.to_string()
+    };
+  }
+  body
+    .split('&')
+    .map(|param| match param.split_once('=') {
+      Some((key, _)) if is_secret_field(key, fields) => format!("{key}={REDACTED}"),
+      _ => param.to_string(),
+    })
+    .collect::<Vec<_>>()
+    .join("&")
+}
+
+/// Redact the secret fields of a JSON value. Returns whether a field was redacted.
+fn redact_json(value: &mut serde_json::Value, fields: &[String]) -> bool {
+  let mut redacted = false;
+  match value {
+    serde_json::Value::Object(object) => {
+      for (key, value) in object.iter_mut() {
+        if is_secret_field(key, fields) && !value.is_null() {
+          *value = serde_json::Value::String(REDACTED.to_string());
+          redacted = true;
+        } else {
+          redacted |= redact_json(value, fields);
+        }
+      }
+    }
+    serde_json::Value::Array(values) => {
+      for value in values {
+        redacted |= redact_json(value, fields);
+      }
+    }
+    _ => {}
+  }
+  redacted
+}
+
+/// The value of a setting, redacted when the setting is a secret: its name, or the suffix of
+/// its name, is one of the [`SECRET_FIELDS`] (`auth_token`, `profile.prod.api_key`...).
+pub(crate) fn redact_setting(key: &str, value: impl Display, show_secrets: bool) -> String {
+  let name = key.rsplit('.').next().unwrap_or(key).to_ascii_lowercase();
+  let secret = SECRET_FIELDS
+    .iter()
+    .any(|field| name == *field || name.ends_with(&format!("_{field}")));
+  if secret && !show_secrets {
+    REDACTED.to_string()
+  } else {
+    value.to_string()
+  }
+}
+
+fn is_secret_field(key: &str, fields: &[String]) -> bool {
+  SECRET_FIELDS
+    .iter()
+    .any(|field| field.eq_ignore_ascii_case(key))
+    || fields.iter().any(|field| field == key)
+}
+
+fn is_secret(key: &HeaderName, auth_headers: &[HeaderName]) -> bool {
+  SECRET_HEADERS.contains(&key.as_str()) || auth_headers.contains(key)
+}
+
+/// Quote a string for a POSIX shell.
+fn shell_quote(value: &str) -> String {
+  format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    clap_match_verbosity, debug_args, redact_body, redact_setting, DryRun, Secrets, Verbosity,
+  };
+  use crate::body::{full, RequestBody};
+  use clap::Command;
+  use hyper::{header::HeaderName, Request, Response};
+  use std::time::Duration;
+
+  fn request() -> Request<RequestBody> {
+    Request::builder()
+      .method("POST")
+      .uri("https://api.local/posts?userId=1")
+      .header("authorization", "Bearer secret")
+      .header("content-type", "application/json")
+      .body(full("{\"title\":\"it's\"}"))
+      .unwrap()
+  }
+
+  #[test]
+  fn test_render_text() {
+    let dry_run = DryRun::default();
+    assert_eq!(
+      dry_run.render(
+        &request(),
+        "{\"title\":\"it's\"}",
+        &Secrets {
+          headers: vec![HeaderName::from_static("authorization")],
+          query: vec![],
+          fields: vec![],
+        }
+      ),
+      "POST https://api.local/posts?userId=1\nauthorization: <redacted>\ncontent-type: application/json\n\n{\n  \"title\": \"it's\"\n}"
+    );
+  }
+
+  #[test]
+  fn test_render_curl() {
+    let dry_run = DryRun {
+      curl: true,
+      show_secrets: true,
+    };
+    assert_eq!(
+      dry_run.render(&request(), "{\"title\":\"it's\"}", &Secrets::default()),
+      "curl -X POST 'https://api.local/posts?userId=1' \\\n  -H 'authorization: Bearer secret' \\\n  -H 'content-type: application/json' \\\n  --data-raw '{\"title\":\"it'\\''s\"}'"
+    );
+  }
+
+  #[test]
+  fn test_secret_query() {
+    let mut request = Request::get("https://api.local/posts?userId=1")
+      .body(())
+      .unwrap();
+    crud_auth::append_query(&mut request, "api_key", "secret").unwrap();
+    let secrets = Secrets::added(Some("userId=1"), &request);
+    assert_eq!(secrets.query, vec!["api_key".to_string()]);
+    let request = request.map(|()| full(""));
+    assert_eq!(
+      DryRun::default().render(&request, "", &secrets),
+      "GET https://api.local/posts?userId=1&api_key=<redacted>"
+    );
+  }
+
+  #[test]
+  fn test_secret_fields() {
+    let fields = vec!["password".to_string()];
+    assert_eq!(
+      redact_body(r#"{"password":"s3cr3t","username":"ann"}"#, false, &fields),
+      r#"{"password":"<redacted>","username":"ann"}"#
+    );
+    assert_eq!(
+      redact_body("username=ann&password=s3cr3t", false, &fields),
+      "username=ann&password=<redacted>"
+    );
+    assert_eq!(
+      redact_body("username=ann&password=s3cr3t", true, &fields),
+      "username=ann&password=s3cr3t"
+    );
+    let secrets = Secrets {
+      fields,
+      ..Default::default()
+    };
+    assert_eq!(
+      DryRun::default().render(
+        &request(),
+        r#"{"username":"ann","password":"s3cr3t"}"#,
+        &secrets
+      ),
+      "POST https://api.local/posts?userId=1\nauthorization: <redacted>\ncontent-type: application/json\n\n{\n  \"password\": \"<redacted>\",\n  \"username\": \"ann\"\n}"
+    );
+  }
+
+  #[test]
+  fn test_default_secret_fields() {
+    assert_eq!(
+      redact_body(
+        r#"{"grant_type":"password","user":{"name":"ann","Password":"s3cr3t"}}"#,
+        false,
+        &[]
+      ),
+      r#"{"grant_type":"password","user":{"Password":"<redacted>","name":"ann"}}"#
+    );
+    assert_eq!(
+      redact_body("grant_type=refresh_token&refresh_token=abc", false, &[]),
+      "grant_type=refresh_token&refresh_token=<redacted>"
+    );
+    // The bodies without secrets are unchanged.
+    assert_eq!(
+      redact_body("{ \"title\": \"it's\" }", false, &[]),
+      "{ \"title\": \"it's\" }"
+    );
+  }
+
+  #[test]
+  fn test_redact_setting() {
+    assert_eq!(redact_setting("auth_token", "abc", false), "<redacted>");
+    assert_eq!(
+      redact_setting("profile.prod.oauth2_client_secret", "abc", false),
+      "<redacted>"
+    );
+    assert_eq!(redact_setting("auth_password", "abc", false), "<redacted>");
+    assert_eq!(redact_setting("api_key", "abc", false), "<redacted>");
+    assert_eq!(redact_setting("auth_token", "abc", true), "abc");
+    assert_eq!(
+      redact_setting("oauth2_token_url", "https://auth", false),
+      "https://auth"
+    );
+    assert_eq!(redact_setting("api_key_header", "X-Key", false), "X-Key");
+  }
+
+  #[test]
+  fn test_verbosity_request() {
+    let request = Request::post("https://api.local/posts?userId=1&api_key=k")
+      .header("authorization", "Bearer secret")
+      .header("content-type", "application/json")
+      .body(())
+      .unwrap();
+    let body = r#"{"title":"it's","token":"abc"}"#;
+    let secrets = Secrets {
+      query: vec!["api_key".to_string()],
+      ..Default::default()
+    };
+    let log = |level, show_secrets| {
+      Verbosity {
+        level,
+        show_secrets,
+      }
+      .request_log(&request, body, &secrets)
+    };
+    assert_eq!(log(0, false), "");
+    assert_eq!(
+      log(1, false),
+      "> POST https://api.local/posts?userId=1&api_key=<redacted>\n"
+    );
+    assert_eq!(
+      log(3, false),
+      "> POST https://api.local/posts?userId=1&api_key=<redacted>\n\
+       > authorization: <redacted>\n\
+       > content-type: application/json\n\
+       >\n{\"title\":\"it's\",\"token\":\"<redacted>\"}\n"
+    );
+    assert_eq!(
+      log(3, true),
+      "> POST https://api.local/posts?userId=1&api_key=k\n\
+       > authorization: Bearer secret\n\
+       > content-type: application/json\n\
+       >\n{\"title\":\"it's\",\"token\":\"abc\"}\n"
+    );
+  }
+
+  #[test]
+  fn test_verbosity_response() {
+    let response = Response::builder()
+      .status(200)
+      .header("set-cookie", "session=abc")
+      .header("content-type", "application/json")
+      .body(())
+      .unwrap();
+    let verbosity = |level| Verbosity {
+      level,
+      show_secrets: false,
+    };
+    let elapsed = Duration::from_micros(1500);
+    assert_eq!(verbosity(0).response_log(&response, elapsed), "");
+    assert_eq!(
+      verbosity(1).response_log(&response, elapsed),
+      "< HTTP/1.1 200 OK\n* connection reused, ttfb: 1.5ms\n"
+    );
+    assert_eq!(
+      verbosity(2).response_log(&response, elapsed),
+      "< HTTP/1.1 200 OK\n\
+       < set-cookie: <redacted>\n\
+       < content-type: application/json\n\
+       * connection reused, ttfb: 1.5ms\n"
+    );
+    let body = br#"{"access_token":"abc","expires_in":3600}"#;
+    assert_eq!(verbosity(2).body_log(body), "");
+    assert_eq!(
+      verbosity(3).body_log(body),
+      "<\n{\"access_token\":\"<redacted>\",\"expires_in\":3600}\n"
+    );
+  }
+
+  #[test]
+  fn test_verbosity_args() {
+    let matches = debug_args(Command::new("app").subcommand(Command::new("posts")))
+      .get_matches_from(["app", "posts", "-vv", "--show-secrets"]);
+    let verbosity = clap_match_verbosity(matches.subcommand_matches("posts").unwrap());
+    assert_eq!(verbosity.level, 2);
+    assert!(verbosity.show_secrets);
+    assert_eq!(clap_match_verbosity(&matches).level, 2);
+  }
+}
@@ -0,0 +1,270 @@
+use hyper::{body::Bytes, header::CONTENT_TYPE, HeaderMap};
+use miette::{IntoDiagnostic, Result, WrapErr};
+use serde::de::{DeserializeOwned, IntoDeserializer};
+
+/// Format of the response bodies.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResponseFormat {
+  Json,
+  /// Requires the `xml` feature.
+  Xml,
+  /// The body is read as a string.
+  Text,
+  /// `application/x-www-form-urlencoded`
+  Form,
+  /// One JSON value per line (`application/x-ndjson`).
+  Ndjson,
+  /// JSON text sequence (`application/json-seq`, RFC 7464).
+  JsonSeq,
+}
+
+/// The `Xml` format in the code generated for `response_format = "xml"`: a compile error
+/// without the `xml` feature.
+#[doc(hidden)]
+#[cfg(feature = "xml")]
+#[macro_export]
+macro_rules! __xml_response_format {
+  () => {
+    $crate::decode::ResponseFormat::Xml
+  };
+}
+
+#[doc(hidden)]
+#[cfg(not(feature = "xml"))]
+#[macro_export]
+macro_rules! __xml_response_format {
+  () => {
+    ::core::compile_error!("response_format = \"xml\" requires the `xml` feature of crud-api")
+  };
+}
+
+impl ResponseFormat {
+  /// The format of a response from its `Content-Type`.
+  pub(crate) fn from_headers(headers: &HeaderMap) -> Option<ResponseFormat> {
+    let content_type = headers.get(CONTENT_TYPE)?.to_str().ok()?;
+    let mime = content_type
+      .split(';')
+      .next()
+      .unwrap_or_default()
+      .trim()
+      .to_ascii_lowercase();
+    if mime == "application/x-ndjson" || mime == "application/jsonl" {
+      Some(ResponseFormat::Ndjson)
+    } else if mime == "application/json-seq" {
+      Some(ResponseFormat::JsonSeq)
+    } else if mime == "application/json" || mime.ends_with("+json") {
+      Some(ResponseFormat::Json)
+    } else if mime == "application/xml" || mime == "text/xml" || mime.ends_with("+xml") {
+      Some(ResponseFormat::Xml)
+    } else if mime == "application/x-www-form-urlencoded" {
+      Some(ResponseFormat::Form)
+    } else if mime.starts_with("text/") {
+      Some(ResponseFormat::Text)
+    } else {
+      None
+    }
+  }
+
+  /// Deserialize a response body.
+  pub(crate) fn decode<T: DeserializeOwned>(&self, body: &Bytes) -> Result<T> {
+    match self {
+      ResponseFormat::Json => serde_json::from_slice(body).into_diagnostic(),
+      #[cfg(feature = "xml")]
+      ResponseFormat::Xml => quick_xml::de::from_reader(body.as_ref()).into_diagnostic(),
+      #[cfg(not(feature = "xml"))]
+      ResponseFormat::Xml => Err(miette::miette!(
+        help = "Enable the `xml` feature of crud-api",
+        "XML responses are not supported"
+      )),
+      ResponseFormat::Text => {
+        let text = String::from_utf8_lossy(body).into_owned();
+        T::deserialize(IntoDeserializer::<serde::de::value::Error>::into_deserializer(text))
+          .into_diagnostic()
+      }
+      ResponseFormat::Form => serde_qs::from_bytes(body).into_diagnostic(),
+      ResponseFormat::Ndjson | ResponseFormat::JsonSeq => {
+        let mut records = Records::new(*self);
+        let values = records
+          .push(body)
+          .into_iter()
+          .chain(records.finish())
+          .map(|record| serde_json::from_slice(&record))
+          .collect::<Result<Vec<serde_json::Value>, _>>()
+          .into_diagnostic()?;
+        serde_json::from_value(serde_json::Value::Array(values)).into_diagnostic()
+      }
+    }
+    .context("Can't deserialize the response")
+  }
+
+  /// The responses of this format are a sequence of values: they can be decoded while
+  /// they're received.
+  pub(crate) fn is_sequence(&self) -> bool {
+    matches!(self, ResponseFormat::Ndjson | ResponseFormat::JsonSeq)
+  }
+}
+
+/// Split an NDJSON or a JSON text sequence into its records.
+pub(crate) struct Records {
+  separator: u8,
+  buffer: Vec<u8>,
+}
+
+impl Records {
+  pub(crate) fn new(format: ResponseFormat) -> Records {
+    Records {
+      // A JSON text of a sequence can span several lines.
+      separator: if format == ResponseFormat::JsonSeq {
+        0x1e
+      } else {
+        b'\n'
+      },
+      buffer: vec![],
+    }
+  }
+
+  /// The records completed by a chunk of the body.
+  pub(crate) fn push(&mut self, chunk: &[u8]) -> Vec<Vec<u8>> {
+    let mut records = vec![];
+    for byte in chunk {
+      if *byte == self.separator {
+        records.extend(self.take());
+      } else {
+        self.buffer.push(*byte);
+      }
+    }
+    records
+  }
+
+  /// The last record, at the end of the body.
+  pub(crate) fn finish(&mut self) -> Option<Vec<u8>> {
+    self.take()
+  }
+
+  /// The current record, without the blank records.
+  fn take(&mut self) -> Option<Vec<u8>> {
+    let record = std::mem::take(&mut self.buffer);
+    if record.iter().all(|byte| byte.is_ascii_whitespace()) {
+      None
+    } else {
+      Some(record)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{Records, ResponseFormat};
+  use hyper::{body::Bytes, header::CONTENT_TYPE, HeaderMap};
+  use serde::Deserialize;
+
+  fn headers(content_type: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, content_type.parse().unwrap());
+    headers
+  }
+
+  #[derive(Debug, Deserialize, PartialEq)]
+  struct Post {
+    id: u32,
+    title: String,
+  }
+
+  #[test]
+  fn test_from_headers() {
+    let format = |content_type| ResponseFormat::from_headers(&headers(content_type));
+    assert_eq!(
+      format("application/json; charset=UTF-8"),
+      Some(ResponseFormat::Json)
+    );
+    assert_eq!(
+      format("application/problem+json"),
+      Some(ResponseFormat::Json)
+    );
+    assert_eq!(format("text/xml"), Some(ResponseFormat::Xml));
+    assert_eq!(format("application/atom+xml"), Some(ResponseFormat::Xml));
+    assert_eq!(format("text/plain"), Some(ResponseFormat::Text));
+    assert_eq!(
+      format("application/x-www-form-urlencoded"),
+      Some(ResponseFormat::Form)
+    );
+    assert_eq!(format("application/x-ndjson"), Some(ResponseFormat::Ndjson));
+    assert_eq!(
+      format("application/json-seq"),
+      Some(ResponseFormat::JsonSeq)
+    );
+    assert_eq!(format("application/octet-stream"), None);
+    assert_eq!(ResponseFormat::from_headers(&HeaderMap::new()), None);
+  }
+
+  #[test]
+  fn test_decode() {
+    let post = Post {
+      id: 1,
+      title: "Hello world".to_string(),
+    };
+    assert_eq!(
+      ResponseFormat::Json
+        .decode::<Post>(&Bytes::from(r#"{"id":1,"title":"Hello world"}"#))
+        .unwrap(),
+      post
+    );
+    assert_eq!(
+      ResponseFormat::Form
+        .decode::<Post>(&Bytes::from("id=1&title=Hello+world"))
+        .unwrap(),
+      post
+    );
+    assert_eq!(
+      ResponseFormat::Text
+        .decode::<String>(&Bytes::from("pong\n"))
+        .unwrap(),
+      "pong\n"
+    );
+    assert!(ResponseFormat::Text
+      .decode::<Post>(&Bytes::from("pong"))
+      .is_err());
+    #[cfg(feature = "xml")]
+    assert_eq!(
+      ResponseFormat::Xml
+        .decode::<Post>(&Bytes::from(
+          "<post><id>1</id><title>Hello world</title></post>"
+        ))
+        .unwrap(),
+      post
+    );
+  }
+
+  #[test]
+  fn test_records() {
+    let mut records = Records::new(ResponseFormat::Ndjson);
+    assert_eq!(
+      records.push(b"{\"id\":1}\n{\"id\""),
+      vec![b"{\"id\":1}".to_vec()]
+    );
+    assert_eq!(
+      records.push(b":2}\n\n{\"id\":3}"),
+      vec![b"{\"id\":2}".to_vec()]
+    );
+    assert_eq!(records.finish(), Some(b"{\"id\":3}".to_vec()));
+    assert_eq!(records.finish(), None);
+
+    let mut records = Records::new(ResponseFormat::JsonSeq);
+    assert_eq!(
+      records.push(b"\x1e{\"id\":\n1}\n\x1e"),
+      vec![b"{\"id\":\n1}\n".to_vec()]
+    );
+    assert_eq!(records.finish(), None);
+
+    let posts: Vec<Post> = ResponseFormat::JsonSeq
+      .decode(&Bytes::from(
+        "\x1e{\"id\":1,\"title\":\"a\"}\n\x1e{\"id\":2,\"title\":\"b\"}\n",
+      ))
+      .unwrap();
+    assert_eq!(posts.len(), 2);
+    let posts: Vec<Post> = ResponseFormat::Ndjson
+      .decode(&Bytes::from("{\"id\":1,\"title\":\"a\"}\n"))
+      .unwrap();
+    assert_eq!(posts[0].title, "a");
+  }
+}
@@ -0,0 +1,330 @@
+//! Resumable downloads of the `stream` endpoints.
+//!
+//! The response is written in a `.part` file next to the output file. It's renamed when
+//! the download is complete. With `--continue`, an interrupted download is resumed with a
+//! `Range` request. The `ETag` or the `Last-Modified` date of the response is kept in a
+//! `.part.validator` file and sent in `If-Range`: the download restarts if the file changed
+//! on the server. An output file that already exists is resumed too, but it's not modified
+//! until the new download is complete.
+//!
+//! When the output is a directory, the file is named from the `Content-Disposition` header
+//! of the response. This response is the download: it's not resumed. The SHA-256 checksum of the file is verified while it's downloaded.
+
+use crate::error::ApiError;
+use base64::{engine::general_purpose, Engine};
+use hyper::{
+  header::{CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, ETAG, LAST_MODIFIED},
+  HeaderMap,
+};
+use sha2::{Digest, Sha256};
+use std::{
+  io,
+  path::{Path, PathBuf},
+};
+use tokio::{fs::File, io::AsyncReadExt};
+
+/// The expected checksum of a download.
+pub(crate) struct Checksum {
+  /// SHA-256 in hexadecimal.
+  pub(crate) sha256: String,
+  /// Where the checksum comes from.
+  pub(crate) origin: String,
+}
+
+impl Checksum {
+  pub(crate) fn verify(&self, hasher: Sha256) -> Result<(), ApiError> {
+    let actual = hex(&hasher.finalize());
+    if actual.eq_ignore_ascii_case(self.sha256.trim()) {
+      Ok(())
+    } else {
+      Err(ApiError::ChecksumMismatch {
+        expected: self.sha256.clone(),
+        actual,
+        origin: self.origin.clone(),
+      })
+    }
+  }
+}
+
+/// The SHA-256 checksum of the `name` header, in hexadecimal.
+///
+/// The checksum is in hexadecimal or in base64. A `Digest` or `Repr-Digest` header
+/// contains a list of digests: the `sha-256` one is used.
+pub(crate) fn header_checksum(headers: &HeaderMap, name: &str) -> Option<String> {
+  let value = headers.get(name)?.to_str().ok()?.trim();
+  let value = match value
+    .split(',')
+    .map(str::trim)
+    .find_map(|digest| digest.strip_prefix("sha-256="))
+  {
+    Some(digest) => digest.trim_matches(':'),
+    None => value,
+  };
+  if value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit()) {
+    return Some(value.to_ascii_lowercase());
+  }
+  general_purpose::STANDARD
+    .decode(value)
+    .ok()
+    .filter(|digest| digest.len() == 32)
+    .map(|digest| hex(&digest))
+}
+
+/// Add the content of a file to the checksum.
+pub(crate) async fn hash_file(path: &Path, hasher: &mut Sha256) -> io::Result<()> {
+  let mut file = File::open(path).await?;
+  let mut buffer = vec![0; 64 * 1024];
+  loop {
+    let read = file.read(&mut buffer).await?;
+    if read == 0 {
+      return Ok(());
+    }
+    hasher.update(&buffer[..read]);
+  }
+}
+
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// The file name of the `Content-Disposition` header.
+pub(crate) fn disposition_filename(headers: &HeaderMap) -> Option<String> {
+  let value = headers.get(CONTENT_DISPOSITION)?.to_str().ok()?;
+  let mut filename = None;
+  for param in value.split(';').skip(1) {
+    let Some((key, value)) = param.split_once('=') else {
+      continue;
+    };
+    match key.trim().to_ascii_lowercase().as_str() {
+      // RFC 5987: `UTF-8'lang'percent-encoded`. It has priority.
+      "filename*" => {
+        if let Some(encoded) = value.trim().splitn(3, '\'').nth(2) {
+          return sanitize(&percent_decode(encoded));
+        }
+      }
+      "filename" => filename = Some(value.trim().trim_matches('"').to_string()),
+      _ => {}
+    }
+  }
+  sanitize(&filename?)
+}
+
+/// The last segment of the path of the URL.
+pub(crate) fn url_filename(uri: &str) -> Option<String> {
+  let path = uri.split(['?', '#']).next()?;
+  let path = path.split_once("://").map_or(path, |(_, rest)| {
+    rest.split_once('/').map_or("", |(_, path)| path)
+  });
+  sanitize(&percent_decode(path.rsplit('/').next()?))
+}
+
+/// Keep the file name only: the server can't choose the directory.
+fn sanitize(name: &str) -> Option<String> {
+  let name = name.replace('\\', "/");
+  let name = name.rsplit('/').next()?.trim();
+  if name.is_empty() || name == "." || name == ".." {
+    None
+  } else {
+    Some(name.to_string())
+  }
+}
+
+fn percent_decode(value: &str) -> String {
+  let bytes = value.as_bytes();
+  let mut decoded = Vec::with_capacity(bytes.len());
+  let mut i = 0;
+  while i < bytes.len() {
+    let byte = match (bytes[i], bytes.get(i + 1..i + 3)) {
+      (b'%', Some(code)) => std::str::from_utf8(code)
+        .ok()
+        .and_then(|code| u8::from_str_radix(code, 16).ok()),
+      _ => None,
+    };
+    match byte {
+      Some(byte) => {
+        decoded.push(byte);
+        i += 3;
+      }
+      None => {
+        decoded.push(bytes[i]);
+        i += 1;
+      }
+    }
+  }
+  String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The file of an unfinished download.
+pub(crate) fn partial_path(path: &Path) -> PathBuf {
+  let mut name = path.file_name().unwrap_or_default().to_os_string();
+  name.push(".part");
+  path.with_file_name(name)
+}
+
+/// The file of the validator of an unfinished download.
+pub(crate) fn validator_path(path: &Path) -> PathBuf {
+  let mut name = path.file_name().unwrap_or_default().to_os_string();
+  name.push(".part.validator");
+  path.with_file_name(name)
+}
+
+/// The validator of a response for `If-Range`: its strong `ETag`, or its `Last-Modified` date.
+pub(crate) fn range_validator(headers: &HeaderMap) -> Option<String> {
+  let header = |name| {
+    headers
+      .get(name)
+      .and_then(|value| value.to_str().ok())
+      .map(str::to_string)
+  };
+  header(ETAG)
+    .filter(|etag| !etag.starts_with("W/"))
+    .or_else(|| header(LAST_MODIFIED))
+}
+
+pub(crate) fn content_length(headers: &HeaderMap) -> Option<u64> {
+  headers.get(CONTENT_LENGTH)?.to_str().ok()?.parse().ok()
+}
+
+/// The `Content-Range` of a response: the first byte of the response, or `None` for an
+/// unsatisfied range, and the size of the file if it's known.
+pub(crate) fn content_range(headers: &HeaderMap) -> Option<(Option<u64>, Option<u64>)> {
+  let range = headers.get(CONTENT_RANGE)?.to_str().ok()?;
+  let (range, total) = range.strip_prefix("bytes ")?.split_once('/')?;
+  let total = match total.trim() {
+    "*" => None,
+    total => Some(total.parse().ok()?),
+  };
+  let start = match range.trim() {
+    "*" => None,
+    range => Some(range.split_once('-')?.0.parse().ok()?),
+  };
+  Some((start, total))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{
+    content_range, disposition_filename, header_checksum, partial_path, range_validator,
+    url_filename, validator_path, Checksum,
+  };
+  use hyper::{
+    header::{CONTENT_DISPOSITION, CONTENT_RANGE, ETAG, LAST_MODIFIED},
+    HeaderMap,
+  };
+  use sha2::{Digest, Sha256};
+  use std::path::Path;
+
+  #[test]
+  fn test_partial_path() {
+    assert_eq!(
+      partial_path(Path::new("out/archive.tar.gz")),
+      Path::new("out/archive.tar.gz.part")
+    );
+  }
+
+  #[test]
+  fn test_range_validator() {
+    assert_eq!(
+      validator_path(Path::new("out/archive.tar.gz")),
+      Path::new("out/archive.tar.gz.part.validator")
+    );
+    let mut headers = HeaderMap::new();
+    assert_eq!(range_validator(&headers), None);
+    headers.insert(
+      LAST_MODIFIED,
+      "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+    );
+    headers.insert(ETAG, "W/\"v1\"".parse().unwrap());
+    // The weak ETags can't be used in If-Range.
+    assert_eq!(
+      range_validator(&headers).as_deref(),
+      Some("Wed, 21 Oct 2015 07:28:00 GMT")
+    );
+    headers.insert(ETAG, "\"v1\"".parse().unwrap());
+    assert_eq!(range_validator(&headers).as_deref(), Some("\"v1\""));
+  }
+
+  #[test]
+  fn test_content_range() {
+    let range = |value: &str| {
+      let mut headers = HeaderMap::new();
+      headers.insert(CONTENT_RANGE, value.parse().unwrap());
+      content_range(&headers)
+    };
+    assert_eq!(range("bytes 100-999/1000"), Some((Some(100), Some(1000))));
+    assert_eq!(range("bytes 100-999/*"), Some((Some(100), None)));
+    assert_eq!(range("bytes */1000"), Some((None, Some(1000))));
+    assert_eq!(range("items 0-9/10"), None);
+    assert_eq!(content_range(&HeaderMap::new()), None);
+  }
+
+  #[test]
+  fn test_filename() {
+    let disposition = |value: &str| {
+      let mut headers = HeaderMap::new();
+      headers.insert(CONTENT_DISPOSITION, value.parse().unwrap());
+      disposition_filename(&headers)
+    };
+    assert_eq!(
+      disposition("attachment; filename=\"report.pdf\""),
+      Some("report.pdf".to_string())
+    );
+    assert_eq!(
+      disposition("attachment; filename=\"report.pdf\"; filename*=UTF-8''r%C3%A9sum%C3%A9.pdf"),
+      Some("résumé.pdf".to_string())
+    );
+    assert_eq!(
+      disposition("attachment; filename=\"../../etc/passwd\""),
+      Some("passwd".to_string())
+    );
+    assert_eq!(disposition("attachment; filename=\"..\""), None);
+    assert_eq!(disposition("inline"), None);
+    assert_eq!(
+      url_filename("https://api.local/jobs/1/artifacts/build%201.zip?ref=main"),
+      Some("build 1.zip".to_string())
+    );
+    assert_eq!(url_filename("https://api.local/"), None);
+  }
+
+  #[test]
+  fn test_checksum() {
+    let sha256 = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+    let checksum = |name: &'static str, value: &str| {
+      let mut headers = HeaderMap::new();
+      headers.insert(name, value.parse().unwrap());
+      header_checksum(&headers, name)
+    };
+    assert_eq!(
+      checksum("x-checksum-sha256", &sha256.to_uppercase()).as_deref(),
+      Some(sha256)
+    );
+    assert_eq!(
+      checksum(
+        "digest",
+        "md5=XrY7u+Ae7tCTyyK7j1rNww==, sha-256=uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek="
+      )
+      .as_deref(),
+      Some(sha256)
+    );
+    assert_eq!(
+      checksum(
+        "repr-digest",
+        "sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:"
+      )
+      .as_deref(),
+      Some(sha256)
+    );
+    assert_eq!(checksum("x-checksum-sha256", "abc"), None);
+
+    let expected = Checksum {
+      sha256: sha256.to_string(),
+      origin: "--sha256".to_string(),
+    };
+    let mut hasher = Sha256::new();
+    hasher.update("hello world");
+    assert!(expected.verify(hasher).is_ok());
+    let mut hasher = Sha256::new();
+    hasher.update("hello");
+    assert!(expected.verify(hasher).is_err());
+  }
+}
@@ -4,7 +4,7 @@ use hyper::StatusCode;
 use miette::Diagnostic;
 use owo_colors::OwoColorize;
 use regex::Regex;
-use std::env;
+use std::{env, time::Duration};
 use thiserror::Error;
 
 #[derive(Debug, Error, Diagnostic)]
@@ -15,6 +15,32 @@ pub enum ApiError {
   #[error("HTTP Status: {status:}")]
   #[diagnostic(code(http::unauthorized), help("{help:}"))]
   HTTPUnauthorizedError { status: StatusCode, help: String },
+  #[error("No response after {timeout:?}")]
+  #[diagnostic(
+    code(http::timeout),
+    help("Increase the timeout with '--timeout <seconds>'")
+  )]
+  TimeoutError { timeout: Duration },
+  #[error("Download interrupted: {reason}")]
+  #[diagnostic(
+    code(http::download_interrupted),
+    help("Run the command again with '--continue' to resume the download")
+  )]
+  DownloadInterrupted { reason: String },
+  #[error("Checksum mismatch: expected {expected} ({origin}), got {actual}")]
+  #[diagnostic(
+    code(http::checksum_mismatch),
+    help("The download is corrupted, or the expected checksum is wrong")
+  )]
+  ChecksumMismatch {
+    expected: String,
+    actual: String,
+    origin: String,
+  },
+  /// The request has been printed instead of being sent (`--dry-run`). It stops the command.
+  #[error("Dry run: the request is not sent")]
+  #[diagnostic(code(http::dry_run))]
+  DryRun,
 
   #[error("Error reading a JSON document")]
   #[diagnostic(code("deserialization error"), help("Check your JSON document"))]
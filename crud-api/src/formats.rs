@@ -8,11 +8,20 @@ use std::{
   str::FromStr,
 };
 
-#[cfg(any(feature = "json", feature = "toml", feature = "yaml", feature = "csv"))]
+#[cfg(any(
+  feature = "json",
+  feature = "toml",
+  feature = "yaml",
+  feature = "csv",
+  feature = "template"
+))]
 #[derive(Clone)]
 pub enum OutputFormat {
   #[cfg(feature = "json")]
   Json,
+  /// One compact JSON value per line.
+  #[cfg(feature = "json")]
+  Ndjson,
   #[cfg(feature = "toml")]
   Toml,
   #[cfg(feature = "yaml")]
@@ -21,9 +30,24 @@ pub enum OutputFormat {
   Csv,
   #[cfg(feature = "csv")]
   Tsv,
+  /// The table as a Markdown table.
+  Markdown,
+  /// The table as an HTML table.
+  Html,
+  /// The table in ASCII boxes, without colors.
+  AsciiTable,
+  /// Each result is rendered with this Handlebars template (`--output-template`).
+  #[cfg(feature = "template")]
+  Template(String),
 }
 
-#[cfg(any(feature = "json", feature = "toml", feature = "yaml", feature = "csv"))]
+#[cfg(any(
+  feature = "json",
+  feature = "toml",
+  feature = "yaml",
+  feature = "csv",
+  feature = "template"
+))]
 impl FromStr for OutputFormat {
   type Err = String;
 
@@ -31,6 +55,8 @@ impl FromStr for OutputFormat {
     match s {
       #[cfg(feature = "json")]
       "json" => Ok(OutputFormat::Json),
+      #[cfg(feature = "json")]
+      "ndjson" => Ok(OutputFormat::Ndjson),
       #[cfg(feature = "toml")]
       "toml" => Ok(OutputFormat::Toml),
       #[cfg(feature = "yaml")]
@@ -39,12 +65,21 @@ impl FromStr for OutputFormat {
       "csv" => Ok(OutputFormat::Csv),
       #[cfg(feature = "csv")]
       "tsv" => Ok(OutputFormat::Tsv),
+      "markdown" => Ok(OutputFormat::Markdown),
+      "html" => Ok(OutputFormat::Html),
+      "asciitable" => Ok(OutputFormat::AsciiTable),
       _ => Err(format!("Invalid variant: {s}")),
     }
   }
 }
 
-#[cfg(any(feature = "json", feature = "toml", feature = "yaml", feature = "csv"))]
+#[cfg(any(
+  feature = "json",
+  feature = "toml",
+  feature = "yaml",
+  feature = "csv",
+  feature = "template"
+))]
 pub fn clap_output_format_decl(
   command: Command,
   formats: Option<&[&'static str]>,
@@ -66,6 +101,8 @@ pub fn clap_output_format_decl(
         &[
           #[cfg(feature = "json")]
           "json",
+          #[cfg(feature = "json")]
+          "ndjson",
           #[cfg(feature = "toml")]
           "toml",
           #[cfg(feature = "yaml")]
@@ -74,6 +111,9 @@ pub fn clap_output_format_decl(
           "csv",
           #[cfg(feature = "csv")]
           "tsv",
+          "markdown",
+          "html",
+          "asciitable",
         ]
       })),
   )
@@ -82,7 +122,8 @@ pub fn clap_output_format_decl(
   not(feature = "json"),
   not(feature = "toml"),
   not(feature = "yaml"),
-  not(feature = "csv")
+  not(feature = "csv"),
+  not(feature = "template")
 ))]
 pub fn clap_output_format_decl(
   command: Command,
@@ -94,10 +135,85 @@ pub fn clap_output_format_decl(
   command
 }
 
-pub fn clap_match_output_format(argmatches: &ArgMatches) -> Option<OutputFormat> {
-  argmatches
-    .get_one::<String>("output_format")
-    .map(|o| OutputFormat::from_str(o).unwrap())
+#[cfg(any(
+  feature = "json",
+  feature = "toml",
+  feature = "yaml",
+  feature = "csv",
+  feature = "template"
+))]
+impl OutputFormat {
+  /// The results are printed one by one: they can be printed while they're received.
+  pub(crate) fn is_line_oriented(&self) -> bool {
+    match self {
+      #[cfg(feature = "json")]
+      OutputFormat::Ndjson => true,
+      #[cfg(feature = "csv")]
+      OutputFormat::Csv | OutputFormat::Tsv => true,
+      #[cfg(feature = "template")]
+      OutputFormat::Template(_) => true,
+      #[allow(unreachable_patterns)]
+      _ => false,
+    }
+  }
+}
+
+#[cfg(feature = "template")]
+pub fn clap_output_template_decl(command: Command, template: Arg, template_file: Arg) -> Command {
+  command
+    .arg(template.conflicts_with_all(["output_format", "output_template_file"]))
+    .arg(
+      template_file
+        .value_hint(clap::ValueHint::FilePath)
+        .conflicts_with("output_format"),
+    )
+}
+#[cfg(not(feature = "template"))]
+pub fn clap_output_template_decl(command: Command, _template: Arg, _template_file: Arg) -> Command {
+  command
+}
+
+/// Format a date like the `date` format of the table fields and the `date` helper of the
+/// templates.
+pub fn format_date(value: &str, format: &str) -> Result<String> {
+  Ok(
+    value
+      .parse::<chrono::DateTime<chrono::Utc>>()
+      .into_diagnostic()
+      .wrap_err("Can't parse Date")?
+      .format(format)
+      .to_string(),
+  )
+}
+
+pub fn clap_match_output_format(argmatches: &ArgMatches) -> Result<Option<OutputFormat>> {
+  #[cfg(feature = "template")]
+  if let Some(template) = argmatches
+    .try_get_one::<String>("output_template")
+    .ok()
+    .flatten()
+  {
+    return Ok(Some(OutputFormat::Template(crate::template::unescape(
+      template,
+    ))));
+  }
+  #[cfg(feature = "template")]
+  if let Some(filename) = argmatches
+    .try_get_one::<String>("output_template_file")
+    .ok()
+    .flatten()
+  {
+    return Ok(Some(OutputFormat::Template(
+      std::fs::read_to_string(filename)
+        .into_diagnostic()
+        .with_context(|| format!("Can't read the template file '{filename}'"))?,
+    )));
+  }
+  Ok(
+    argmatches
+      .get_one::<String>("output_format")
+      .map(|o| OutputFormat::from_str(o).unwrap()),
+  )
 }
 
 pub fn clap_match_input_from_file<T: DeserializeOwned>(argmatches: &ArgMatches) -> Result<Option<T>> {
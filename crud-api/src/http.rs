@@ -1,40 +1,71 @@
-use crate::{error::ApiError, Query};
+pub use crate::client::HttpClient;
+use crate::{
+  body::{Body, PayloadEncoding},
+  cache::{Cache, CacheEntry},
+  debug::{redact_body, Secrets},
+  decode::Records,
+  decode::ResponseFormat,
+  download::{
+    content_length, content_range, disposition_filename, hash_file, header_checksum, partial_path,
+    range_validator, url_filename, validator_path, Checksum,
+  },
+  error::ApiError,
+  pagination::{PageArgs, Pagination, MAX_PAGES},
+  progress::progress_bar,
+  retry::RetryPolicy,
+  DummyTryFrom, Query,
+};
 use async_trait::async_trait;
 use crud_auth::CrudAuth;
-use http::uri::Authority;
 use http_body_util::BodyExt;
-use hyper::client::conn::http1::SendRequest;
-use hyper::{body::Buf, Method, Request, StatusCode, Uri};
-use hyper_util::rt::TokioIo;
-use indicatif::{ProgressBar, ProgressStyle};
-use log::trace;
+pub use hyper::StatusCode;
+use hyper::{
+  body::{Buf, Bytes, Incoming},
+  header::{HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, IF_RANGE, RANGE},
+  HeaderMap, Method, Request,
+};
+use log::{debug, trace, warn};
 use miette::{miette, IntoDiagnostic, Result, WrapErr};
 use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
-  collections::HashMap, fmt::Debug, io::Read, marker::PhantomData, path::Path, sync::Arc,
-  time::Duration,
+  collections::{HashMap, HashSet},
+  fmt::Debug,
+  io::Read,
+  marker::PhantomData,
+  path::{Path, PathBuf, MAIN_SEPARATOR},
+  time::{Duration, Instant},
 };
 use tokio::{
-  fs::{create_dir_all, File},
+  fs::{
+    copy, create_dir_all, metadata, read_to_string, remove_file, rename, write, File, OpenOptions,
+  },
   io::{stdout, AsyncWriteExt},
-  net::TcpStream,
-};
-use tokio_rustls::{
-  rustls::{ClientConfig, RootCertStore},
-  TlsConnector,
+  time::{sleep, timeout},
 };
 
 pub struct HTTPApi<'a> {
+  client: &'a HttpClient,
   uri: String,
   method: Method,
   ok_status: StatusCode,
   ko_status: &'a HashMap<StatusCode, String>,
   auth: Option<&'a (dyn CrudAuth + Send + Sync)>,
   headers: &'a Vec<Header<'a>>,
+  pagination: Option<(Pagination<'a>, PageArgs)>,
+  retry: RetryPolicy,
+  cache_ttl: Option<u64>,
+  response_format: Option<ResponseFormat>,
+  payload_encoding: PayloadEncoding<'a>,
+  resume: bool,
+  sha256: Option<String>,
+  checksum_header: Option<String>,
+  secret_fields: &'a [&'a str],
 }
 
 impl<'a> HTTPApi<'a> {
   pub fn new(
+    client: &'a HttpClient,
     uri: String,
     method: Method,
     ok_status: StatusCode,
@@ -43,245 +74,655 @@ impl<'a> HTTPApi<'a> {
     headers: &'a Vec<Header<'a>>,
   ) -> HTTPApi<'a> {
     HTTPApi {
+      client,
       uri,
       method,
       ok_status,
       ko_status,
       auth,
       headers,
+      pagination: None,
+      retry: RetryPolicy::default(),
+      cache_ttl: None,
+      response_format: None,
+      payload_encoding: PayloadEncoding::default(),
+      resume: false,
+      sha256: None,
+      checksum_header: None,
+      secret_fields: &[],
     }
   }
-}
 
-#[derive(Clone)]
-pub struct Header<'a> {
-  pub key: &'a str,
-  pub value: &'a str,
-}
+  /// Follow the pages of the results.
+  pub fn paginate(mut self, pagination: Pagination<'a>, args: PageArgs) -> HTTPApi<'a> {
+    self.pagination = Some((pagination, args));
+    self
+  }
 
-async fn connect(uri: &str) -> Result<(SendRequest<String>, Authority)> {
-  let url: Uri = uri
-    .parse()
-    .into_diagnostic()
-    .context("Error during URL parsing")?;
-
-  let host = url.host().expect("uri has no host");
-  let port = url.port_u16().unwrap_or({
-    if let Some(scheme) = url.scheme() {
-      trace!("scheme {:?}", scheme);
-      if scheme == &http::uri::Scheme::HTTPS {
-        443
-      } else {
-        80
+  /// Timeout and retries of the endpoint.
+  pub fn retry_policy(mut self, policy: RetryPolicy) -> HTTPApi<'a> {
+    self.retry = policy;
+    self
+  }
+
+  /// Cache the responses for `ttl` seconds.
+  pub fn cache_ttl(mut self, ttl: u64) -> HTTPApi<'a> {
+    self.cache_ttl = Some(ttl);
+    self
+  }
+
+  /// Decode the responses with this format instead of their `Content-Type`.
+  pub fn response_format(mut self, format: ResponseFormat) -> HTTPApi<'a> {
+    self.response_format = Some(format);
+    self
+  }
+
+  /// Encode the payload as a form, a multipart form or a raw body instead of JSON.
+  pub fn payload_encoding(mut self, encoding: PayloadEncoding<'a>) -> HTTPApi<'a> {
+    self.payload_encoding = encoding;
+    self
+  }
+
+  /// Resume the download of the output file (`--continue`).
+  pub fn resume_download(mut self, resume: bool) -> HTTPApi<'a> {
+    self.resume = resume;
+    self
+  }
+
+  /// Verify the SHA-256 checksum of the download (`--sha256`), or the checksum sent by the
+  /// server in the `header` header (`--checksum-header`).
+  pub fn checksum(mut self, sha256: Option<String>, header: Option<String>) -> HTTPApi<'a> {
+    self.sha256 = sha256;
+    self.checksum_header = header;
+    self
+  }
+
+  /// Fields of the payload that are redacted from the printed requests (ie. a password).
+  pub fn secret_fields(mut self, fields: &'a [&'a str]) -> HTTPApi<'a> {
+    self.secret_fields = fields;
+    self
+  }
+
+  /// Format of a response: the format of the endpoint, or its `Content-Type`, or JSON.
+  fn format(&self, headers: &HeaderMap) -> ResponseFormat {
+    self
+      .response_format
+      .or_else(|| ResponseFormat::from_headers(headers))
+      .unwrap_or(ResponseFormat::Json)
+  }
+
+  /// The cache of the responses of this request, and their TTL.
+  fn cache(&self) -> Option<(&Cache, u64)> {
+    match self.client.cache() {
+      Some(cache) if self.method == Method::GET && self.client.dry_run().is_none() => {
+        cache.ttl(self.cache_ttl).map(|ttl| (cache, ttl))
       }
-    } else {
-      80
-    }
-  });
-  let addr = format!("{}:{}", host, port);
-
-  let mut root_cert_store = RootCertStore::empty();
-  root_cert_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
-  let config = ClientConfig::builder()
-    .with_root_certificates(root_cert_store)
-    .with_no_client_auth();
-  let connector = TlsConnector::from(Arc::new(config));
-
-  let domain = url.host().unwrap();
-  let domain = pki_types::ServerName::try_from(domain)
-    .into_diagnostic()?
-    .to_owned();
-
-  let stream = TcpStream::connect(addr).await.into_diagnostic()?;
-  let sender = if let Some(scheme) = url.scheme() {
-    if scheme == &http::uri::Scheme::HTTPS {
-      let io = TokioIo::new(connector.connect(domain, stream).await.into_diagnostic()?);
-      let (sender, conn) = hyper::client::conn::http1::handshake(io)
-        .await
-        .into_diagnostic()?;
-      tokio::task::spawn(async move {
-        if let Err(err) = conn.await {
-          println!("Connection failed: {:?}", err);
+      _ => None,
+    }
+  }
+
+  /// Send the request and read the response, or read it from the cache.
+  async fn fetch(&self, uri: &str, body: &Body) -> Result<(HeaderMap, Bytes)> {
+    let Some((cache, ttl)) = self.cache() else {
+      let response = self.send(uri, body, Condition::None).await?;
+      let headers = response.headers().clone();
+      return Ok((headers, self.read_body(uri, response).await?));
+    };
+
+    let key = self.cache_key(uri).await?;
+    let request = self.endpoint_headers()?;
+    let verbosity = self.client.verbosity();
+    let mut entry = cache.get(&key).filter(|entry| entry.matches(&request));
+    if let Some(entry) = entry.as_ref().filter(|entry| entry.is_fresh()) {
+      verbosity.note(&format!("cache: fresh response of {uri}"));
+      return Ok(entry.response());
+    }
+    let condition = entry.as_ref().map_or(Condition::None, Condition::Cached);
+    let response = self.send(uri, body, condition).await?;
+    if let (StatusCode::NOT_MODIFIED, Some(entry)) = (response.status(), entry.as_mut()) {
+      verbosity.note(&format!("cache: {uri} not modified"));
+      entry.renew(ttl);
+      store(cache, &key, entry);
+      return Ok(entry.response());
+    }
+    let headers = response.headers().clone();
+    let body = self.read_body(uri, response).await?;
+    match CacheEntry::new(self.method.as_str(), uri, &request, &headers, &body, ttl) {
+      Some(entry) => store(cache, &key, &entry),
+      None => cache.remove(&key),
+    }
+    Ok((headers, body))
+  }
+
+  /// The cache key of the request: its method, its url and the identity of the user.
+  ///
+  /// Without [CrudAuth::identity], the user is identified by the credentials added to the
+  /// request by the authentication.
+  async fn cache_key(&self, uri: &str) -> Result<String> {
+    let identity = match self.auth {
+      Some(auth) => match auth.identity() {
+        Some(identity) => Some(identity),
+        None => {
+          auth.prepare().await?;
+          let mut req = Request::builder()
+            .uri(uri)
+            .body(())
+            .into_diagnostic()
+            .with_context(|| format!("URL: {uri}"))?;
+          auth
+            .authorize(&mut req)
+            .await
+            .context("Can't authenticate the request")?;
+          let mut credentials = req.uri().query().unwrap_or_default().to_string();
+          for (name, value) in req.headers() {
+            credentials.push_str(&format!("\n{name}: {}", value.to_str().unwrap_or_default()));
+          }
+          Some(credentials)
         }
-      });
-      sender
+      },
+      None => None,
+    };
+    Ok(Cache::key(self.method.as_str(), uri, identity.as_deref()))
+  }
+
+  /// Send the request and returns the response if its status is the expected status.
+  ///
+  /// The request is retried on the transport errors, timeouts and the statuses of the
+  /// retry policy. On `401 Unauthorized`, it's sent again once if the authentication
+  /// refreshes its credentials.
+  ///
+  /// The statuses of the `condition` are expected too. A body read from stdin can't be sent
+  /// twice: the request is not retried.
+  ///
+  /// With `--dry-run`, the request is printed and [ApiError::DryRun] is returned.
+  async fn send(
+    &self,
+    uri: &str,
+    body: &Body,
+    condition: Condition<'_>,
+  ) -> Result<hyper::Response<Incoming>> {
+    // The dry run prints the request with the credentials that would be sent.
+    if let Some(auth) = self.auth {
+      auth.prepare().await?;
+    }
+    if let Some(dry_run) = self.client.dry_run() {
+      let (req, secrets) = self.request(uri, body, condition).await?;
+      println!("{}", dry_run.render(&req, &body.to_string(), &secrets));
+      return Err(ApiError::DryRun.into());
+    }
+
+    let policy = self.client.retry_policy(&self.retry);
+    let retries = if body.is_replayable() {
+      policy.retries(&self.method)
     } else {
-      let io = TokioIo::new(stream);
-      let (sender, conn) = hyper::client::conn::http1::handshake(io)
-        .await
-        .into_diagnostic()?;
-      tokio::task::spawn(async move {
-        if let Err(err) = conn.await {
-          println!("Connection failed: {:?}", err);
+      0
+    };
+    let mut attempt = 0;
+    let mut refreshed = !body.is_replayable();
+    loop {
+      attempt += 1;
+      let (req, secrets) = self.request(uri, body, condition).await?;
+      let verbosity = self.client.verbosity();
+      verbosity.request(&req, &body.to_string(), &secrets);
+      let request_body = body.request_body().await?;
+      let req = req.map(|()| request_body);
+      let start = Instant::now();
+      let (error, headers) = match timeout(policy.timeout(), self.client.request(req)).await {
+        Ok(Ok(response)) => {
+          trace!("Response status: {}", response.status());
+          verbosity.response(&response, start.elapsed());
+          if response.status() == self.ok_status || condition.expects(response.status()) {
+            return Ok(response);
+          } else if response.status() == StatusCode::UNAUTHORIZED && !refreshed {
+            if let Some(auth) = self.auth {
+              refreshed = true;
+              if auth.refresh().await? {
+                debug!("{} {uri}: credentials refreshed", self.method);
+                verbosity.note("unauthorized: credentials refreshed, retry");
+                // The refresh doesn't count as a retry.
+                attempt -= 1;
+                continue;
+              }
+            }
+            return Err(self.error(uri, response).await);
+          } else if attempt > retries || !policy.should_retry(response.status()) {
+            return Err(self.error(uri, response).await);
+          }
+          (
+            response.status().to_string(),
+            Some(response.headers().clone()),
+          )
         }
-      });
-      sender
+        Ok(Err(e)) => {
+          if attempt > retries {
+            return Err(
+              e.wrap_err(format!(
+                "Payload: {}",
+                redact_body(&body.to_string(), false, &secrets.fields)
+              ))
+              .wrap_err(format!("URL: {uri}"))
+              .wrap_err("HTTP call fail"),
+            );
+          }
+          (e.to_string(), None)
+        }
+        Err(_) => {
+          if attempt > retries {
+            return Err(self.timeout_error(uri, policy.timeout()));
+          }
+          ("timeout".to_string(), None)
+        }
+      };
+      let delay = policy.delay(attempt, headers.as_ref());
+      debug!("{} {uri} failed ({error}). Retry in {delay:?}", self.method);
+      verbosity.note(&format!("{error}: retry in {delay:.1?}"));
+      sleep(delay).await;
     }
-  } else {
-    let io = TokioIo::new(stream);
-    let (sender, conn) = hyper::client::conn::http1::handshake(io)
-      .await
-      .into_diagnostic()?;
-    tokio::task::spawn(async move {
-      if let Err(err) = conn.await {
-        println!("Connection failed: {:?}", err);
-      }
-    });
-    sender
-  };
-  // The authority of our URL will be the hostname of the httpbin remote
-  let authority = url.authority().unwrap().clone();
+  }
 
-  Ok((sender, authority))
-}
+  /// Build the request, without its body: the files and stdin are only opened when the
+  /// request is sent. Returns it with the parts set by the authentication.
+  async fn request(
+    &self,
+    uri: &str,
+    body: &Body,
+    condition: Condition<'_>,
+  ) -> Result<(Request<()>, Secrets)> {
+    trace!("Request {} to {}", self.method, uri);
+    let mut req = Request::builder()
+      .method(&self.method)
+      .uri(uri)
+      .body(())
+      .into_diagnostic()
+      .with_context(|| format!("URL: {uri}"))
+      .context("HTTP request preparation failed.")?;
+    let query = req.uri().query().map(str::to_string);
+    if let Some(auth) = self.auth {
+      auth
+        .authorize(&mut req)
+        .await
+        .context("Can't authenticate the request")?;
+    }
+    let mut secrets = Secrets::added(query.as_deref(), &req);
+    secrets.fields = self
+      .secret_fields
+      .iter()
+      .map(|field| field.to_string())
+      .collect();
 
-#[async_trait]
-impl Query for HTTPApi<'_> {
-  async fn query<P, T, R, Q>(
+    let headers = req.headers_mut();
+    for (name, value) in &self.endpoint_headers()? {
+      headers.append(name, value.clone());
+    }
+    headers.insert(
+      CONTENT_TYPE,
+      HeaderValue::from_str(body.content_type()).into_diagnostic()?,
+    );
+    if let Some(len) = body.stream_len() {
+      headers.insert(CONTENT_LENGTH, HeaderValue::from(len));
+    }
+    for (name, value) in condition.headers() {
+      headers.insert(name, HeaderValue::from_str(&value).into_diagnostic()?);
+    }
+    Ok((req, secrets))
+  }
+
+  /// The headers of the endpoint.
+  fn endpoint_headers(&self) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for Header { key, value } in self.headers.iter() {
+      headers.append(
+        HeaderName::from_bytes(key.as_bytes()).into_diagnostic()?,
+        HeaderValue::from_str(value).into_diagnostic()?,
+      );
+    }
+    Ok(headers)
+  }
+
+  /// Read the whole body of the response.
+  async fn read_body(&self, uri: &str, response: hyper::Response<Incoming>) -> Result<Bytes> {
+    let policy = self.client.retry_policy(&self.retry);
+    let body = timeout(policy.timeout(), response.collect())
+      .await
+      .map_err(|_| self.timeout_error(uri, policy.timeout()))?
+      .into_diagnostic()
+      .with_context(|| format!("URL: {uri}"))
+      .context("Can't read the HTTP response")?
+      .to_bytes();
+    self.client.verbosity().body(&body);
+    Ok(body)
+  }
+
+  /// Query the results of a `multiple_results` endpoint. The results of an NDJSON or a JSON
+  /// text sequence response are passed to `row` while they're received. The other responses,
+  /// the pages and the cached responses are decoded at once.
+  pub async fn query_rows<P, R, Q>(
     &self,
     payload: Option<P>,
     query_args: Option<Q>,
-    transform_from_type: Option<PhantomData<T>>,
-  ) -> Result<R>
+    mut row: impl FnMut(R) -> Result<()> + Send,
+  ) -> Result<()>
   where
     P: Send + Serialize + Debug,
-    T: TryInto<R, Error = String> + DeserializeOwned + Send,
-    R: Send + DeserializeOwned + Debug + Default,
+    R: Send + Serialize + DeserializeOwned + Debug,
     Q: Send + Serialize + Debug,
   {
+    let sequence = self
+      .response_format
+      .is_none_or(|format| format.is_sequence());
+    if !sequence || self.pagination.is_some() || self.cache().is_some() {
+      let results: Vec<R> = self
+        .query(payload, query_args, None::<PhantomData<DummyTryFrom>>)
+        .await?;
+      return results.into_iter().try_for_each(row);
+    }
+
     let mut uri = self.uri.to_owned();
     if let Some(qa) = query_args {
       uri = format!("{}?{}", uri, serde_qs::to_string(&qa).unwrap());
     }
-    let (mut sender, authority) = connect(&uri).await?;
-
-    let req = Request::builder().method(&self.method).uri(&uri);
-    trace!("Request {} to {}", self.method, uri);
-    let req = if let Some(auth) = self.auth {
-      let (header_key, header_value) = auth.auth_header();
-      if !header_key.is_empty() {
-        req.header(header_key, header_value)
-      } else {
-        req
+    let body = Body::encode(&payload, &self.payload_encoding)?;
+    let mut response = self.send(&uri, &body, Condition::None).await?;
+    let format = self.format(response.headers());
+    if !format.is_sequence() {
+      let body = self.read_body(&uri, response).await?;
+      if !body.has_remaining() {
+        return Ok(());
       }
-    } else {
-      req
+      let results: Vec<R> = format.decode(&body)?;
+      return results.into_iter().try_for_each(row);
+    }
+
+    // The timeout applies between two chunks.
+    let idle_timeout = self.client.retry_policy(&self.retry).timeout();
+    let verbosity = self.client.verbosity();
+    let mut records = Records::new(format);
+    let mut decode = |record: Vec<u8>| -> Result<()> {
+      verbosity.body(&record);
+      row(
+        serde_json::from_slice(&record)
+          .into_diagnostic()
+          .context("Can't deserialize the response")?,
+      )
     };
-    let mut req = req;
-    for Header { key, value } in self.headers.iter() {
-      req = req.header(*key, *value)
+    while let Some(chunk) = timeout(idle_timeout, response.frame())
+      .await
+      .map_err(|_| self.timeout_error(&uri, idle_timeout))?
+    {
+      let frame = chunk
+        .into_diagnostic()
+        .with_context(|| format!("URL: {uri}"))
+        .context("Can't read the HTTP response")?;
+      if let Some(chunk) = frame.data_ref() {
+        records.push(chunk).into_iter().try_for_each(&mut decode)?;
+      }
     }
+    records.finish().into_iter().try_for_each(decode)
+  }
 
-    let req = req
-      .header("content-type", "application/json; charset=UTF-8")
-      .header(hyper::header::HOST, authority.as_str())
-      .body(match payload {
-        Some(ref payload) => {
-          let p = serde_json::to_string(&payload)
-            .into_diagnostic()
-            .context("Error during payload serialization")?;
-          trace!("Payload: {}", p);
-          p
-        }
-        None => String::new(),
-      })
+  /// Rename the downloaded file.
+  async fn complete(&self, part: &Path, path: &Path) -> Result<()> {
+    rename(part, path)
+      .await
       .into_diagnostic()
-      .with_context(|| format!("Payload: {payload:?}"))
-      .with_context(|| format!("URL: {uri}"))
-      .context("HTTP request preparation failed.")?;
+      .with_context(|| format!("Failed to rename {} to {}", part.display(), path.display()))
+  }
+
+  /// The expected checksum of a download.
+  fn expected_checksum(&self, headers: &HeaderMap) -> Result<Option<Checksum>> {
+    if let Some(sha256) = &self.sha256 {
+      return Ok(Some(Checksum {
+        sha256: sha256.clone(),
+        origin: "--sha256".to_string(),
+      }));
+    }
+    let Some(name) = &self.checksum_header else {
+      return Ok(None);
+    };
+    match header_checksum(headers, name) {
+      Some(sha256) => Ok(Some(Checksum {
+        sha256,
+        origin: format!("{name} header"),
+      })),
+      None => Err(miette!(
+        help = "The header should contain a SHA-256 checksum in hexadecimal or base64",
+        "No SHA-256 checksum in the {name} header of the response"
+      )),
+    }
+  }
+
+  /// Verify the checksum of a download. The partial file is removed when it doesn't match.
+  async fn verify(&self, checksum: Option<(Checksum, Sha256)>, part: Option<&Path>) -> Result<()> {
+    let Some((expected, hasher)) = checksum else {
+      return Ok(());
+    };
+    if let Err(error) = expected.verify(hasher) {
+      if let Some(part) = part {
+        remove_file(part)
+          .await
+          .into_diagnostic()
+          .with_context(|| format!("Failed to remove file: {}", part.display()))?;
+      }
+      return Err(error.into());
+    }
+    self.client.verbosity().note("checksum verified");
+    Ok(())
+  }
+
+  fn timeout_error(&self, uri: &str, timeout: Duration) -> miette::Report {
+    miette::Report::new(ApiError::TimeoutError { timeout }).wrap_err(format!("URL: {uri}"))
+  }
+
+  /// Build the error from an unexpected response.
+  async fn error(&self, uri: &str, response: hyper::Response<Incoming>) -> miette::Report {
+    let empty_string = String::default();
+    let message = self
+      .ko_status
+      .get(&response.status())
+      .unwrap_or(&empty_string)
+      .to_string();
 
-    let response = sender
-      .send_request(req)
+    trace!("Response {:?}", response);
+
+    let status = response.status();
+
+    let mut error_body = String::new();
+    if let Err(e) = response
+      .collect()
       .await
       .into_diagnostic()
-      .with_context(|| format!("Payload: {payload:?}"))
       .with_context(|| format!("URL: {uri}"))
-      .context("HTTP call fail")?;
-    trace!("Response status: {}", response.status());
-    if response.status() == self.ok_status {
-      let body = response
-        .collect()
-        .await
-        .into_diagnostic()
-        .with_context(|| format!("URL: {uri}"))
-        .context("Can't read the HTTP response")?
-        .aggregate();
+      .wrap_err("Can't read the HTTP error response")
+      .and_then(|body| {
+        body
+          .aggregate()
+          .reader()
+          .read_to_string(&mut error_body)
+          .into_diagnostic()
+          .wrap_err("Can't read error as string")
+      })
+    {
+      return e;
+    }
+    self.client.verbosity().body(error_body.as_bytes());
+    miette::Report::new(ApiError::from_http_status(status, self.auth))
+      .wrap_err(error_body)
+      .wrap_err(format!("URL: {uri}"))
+      .wrap_err(if message.is_empty() {
+        "Unexpected HTTP Status Code".to_string()
+      } else {
+        message
+      })
+  }
 
-      if !body.has_remaining() {
-        Ok(R::default()) // I don't find a type that can deserialize an empty string.
+  /// Fetch all the requested pages and concatenate their results.
+  async fn query_pages<T, R>(
+    &self,
+    pagination: &Pagination<'a>,
+    args: &PageArgs,
+    uri: &str,
+    body: Body,
+    transform_from_type: Option<PhantomData<T>>,
+  ) -> Result<R>
+  where
+    T: TryInto<R, Error = String> + DeserializeOwned + Send,
+    R: Send + Serialize + DeserializeOwned + Debug + Default,
+  {
+    let mut items: Vec<serde_json::Value> = vec![];
+    let mut fetched = HashSet::new();
+    let mut previous = None;
+    let mut next = Some(pagination.first_page(uri, args)?);
+    while let Some(uri) = next {
+      fetched.insert(uri.clone());
+      let (headers, page) = self.fetch(&uri, &body).await?;
+      let page: serde_json::Value = if page.has_remaining() {
+        self.format(&headers).decode(&page)?
       } else {
-        #[cfg(feature = "debug-http")]
-        {
-          let mut buffer = String::new();
-          body
-            .reader()
-            .read_to_string(&mut buffer)
-            .into_diagnostic()
-            .wrap_err("Can't read error as string")?;
-          println!("{}", buffer);
-          let result: R = if transform_from_type.is_some() {
-            let raw_result: T = serde_json::from_str(&buffer)
-              .into_diagnostic()
-              .context("Can't deserialize the response")?;
-            raw_result.try_into().map_err(|e| miette!("{}", e))?
-          } else {
-            serde_json::from_str(&buffer)
-              .into_diagnostic()
-              .context("Can't deserialize the response")?
-          };
-          Ok(result)
+        serde_json::Value::Null
+      };
+      // A server that ignores the page parameter sends the same page again.
+      if previous.as_ref() == Some(&page) {
+        warn!("The page {uri} is the same as the previous page: the pagination stops");
+        break;
+      }
+
+      // `transform_from` converts the results at `items_path`.
+      let results = pagination.results(&page);
+      let page_items = if transform_from_type.is_some() {
+        let raw_result: T = serde_json::from_value(results)
+          .into_diagnostic()
+          .context("Can't deserialize the response")?;
+        let result: R = raw_result.try_into().map_err(|e| miette!("{}", e))?;
+        serde_json::from_value(serde_json::to_value(result).into_diagnostic()?)
+          .into_diagnostic()
+          .context("The transformed results are not a list")?
+      } else {
+        pagination.items(results)?
+      };
+      let count = page_items.len();
+      trace!("Page {uri}: {count} results");
+      items.extend(page_items);
+
+      next = match args.max_items {
+        Some(max_items) if items.len() >= max_items => {
+          items.truncate(max_items);
+          None
         }
-        #[cfg(not(feature = "debug-http"))]
-        {
-          let result: R = if transform_from_type.is_some() {
-            let raw_result: T = serde_json::from_reader(body.reader())
-              .into_diagnostic()
-              .context("Can't deserialize the response")?;
-            raw_result.try_into().map_err(|e| miette!("{}", e))?
-          } else {
-            serde_json::from_reader(body.reader())
-              .into_diagnostic()
-              .context("Can't deserialize the response")?
-          };
-          Ok(result)
+        // `--max-items` follows the pages until it's reached.
+        _ if !args.all && args.max_items.is_none() => None,
+        _ if fetched.len() >= MAX_PAGES => {
+          warn!("{MAX_PAGES} pages fetched: the pagination stops");
+          None
         }
-      }
-    } else {
-      let empty_string = String::default();
-      let message = self
-        .ko_status
-        .get(&response.status())
-        .unwrap_or(&empty_string)
-        .to_string();
+        _ => match pagination.next_page(&uri, args, &headers, &page, count)? {
+          Some(next) if fetched.contains(&next) => {
+            warn!("The page {next} was already fetched: the pagination stops");
+            None
+          }
+          next => next,
+        },
+      };
+      previous = Some(page);
+    }
+    serde_json::from_value(serde_json::Value::Array(items))
+      .into_diagnostic()
+      .context("Can't deserialize the response")
+  }
+}
 
-      trace!("Response {:?}", response);
+/// Condition of a request. Its response can have another status than the expected status.
+#[derive(Clone, Copy)]
+enum Condition<'c> {
+  None,
+  /// Revalidate a cached response: `304 Not Modified` if it's still valid.
+  Cached(&'c CacheEntry),
+  /// Resume a download from an offset if the file has this validator (`If-Range`):
+  /// `206 Partial Content`, or `416 Range Not Satisfiable` if the download is complete.
+  Range(u64, Option<&'c str>),
+}
 
-      let status = response.status();
+impl Condition<'_> {
+  fn headers(&self) -> Vec<(HeaderName, String)> {
+    match self {
+      Condition::None => vec![],
+      Condition::Cached(entry) => entry
+        .validators()
+        .into_iter()
+        .map(|(name, value)| (name, value.to_string()))
+        .collect(),
+      Condition::Range(offset, validator) => {
+        let mut headers = vec![(RANGE, format!("bytes={offset}-"))];
+        if let Some(validator) = validator {
+          headers.push((IF_RANGE, validator.to_string()));
+        }
+        headers
+      }
+    }
+  }
 
-      let mut error_body = String::new();
-      response
-        .collect()
-        .await
-        .into_diagnostic()
-        .with_context(|| format!("URL: {uri}"))
-        .wrap_err("Can't read the HTTP error response")?
-        .aggregate()
-        .reader()
-        .read_to_string(&mut error_body)
-        .into_diagnostic()
-        .wrap_err("Can't read error as string")?;
-      #[cfg(feature = "debug-http")]
-      {
-        println!("{}", error_body);
+  fn expects(&self, status: StatusCode) -> bool {
+    match self {
+      Condition::None => false,
+      Condition::Cached(_) => status == StatusCode::NOT_MODIFIED,
+      Condition::Range(..) => {
+        status == StatusCode::PARTIAL_CONTENT || status == StatusCode::RANGE_NOT_SATISFIABLE
       }
-      Err(ApiError::from_http_status(status, self.auth))
-        .wrap_err(error_body)
-        .wrap_err_with(|| format!("URL: {uri}"))
-        .wrap_err(if message.is_empty() {
-          "Unexpected HTTP Status Code".to_string()
-        } else {
-          message
-        })?
+    }
+  }
+}
+
+/// Store a response in the cache. The request doesn't fail if the cache is not writable.
+fn store(cache: &Cache, key: &str, entry: &CacheEntry) {
+  if let Err(e) = cache.store(key, entry) {
+    warn!("Can't cache the response: {e:?}");
+  }
+}
+
+#[derive(Clone)]
+pub struct Header<'a> {
+  pub key: &'a str,
+  pub value: &'a str,
+}
+
+#[async_trait]
+impl Query for HTTPApi<'_> {
+  async fn query<P, T, R, Q>(
+    &self,
+    payload: Option<P>,
+    query_args: Option<Q>,
+    transform_from_type: Option<PhantomData<T>>,
+  ) -> Result<R>
+  where
+    P: Send + Serialize + Debug,
+    T: TryInto<R, Error = String> + DeserializeOwned + Send,
+    R: Send + Serialize + DeserializeOwned + Debug + Default,
+    Q: Send + Serialize + Debug,
+  {
+    let mut uri = self.uri.to_owned();
+    if let Some(qa) = query_args {
+      uri = format!("{}?{}", uri, serde_qs::to_string(&qa).unwrap());
+    }
+    let body = Body::encode(&payload, &self.payload_encoding)?;
+
+    if let Some((pagination, args)) = &self.pagination {
+      return self
+        .query_pages(pagination, args, &uri, body, transform_from_type)
+        .await;
+    }
+
+    let (headers, body) = self.fetch(&uri, &body).await?;
+
+    if !body.has_remaining() {
+      Ok(R::default()) // I don't find a type that can deserialize an empty string.
+    } else {
+      let format = self.format(&headers);
+      let result: R = if transform_from_type.is_some() {
+        let raw_result: T = format.decode(&body)?;
+        raw_result.try_into().map_err(|e| miette!("{}", e))?
+      } else {
+        format.decode(&body)?
+      };
+      Ok(result)
     }
   }
 
@@ -296,149 +737,886 @@ impl Query for HTTPApi<'_> {
     Q: Send + Serialize + Debug,
   {
     let mut uri = self.uri.to_owned();
-    let (mut sender, authority) = connect(&uri).await?;
-
     if let Some(qa) = query_args {
       uri = format!("{}?{}", uri, serde_qs::to_string(&qa).unwrap());
     }
-    let req = Request::builder().method(&self.method).uri(&uri);
-    trace!("Request {} to {}", self.method, uri);
-    let req = if let Some(auth) = self.auth {
-      let (header_key, header_value) = auth.auth_header();
-      if !header_key.is_empty() {
-        req.header(header_key, header_value)
-      } else {
-        req
+    let body = Body::encode(&payload, &self.payload_encoding)?;
+
+    // The download can be long: the timeout applies between two chunks.
+    let idle_timeout = self.client.retry_policy(&self.retry).timeout();
+    let Some(path) = filename else {
+      let mut response = self.send(&uri, &body, Condition::None).await?;
+      let mut checksum = self
+        .expected_checksum(response.headers())?
+        .map(|expected| (expected, Sha256::new()));
+      let bar = progress_bar(content_length(response.headers()), "Downloading...")?;
+      while let Some(chunk) = timeout(idle_timeout, response.frame())
+        .await
+        .map_err(|_| self.timeout_error(&uri, idle_timeout))?
+      {
+        let frame = chunk
+          .into_diagnostic()
+          .with_context(|| format!("URL: {uri}"))?;
+        if let Some(chunk) = frame.data_ref() {
+          bar.inc(chunk.len().try_into().into_diagnostic()?);
+          if let Some((_, hasher)) = checksum.as_mut() {
+            hasher.update(chunk);
+          }
+          stdout()
+            .write_all(chunk)
+            .await
+            .into_diagnostic()
+            .context("Failed to write: {}")?;
+        }
       }
+      bar.finish_and_clear();
+      return self.verify(checksum, None).await;
+    };
+
+    let verbosity = self.client.verbosity();
+    let mut path = PathBuf::from(path);
+    let mut first_response = None;
+    if path.is_dir() || path.as_os_str().to_string_lossy().ends_with(MAIN_SEPARATOR) {
+      // The file is named by the response, which is the download: the request is not sent
+      // again, so the download can't be resumed.
+      let response = self.send(&uri, &body, Condition::None).await?;
+      let filename = disposition_filename(response.headers())
+        .or_else(|| url_filename(&uri))
+        .unwrap_or_else(|| "download".to_string());
+      path.push(filename);
+      verbosity.note(&format!("output file: {}", path.display()));
+      if self.resume {
+        verbosity.note("the output file isn't known before the request: restart the download");
+      }
+      first_response = Some(response);
+    }
+    let resume = self.resume && first_response.is_none();
+    let path = path.as_path();
+    if let Some(dir) = path.parent() {
+      create_dir_all(dir)
+        .await
+        .into_diagnostic()
+        .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    }
+    let part = partial_path(path);
+    let validator = validator_path(path);
+    // The download is resumed from the partial file, or from the output file of a previous
+    // download. The output file is not modified until the new download is complete.
+    let resumed = if !resume {
+      None
+    } else if part.exists() {
+      Some(part.clone())
+    } else if path.exists() {
+      Some(path.to_path_buf())
     } else {
-      req
+      None
     };
-    let mut req = req;
-    for Header { key, value } in self.headers.iter() {
-      req = req.header(*key, *value)
-    }
-    let req = req
-      .header("content-type", "application/json")
-      .header(hyper::header::HOST, authority.as_str())
-      .body(match payload {
-        Some(ref payload) => {
-          let p = serde_json::to_string(&payload)
+    let mut offset = match &resumed {
+      Some(resumed) => metadata(resumed).await.map(|m| m.len()).unwrap_or_default(),
+      None => 0,
+    };
+    let if_range = match &resumed {
+      Some(resumed) if *resumed == part => read_to_string(&validator).await.ok(),
+      _ => None,
+    };
+    let condition = if offset > 0 {
+      Condition::Range(offset, if_range.as_deref())
+    } else {
+      Condition::None
+    };
+    let mut response = match first_response {
+      Some(response) => response,
+      None => self.send(&uri, &body, condition).await?,
+    };
+    match (response.status(), &resumed) {
+      (StatusCode::RANGE_NOT_SATISFIABLE, Some(resumed)) => {
+        if let Some((_, Some(total))) = content_range(response.headers()) {
+          if total == offset {
+            verbosity.note("range not satisfiable: the download is complete");
+            let checksum = match self.expected_checksum(response.headers())? {
+              Some(expected) => {
+                let mut hasher = Sha256::new();
+                hash_file(resumed, &mut hasher)
+                  .await
+                  .into_diagnostic()
+                  .with_context(|| format!("Failed to read file: {}", resumed.display()))?;
+                Some((expected, hasher))
+              }
+              None => None,
+            };
+            if *resumed != part {
+              // The output file is already complete.
+              return self.verify(checksum, None).await;
+            }
+            self.verify(checksum, Some(&part)).await?;
+            remove_file(&validator).await.ok();
+            return self.complete(&part, path).await;
+          }
+        }
+        verbosity.note("range not satisfiable: restart the download");
+        offset = 0;
+        response = self.send(&uri, &body, Condition::None).await?;
+      }
+      (StatusCode::PARTIAL_CONTENT, Some(resumed)) => {
+        let start = content_range(response.headers()).and_then(|(start, _)| start);
+        if start != Some(offset) {
+          return Err(miette!(
+            help = format!("Remove {} to restart the download", resumed.display()),
+            "Unexpected range of the response: {}",
+            response
+              .headers()
+              .get(CONTENT_RANGE)
+              .and_then(|range| range.to_str().ok())
+              .unwrap_or("none")
+          ));
+        }
+        if *resumed != part {
+          copy(resumed, &part)
+            .await
             .into_diagnostic()
-            .context("Error during payload serialization")?;
-          trace!("Payload: {}", p);
-          p
+            .with_context(|| format!("Failed to copy file: {}", resumed.display()))?;
         }
-        None => String::new(),
-      })
-      .into_diagnostic()
-      .with_context(|| format!("Payload: {payload:?}"))
-      .with_context(|| format!("URL: {uri}"))
-      .context("HTTP request preparation failed.")?;
+        verbosity.note(&format!("resume the download at {offset} bytes"));
+      }
+      _ if offset > 0 => {
+        verbosity.note("the file changed or the server ignored the range: restart the download");
+        offset = 0;
+      }
+      _ => {}
+    }
+    if offset == 0 {
+      match range_validator(response.headers()) {
+        Some(value) => write(&validator, value)
+          .await
+          .into_diagnostic()
+          .with_context(|| format!("Failed to write file: {}", validator.display()))?,
+        None => {
+          remove_file(&validator).await.ok();
+        }
+      }
+    }
 
-    let mut response = sender
-      .send_request(req)
+    let mut checksum = self
+      .expected_checksum(response.headers())?
+      .map(|expected| (expected, Sha256::new()));
+    if let Some((_, hasher)) = checksum.as_mut().filter(|_| offset > 0) {
+      hash_file(&part, hasher)
+        .await
+        .into_diagnostic()
+        .with_context(|| format!("Failed to read file: {}", part.display()))?;
+    }
+    let length = content_length(response.headers());
+    let mut file = if offset > 0 {
+      OpenOptions::new().append(true).open(&part).await
+    } else {
+      File::create(&part).await
+    }
+    .into_diagnostic()
+    .with_context(|| format!("Failed to create file: {}", part.display()))?;
+    let bar = progress_bar(length.map(|length| length + offset), "Downloading...")?;
+    bar.set_position(offset);
+
+    let mut received = 0;
+    while let Some(chunk) = timeout(idle_timeout, response.frame())
       .await
-      .into_diagnostic()
-      .with_context(|| format!("Payload: {payload:?}"))
-      .with_context(|| format!("URL: {uri}"))
-      .context("HTTP call fail")?;
-    trace!("Response status: {}", response.status());
-    let bar = ProgressBar::new_spinner();
-    bar.enable_steady_tick(Duration::from_millis(150));
-    bar.set_style(
-      ProgressStyle::default_spinner()
-        .template("{spinner:.blue} {msg} {bytes:>12} @ {bytes_per_sec:15} ({elapsed})")
-        .into_diagnostic()?
-        .tick_strings(&[
-          "▹▹▹▹▹",
-          "▸▹▹▹▹",
-          "▹▸▹▹▹",
-          "▹▹▸▹▹",
-          "▹▹▹▸▹",
-          "▹▹▹▹▸",
-          "▪▪▪▪▪",
-        ]),
-    );
-    bar.set_message("Downloading...");
-    if response.status() == self.ok_status {
-      // use hyper::body::HttpBody;
-      // use tokio::io::{stdout, AsyncWriteExt as _};
-      // while let Some(chunk) = response.body_mut().data().await {
-      //   stdout().write_all(&chunk?).await?;
-      // }
-      if let Some(path) = filename {
-        let path = Path::new(&path);
-        if let Some(dir) = path.parent() {
-          create_dir_all(dir)
-            .await
-            .into_diagnostic()
-            .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+      .map_err(|_| interrupted(self.timeout_error(&uri, idle_timeout)))?
+    {
+      let frame = chunk
+        .into_diagnostic()
+        .with_context(|| format!("URL: {uri}"))
+        .map_err(interrupted)?;
+      if let Some(chunk) = frame.data_ref() {
+        received += chunk.len() as u64;
+        bar.inc(chunk.len().try_into().into_diagnostic()?);
+        if let Some((_, hasher)) = checksum.as_mut() {
+          hasher.update(chunk);
         }
-        let mut file = File::create(path)
+        file
+          .write_all(chunk)
           .await
           .into_diagnostic()
-          .with_context(|| format!("Failed to create file: {}", path.display()))?;
+          .with_context(|| format!("Failed to write file: {}", part.display()))?;
+      }
+    }
+    file
+      .flush()
+      .await
+      .into_diagnostic()
+      .with_context(|| format!("Failed to write file: {}", part.display()))?;
+    bar.finish_and_clear();
+    if let Some(length) = length.filter(|length| *length != received) {
+      return Err(interrupted(miette!(
+        "Incomplete download: received {received} of {length} bytes"
+      )));
+    }
+    self.verify(checksum, Some(&part)).await?;
+    remove_file(&validator).await.ok();
+    self.complete(&part, path).await
+  }
+}
 
-        while let Some(chunk) = response.frame().await {
-          let frame = chunk
-            .into_diagnostic()
-            .with_context(|| format!("URL: {uri}"))?;
-          if let Some(chunk) = frame.data_ref() {
-            bar.inc(chunk.len().try_into().into_diagnostic()?);
-            file
-              .write_all(chunk)
-              .await
-              .into_diagnostic()
-              .with_context(|| format!("Failed to write file: {}", path.display()))?;
+/// The error of an interrupted download.
+fn interrupted(error: miette::Report) -> miette::Report {
+  miette::Report::new(ApiError::DownloadInterrupted {
+    reason: error
+      .chain()
+      .map(ToString::to_string)
+      .collect::<Vec<_>>()
+      .join(": "),
+  })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use super::{HTTPApi, HttpClient};
+  use crate::{
+    body::PayloadEncoding,
+    cache::{cache_args, clap_match_cache},
+    debug::{is_dry_run, DryRun},
+    error::ApiError,
+    pagination::{PageArgs, Pagination, PaginationKind},
+    DummyTryFrom, Query,
+  };
+  use async_trait::async_trait;
+  use clap::{Arg, ArgAction, ArgMatches, Command};
+  use config::Config;
+  use crud_auth::CrudAuth;
+  use hyper::{Method, StatusCode};
+  use serde::{Deserialize, Serialize};
+  use std::{
+    collections::HashMap,
+    marker::PhantomData,
+    sync::{
+      atomic::{AtomicU32, Ordering},
+      Arc, Mutex,
+    },
+  };
+  use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+  };
+
+  /// A server answering each request with `respond(request)`. Returns its URL and the
+  /// requests received, without their body.
+  pub(crate) async fn serve(
+    respond: impl Fn(&str) -> String + Send + Sync + 'static,
+  ) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let requests = Arc::new(Mutex::new(vec![]));
+    let received = requests.clone();
+    tokio::spawn(async move {
+      while let Ok((mut stream, _)) = listener.accept().await {
+        let mut request = vec![];
+        let mut buf = [0; 4096];
+        while !request.windows(4).any(|end| end == b"\r\n\r\n") {
+          match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => request.extend_from_slice(&buf[..n]),
           }
         }
+        let request = String::from_utf8_lossy(&request).to_string();
+        let response = respond(&request);
+        received.lock().unwrap().push(request);
+        stream.write_all(response.as_bytes()).await.ok();
+        // The client reads what it received before the end of a truncated response.
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        stream.shutdown().await.ok();
+      }
+    });
+    (url, requests)
+  }
+
+  /// A raw HTTP response.
+  pub(crate) fn response(status: &str, headers: &[(&str, &str)], body: &str) -> String {
+    let headers: String = headers
+      .iter()
+      .map(|(name, value)| format!("{name}: {value}\r\n"))
+      .collect();
+    format!(
+      "HTTP/1.1 {status}\r\ncontent-length: {}\r\nconnection: close\r\n{headers}\r\n{body}",
+      body.len()
+    )
+  }
+
+  /// The path and query of a request.
+  pub(crate) fn target(request: &str) -> &str {
+    request.split(' ').nth(1).unwrap_or_default()
+  }
+
+  /// A client with the default settings.
+  pub(crate) fn client() -> HttpClient {
+    let settings = [
+      "profile",
+      "proxy",
+      "ca_cert",
+      "client_cert",
+      "client_key",
+      "timeout",
+      "retries",
+    ];
+    let matches = Command::new("test")
+      .args(settings.map(|id| Arg::new(id).long(id)))
+      .args(["system_certs", "insecure"].map(|id| Arg::new(id).long(id).action(ArgAction::SetTrue)))
+      .get_matches_from(["test"]);
+    HttpClient::new(&Config::default(), &matches).unwrap()
+  }
+
+  #[derive(Debug, Default, Deserialize, Serialize, PartialEq)]
+  struct Item {
+    id: u32,
+  }
+
+  #[derive(Deserialize)]
+  #[serde(transparent)]
+  struct RawItems(Vec<HashMap<String, u32>>);
+
+  impl TryFrom<RawItems> for Vec<Item> {
+    type Error = String;
+
+    fn try_from(raw: RawItems) -> Result<Self, Self::Error> {
+      raw
+        .0
+        .into_iter()
+        .map(|item| {
+          item
+            .get("key")
+            .map(|id| Item { id: *id })
+            .ok_or("no key".into())
+        })
+        .collect()
+    }
+  }
+
+  async fn pages(pagination: Pagination<'_>, args: PageArgs, url: &str) -> Vec<u32> {
+    let client = client();
+    let ko_status = HashMap::new();
+    let headers = vec![];
+    let items: Vec<Item> = HTTPApi::new(
+      &client,
+      format!("{url}/items"),
+      Method::GET,
+      StatusCode::OK,
+      &ko_status,
+      None,
+      &headers,
+    )
+    .paginate(pagination, args)
+    .query(None::<()>, None::<()>, None::<PhantomData<DummyTryFrom>>)
+    .await
+    .unwrap();
+    items.into_iter().map(|item| item.id).collect()
+  }
+
+  fn pagination(kind: PaginationKind) -> Pagination<'static> {
+    Pagination {
+      kind,
+      page_param: "page",
+      per_page_param: "per_page",
+      cursor_param: "cursor",
+      cursor_path: "next",
+      items_path: "",
+    }
+  }
+
+  #[tokio::test]
+  async fn test_query_pages() {
+    // The links are followed.
+    let (url, requests) = serve(|request| match target(request) {
+      "/items" => response(
+        "200 OK",
+        &[("link", "</items?page=2>; rel=\"next\"")],
+        r#"[{"id":1}]"#,
+      ),
+      _ => response("200 OK", &[], r#"[{"id":2}]"#),
+    })
+    .await;
+    let all = || PageArgs {
+      all: true,
+      ..Default::default()
+    };
+    assert_eq!(
+      pages(pagination(PaginationKind::Link), all(), &url).await,
+      [1, 2]
+    );
+    assert_eq!(requests.lock().unwrap().len(), 2);
+
+    // The server ignores the page parameter: the same page is sent again.
+    let (url, requests) = serve(|_| response("200 OK", &[], r#"[{"id":1},{"id":2}]"#)).await;
+    assert_eq!(
+      pages(pagination(PaginationKind::Page), all(), &url).await,
+      [1, 2]
+    );
+    assert_eq!(requests.lock().unwrap().len(), 2);
+
+    // Without `--all`, `--max-items` follows the pages until it's reached.
+    let (url, requests) = serve(|request| {
+      let page = match target(request) {
+        "/items" => 1,
+        target => target.trim_start_matches("/items?page=").parse().unwrap(),
+      };
+      response(
+        "200 OK",
+        &[("link", &format!("</items?page={}>; rel=\"next\"", page + 1))],
+        &format!(r#"[{{"id":{page}}}]"#),
+      )
+    })
+    .await;
+    let max_items = PageArgs {
+      max_items: Some(2),
+      ..Default::default()
+    };
+    assert_eq!(
+      pages(pagination(PaginationKind::Link), max_items, &url).await,
+      [1, 2]
+    );
+    assert_eq!(requests.lock().unwrap().len(), 2);
+    assert_eq!(
+      pages(pagination(PaginationKind::Link), PageArgs::default(), &url).await,
+      [1]
+    );
+
+    // The cursor is the same as the previous one.
+    let (url, requests) = serve(|request| {
+      let id = if target(request).contains("cursor") {
+        2
       } else {
-        while let Some(chunk) = response.frame().await {
-          let frame = chunk
-            .into_diagnostic()
-            .with_context(|| format!("URL: {uri}"))?;
-          if let Some(chunk) = frame.data_ref() {
-            bar.inc(chunk.len().try_into().into_diagnostic()?);
-            stdout()
-              .write_all(chunk)
-              .await
-              .into_diagnostic()
-              .context("Failed to write: {}")?;
-          }
-        }
+        1
       };
-      bar.finish_and_clear();
+      response(
+        "200 OK",
+        &[],
+        &format!(r#"{{"data":{{"items":[{{"key":{id}}}]}},"next":"abc"}}"#),
+      )
+    })
+    .await;
+    let client = client();
+    let ko_status = HashMap::new();
+    let headers = vec![];
+    let items: Vec<Item> = HTTPApi::new(
+      &client,
+      format!("{url}/items"),
+      Method::GET,
+      StatusCode::OK,
+      &ko_status,
+      None,
+      &headers,
+    )
+    .paginate(
+      Pagination {
+        items_path: "data.items",
+        ..pagination(PaginationKind::Cursor)
+      },
+      PageArgs {
+        all: true,
+        ..Default::default()
+      },
+    )
+    .query(None::<()>, None::<()>, Some(PhantomData::<RawItems>))
+    .await
+    .unwrap();
+    assert_eq!(items, [Item { id: 1 }, Item { id: 2 }]);
+    let requests = requests.lock().unwrap();
+    assert_eq!(
+      requests.iter().map(|r| target(r)).collect::<Vec<_>>(),
+      ["/items", "/items?cursor=abc"]
+    );
+  }
+
+  /// Download `{url}/file` in `dir/file`.
+  async fn download(url: &str, dir: &std::path::Path, resume: bool) -> miette::Result<()> {
+    let client = client();
+    let ko_status = HashMap::new();
+    let headers = vec![];
+    HTTPApi::new(
+      &client,
+      format!("{url}/file"),
+      Method::GET,
+      StatusCode::OK,
+      &ko_status,
+      None,
+      &headers,
+    )
+    .resume_download(resume)
+    .stream(
+      None::<()>,
+      None::<()>,
+      Some(dir.join("file").to_string_lossy().to_string()),
+    )
+    .await
+  }
+
+  fn header<'r>(request: &'r str, name: &str) -> Option<&'r str> {
+    request.lines().find_map(|line| {
+      let (key, value) = line.split_once(':')?;
+      key.eq_ignore_ascii_case(name).then(|| value.trim())
+    })
+  }
+
+  #[tokio::test]
+  async fn test_resume_download() {
+    let dir = std::env::temp_dir().join(format!("crud-api-download-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let read = |name: &str| std::fs::read_to_string(dir.join(name)).ok();
+
+    // The download is interrupted: the Content-Length is not received. The ETag is kept.
+    let (url, _) =
+      serve(|_| response("200 OK", &[("etag", "\"v1\"")], "hello world").replace("world", "")).await;
+    assert!(download(&url, &dir, false).await.is_err());
+    assert_eq!(read("file"), None);
+    assert_eq!(read("file.part").as_deref(), Some("hello "));
+    assert_eq!(read("file.part.validator").as_deref(), Some("\"v1\""));
+
+    // The download is resumed if the file didn't change.
+    let (url, requests) =
+      serve(
+        |request| match (header(request, "range"), header(request, "if-range")) {
+          (Some("bytes=6-"), Some("\"v1\"")) => response(
+            "206 Partial Content",
+            &[("content-range", "bytes 6-10/11")],
+            "world",
+          ),
+          _ => response("500 Internal Server Error", &[], ""),
+        },
+      )
+      .await;
+    download(&url, &dir, true).await.unwrap();
+    assert_eq!(requests.lock().unwrap().len(), 1);
+    assert_eq!(read("file").as_deref(), Some("hello world"));
+    assert_eq!(read("file.part"), None);
+    assert_eq!(read("file.part.validator"), None);
+
+    // The output file is complete: it's not modified.
+    let (url, requests) = serve(|request| match header(request, "range") {
+      Some("bytes=11-") => response(
+        "416 Range Not Satisfiable",
+        &[("content-range", "bytes */11")],
+        "",
+      ),
+      _ => response("500 Internal Server Error", &[], ""),
+    })
+    .await;
+    download(&url, &dir, true).await.unwrap();
+    assert_eq!(header(&requests.lock().unwrap()[0], "if-range"), None);
+    assert_eq!(read("file").as_deref(), Some("hello world"));
+    assert_eq!(read("file.part"), None);
+
+    // The file changed on the server: the server sends the whole file, and the output file
+    // is replaced when the download is complete.
+    std::fs::write(dir.join("file.part"), "hel").unwrap();
+    std::fs::write(dir.join("file.part.validator"), "\"v1\"").unwrap();
+    let (url, _) = serve(|_| response("200 OK", &[("etag", "\"v2\"")], "HELLO WORLD")).await;
+    download(&url, &dir, true).await.unwrap();
+    assert_eq!(read("file").as_deref(), Some("HELLO WORLD"));
+    assert_eq!(read("file.part"), None);
+
+    // The output file is resumed from its end, in the partial file.
+    std::fs::write(dir.join("file"), "HELLO").unwrap();
+    let (url, _) = serve(|request| match header(request, "range") {
+      Some("bytes=5-") => response(
+        "206 Partial Content",
+        &[("content-range", "bytes 5-10/11")],
+        " WORLD",
+      ),
+      _ => response("500 Internal Server Error", &[], ""),
+    })
+    .await;
+    download(&url, &dir, true).await.unwrap();
+    assert_eq!(read("file").as_deref(), Some("HELLO WORLD"));
+
+    // An unexpected range is refused.
+    std::fs::write(dir.join("file.part"), "HELLO").unwrap();
+    let (url, _) = serve(|_| {
+      response(
+        "206 Partial Content",
+        &[("content-range", "bytes 0-10/11")],
+        "HELLO WORLD",
+      )
+    })
+    .await;
+    assert!(download(&url, &dir, true).await.is_err());
+    assert_eq!(read("file").as_deref(), Some("HELLO WORLD"));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[tokio::test]
+  async fn test_download_in_directory() {
+    let dir = std::env::temp_dir().join(format!("crud-api-directory-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("report.txt"), "hello").unwrap();
+
+    // The file is named by the response, which is the download: it's not requested again
+    // with a range, even with `--continue`.
+    let (url, requests) = serve(|_| {
+      response(
+        "200 OK",
+        &[("content-disposition", "attachment; filename=\"report.txt\"")],
+        "hello world",
+      )
+    })
+    .await;
+    let client = client();
+    let ko_status = HashMap::new();
+    let headers = vec![];
+    HTTPApi::new(
+      &client,
+      format!("{url}/file"),
+      Method::POST,
+      StatusCode::OK,
+      &ko_status,
+      None,
+      &headers,
+    )
+    .resume_download(true)
+    .stream(
+      Some(Item { id: 1 }),
+      None::<()>,
+      Some(dir.to_string_lossy().to_string()),
+    )
+    .await
+    .unwrap();
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(header(&requests[0], "range"), None);
+    assert_eq!(
+      std::fs::read_to_string(dir.join("report.txt")).unwrap(),
+      "hello world"
+    );
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  /// An authentication with a new token for each request.
+  #[derive(Default)]
+  struct RotatingAuth(AtomicU32);
+
+  #[async_trait]
+  impl CrudAuth for RotatingAuth {
+    fn clap_auth(&self, app: Command) -> Command {
+      app
+    }
+
+    fn clap_matches(&mut self, _: &ArgMatches, _: &mut Command, _: &Config) {}
+
+    async fn authorize(&self, request: &mut crud_auth::Request<()>) -> miette::Result<()> {
+      let token = self.0.fetch_add(1, Ordering::SeqCst);
+      request
+        .headers_mut()
+        .insert("authorization", format!("Bearer {token}").parse().unwrap());
       Ok(())
-    } else {
-      let empty_string = String::default();
-      let message = self
-        .ko_status
-        .get(&response.status())
-        .unwrap_or(&empty_string)
-        .to_string();
-
-      let status = response.status();
-      let mut error_body = String::new();
-      response
-        .collect()
+    }
+
+    fn identity(&self) -> Option<String> {
+      Some("alice".into())
+    }
+
+    fn error_help_message(&self) -> String {
+      String::new()
+    }
+  }
+
+  #[tokio::test]
+  async fn test_fetch_cache() {
+    let dir = std::env::temp_dir().join(format!("crud-api-fetch-{}", std::process::id()));
+    let settings = Config::builder()
+      .set_override("cache_path", dir.to_str().unwrap())
+      .unwrap()
+      .build()
+      .unwrap();
+    let matches = cache_args(Command::new("test").arg(Arg::new("profile").long("profile")))
+      .get_matches_from(["test"]);
+    let client = client().with_cache(clap_match_cache(&settings, &matches).unwrap());
+    let (url, requests) = serve(|request| match target(request) {
+      "/fresh" => response("200 OK", &[], r#"[{"id":1}]"#),
+      "/etag" if header(request, "if-none-match") == Some("\"v1\"") => {
+        response("304 Not Modified", &[], "")
+      }
+      "/etag" => response("200 OK", &[("etag", "\"v1\"")], r#"[{"id":2}]"#),
+      _ => response("200 OK", &[("cache-control", "no-store")], r#"[{"id":3}]"#),
+    })
+    .await;
+    let auth = RotatingAuth::default();
+    let fetch = |path: &str, ttl| {
+      let client = &client;
+      let auth = &auth;
+      let uri = format!("{url}{path}");
+      async move {
+        let ko_status = HashMap::new();
+        let headers = vec![];
+        let items: Vec<Item> = HTTPApi::new(
+          client,
+          uri,
+          Method::GET,
+          StatusCode::OK,
+          &ko_status,
+          Some(auth),
+          &headers,
+        )
+        .cache_ttl(ttl)
+        .query(None::<()>, None::<()>, None::<PhantomData<DummyTryFrom>>)
         .await
-        .into_diagnostic()
-        .with_context(|| format!("URL: {uri}"))
-        .wrap_err("Can't read the HTTP error response")?
-        .aggregate()
-        .reader()
-        .read_to_string(&mut error_body)
-        .into_diagnostic()
-        .wrap_err("Can't read error as string")?;
+        .unwrap();
+        items.into_iter().map(|item| item.id).collect::<Vec<_>>()
+      }
+    };
+
+    // A fresh response is read from the cache, even if the token changed.
+    assert_eq!(fetch("/fresh", 60).await, [1]);
+    assert_eq!(fetch("/fresh", 60).await, [1]);
+    assert_eq!(requests.lock().unwrap().len(), 1);
+
+    // A stale response is revalidated and renewed.
+    assert_eq!(fetch("/etag", 0).await, [2]);
+    assert_eq!(fetch("/etag", 0).await, [2]);
+    {
+      let requests = requests.lock().unwrap();
+      assert_eq!(requests.len(), 3);
+      assert_eq!(header(&requests[1], "if-none-match"), None);
+      assert_eq!(header(&requests[2], "if-none-match"), Some("\"v1\""));
+    }
+    assert_eq!(fetch("/etag", 60).await, [2]);
+    assert_eq!(requests.lock().unwrap().len(), 4);
+    assert_eq!(fetch("/etag", 60).await, [2]);
+    assert_eq!(requests.lock().unwrap().len(), 4);
+
+    // A response with `Cache-Control: no-store` is not stored.
+    assert_eq!(fetch("/no-store", 60).await, [3]);
+    assert_eq!(fetch("/no-store", 60).await, [3]);
+    assert_eq!(requests.lock().unwrap().len(), 6);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  /// An authentication refreshing its token on `401 Unauthorized`.
+  #[derive(Default)]
+  struct RefreshAuth {
+    token: AtomicU32,
+    prepares: AtomicU32,
+    refreshes: AtomicU32,
+  }
+
+  #[async_trait]
+  impl CrudAuth for RefreshAuth {
+    fn clap_auth(&self, app: Command) -> Command {
+      app
+    }
 
-      Err(ApiError::from_http_status(status, self.auth))
-        .wrap_err(error_body)
-        .wrap_err_with(|| format!("URL: {uri}"))
-        .wrap_err(if message.is_empty() {
-          "Unexpected HTTP Status Code".to_string()
-        } else {
-          message
-        })?
+    fn clap_matches(&mut self, _: &ArgMatches, _: &mut Command, _: &Config) {}
+
+    async fn prepare(&self) -> miette::Result<()> {
+      self.prepares.fetch_add(1, Ordering::SeqCst);
+      Ok(())
+    }
+
+    async fn authorize(&self, request: &mut crud_auth::Request<()>) -> miette::Result<()> {
+      let token = self.token.load(Ordering::SeqCst);
+      request
+        .headers_mut()
+        .insert("authorization", format!("Bearer {token}").parse().unwrap());
+      Ok(())
+    }
+
+    async fn refresh(&self) -> miette::Result<bool> {
+      self.refreshes.fetch_add(1, Ordering::SeqCst);
+      self.token.fetch_add(1, Ordering::SeqCst);
+      Ok(true)
     }
+
+    fn error_help_message(&self) -> String {
+      String::new()
+    }
+  }
+
+  async fn get(
+    client: &HttpClient,
+    uri: String,
+    auth: &(dyn CrudAuth + Send + Sync),
+  ) -> miette::Result<Vec<Item>> {
+    let ko_status = HashMap::new();
+    let headers = vec![];
+    HTTPApi::new(
+      client,
+      uri,
+      Method::GET,
+      StatusCode::OK,
+      &ko_status,
+      Some(auth),
+      &headers,
+    )
+    .query(None::<()>, None::<()>, None::<PhantomData<DummyTryFrom>>)
+    .await
+  }
+
+  #[tokio::test]
+  async fn test_unauthorized_refresh() {
+    let client = client();
+    let (url, requests) = serve(|request| match header(request, "authorization") {
+      Some("Bearer 1") if target(request) == "/refreshed" => response("200 OK", &[], r#"[{"id":1}]"#),
+      _ => response("401 Unauthorized", &[], ""),
+    })
+    .await;
+
+    // The request is sent again once with the refreshed credentials.
+    let auth = RefreshAuth::default();
+    assert_eq!(
+      get(&client, format!("{url}/refreshed"), &auth)
+        .await
+        .unwrap(),
+      [Item { id: 1 }]
+    );
+    assert_eq!(auth.refreshes.load(Ordering::SeqCst), 1);
+    {
+      let requests = requests.lock().unwrap();
+      assert_eq!(requests.len(), 2);
+      assert_eq!(header(&requests[0], "authorization"), Some("Bearer 0"));
+      assert_eq!(header(&requests[1], "authorization"), Some("Bearer 1"));
+    }
+
+    // The second 401 is returned.
+    let auth = RefreshAuth::default();
+    let error = get(&client, format!("{url}/unauthorized"), &auth)
+      .await
+      .unwrap_err();
+    assert!(matches!(
+      error.downcast_ref(),
+      Some(ApiError::HTTPUnauthorizedError { .. })
+    ));
+    assert_eq!(auth.refreshes.load(Ordering::SeqCst), 1);
+    assert_eq!(requests.lock().unwrap().len(), 4);
+  }
+
+  #[tokio::test]
+  async fn test_dry_run() {
+    let client = client().with_dry_run(Some(DryRun::default()));
+    let (url, requests) = serve(|_| response("200 OK", &[], "[]")).await;
+    // The credentials are prepared as for a sent request.
+    let auth = RefreshAuth::default();
+    let error = get(&client, format!("{url}/items"), &auth)
+      .await
+      .unwrap_err();
+    assert!(is_dry_run(&error));
+    assert_eq!(auth.prepares.load(Ordering::SeqCst), 1);
+
+    // The body file is not opened.
+    let ko_status = HashMap::new();
+    let headers = vec![];
+    let items: miette::Result<Vec<Item>> = HTTPApi::new(
+      &client,
+      format!("{url}/items"),
+      Method::POST,
+      StatusCode::OK,
+      &ko_status,
+      None,
+      &headers,
+    )
+    .payload_encoding(PayloadEncoding::Raw {
+      file: "no/such/file".to_string(),
+      content_type: "text/csv",
+    })
+    .query(None::<()>, None::<()>, None::<PhantomData<DummyTryFrom>>)
+    .await;
+    assert!(is_dry_run(&items.unwrap_err()));
+    assert!(requests.lock().unwrap().is_empty());
   }
 }
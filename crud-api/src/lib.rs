@@ -7,9 +7,23 @@
 //! ## Features
 //!
 //! API:
-//! - data are encoded in JSON. It don't support XML, grpc, ...
-//! - output can be formated on json, yaml, toml, csv or tsv
-//! - output stream on stdout or in a file
+//! - payloads are encoded in JSON, as URL-encoded or multipart forms, or sent raw from a file or stdin. See the `payload_encoding` of the endpoints. It doesn't support grpc, ...
+//! - the `UploadFile` fields of the multipart forms are streamed from disk or stdin with a progress bar.
+//! - responses are decoded according to their `Content-Type` or the `response_format` of the endpoint: JSON, plain text, form-urlencoded, XML (with the `xml` feature), NDJSON and JSON text sequences.
+//! - the NDJSON and JSON-seq lists are printed while they're received when the output is line oriented (`--format ndjson`, `csv`, `tsv` or `--output-template`) and they're not sorted.
+//! - output can be formated on json, ndjson (one json result per line), yaml, toml, csv or tsv, or as a markdown, html or asciitable table (no colors, to paste in tickets and wikis).
+//! - output can be rendered line by line with a Handlebars template (`--output-template '{{id}}\t{{title}}'` or `--output-template-file`, `template` feature). The templates have `date`, `timestamp`, `duration`, `bytes` and `check` helpers.
+//! - the results are filtered (`--filter 'state==opened'`), sorted (`--sort -updated_at`) and projected (`--fields id,title`) on the client side, whatever the output format. When an argument of the query or of the payload has the same name, the selection argument is renamed after its id: `--select-fields`, `--select-filter`, `--select-sort` or `--output-query`.
+//! - `--query` reshapes the results with a [JMESPath](https://jmespath.org) expression (`--query '[].{id: id, author: author.name}'`).
+//! - output stream on stdout or in a file. The downloads in a file are resumed with `--continue` (not the downloads in a directory, named by the response), unless the file changed on the server (`If-Range`). Their SHA-256 checksum is verified with `--sha256` or `--checksum-header`.
+//! - HTTP connections are pooled and kept alive between the requests. HTTP/2 is negotiated when the `http2` feature is enabled (default).
+//! - HTTP proxies are read from the `HTTP_PROXY`, `HTTPS_PROXY` and `NO_PROXY` environment variables, the `proxy` setting or the `--proxy` argument. HTTPS requests are tunnelled with `CONNECT`.
+//! - TLS: additional certificate authorities (`ca_cert`), system certificates store (`system_certs`), client certificates (`client_cert` and `client_key`) and `insecure` mode can be set in the settings or with the arguments.
+//! - `--dry-run` prints the resolved request (method, url, headers and payload) instead of sending it. `--curl` prints it as a `curl` command. The secrets are redacted unless `--show-secrets` is given.
+//! - `-v`, `-vv` and `-vvv` log the HTTP exchanges on stderr: request line, response status and timings (DNS, connect, TLS, time to first byte), then the headers, then the bodies. The secrets are redacted unless `--show-secrets` is given: the authentication headers and parameters, the cookies, and the fields of the bodies such as `password`, `client_secret`, `token` or `refresh_token`.
+//! - `config` manages the settings file and the profiles: `get`, `set`, `unset`, `list`, `path`, `edit` and `profile add/remove/list/use`. The comments of the file are kept and the known settings (`base_url`, `timeout`...) are checked.
+//! - the settings are merged from the system file `/etc/<app>/settings.toml`, the user `settings.toml`, the project file `.<app>.toml` (found in the current directory or its parents; it only sets `default_profile`, `timeout`, `retries`, `cache` and `cache_ttl`: the credentials, TLS, proxy and `base_url` settings come from the user's files), the `--config` file and the environment variables. `config show --origin` tells where each value comes from.
+//! - the `GET` responses are cached on disk for the endpoints with a `cache_ttl`, or for all of them with the `cache` setting. An expired response is revalidated with its `ETag` or `Last-Modified` header, and the responses with `Cache-Control: no-store` or `private` are not stored. `--refresh` ignores the cached responses, `--no-cache` disables the cache and `cache clear`/`cache stats` manage it.
 //!
 //!
 //! ## Tutorial
@@ -89,6 +103,7 @@
 //! The minimal parameters are:
 //! - `route`, the target api route.
 //! - `cli_route`, the route transcipted as cli arguments. Each slash separate a subcommand.
+//!
 //! The other parameters can found in [`crud_api_endpoint::Api`] and [`crud_api_endpoint::Enpoint`] structs documentation.
 //!
 //! ```rust
@@ -157,6 +172,111 @@
 //! }
 //! ```
 //!
+//! ## Pagination
+//!
+//! List endpoints (`multiple_results`) can be paginated with the `pagination` parameter.
+//! The generated subcommand accepts the `--all`, `--page`, `--per-page` and `--max-items` arguments.
+//! With `--all`, the pages are fetched and concatenated. With `--max-items`, the pages are fetched until this number of results is reached.
+//!
+//! The `kind` of pagination describes how the next page is found:
+//! - `page` (default): the `page_param` query parameter is incremented until a page is empty.
+//! - `link`: the `Link: <...>; rel="next"` header is followed.
+//! - `cursor`: the cursor found at `cursor_path` in the body is sent in the `cursor_param` query parameter.
+//!
+//! When the results are not the body itself, `items_path` gives their location (ie. `data.items`). A `transform_from` type is deserialized from the results at `items_path`.
+//! The pagination stops when a page is the same as the previous one, when the next page was already fetched, or after 1000 pages.
+//! ```rust
+//! # use serde::{Deserialize, Serialize};
+//! # use crud_api::Api;
+//! #[derive(Api, Debug, Default, Deserialize, Serialize)]
+//! #[api(
+//!   endpoint(
+//!     route = "/posts",
+//!     cli_route = "/post",
+//!     multiple_results,
+//!     pagination(kind = "page", page_param = "_page", per_page_param = "_limit"),
+//!   ))]
+//! struct Post {
+//!   id: u32,
+//!   title: String,
+//! }
+//! ```
+//!
+//! ## Timeouts and Retries
+//!
+//! The requests time out after 30 seconds. The failed requests can be retried with an exponential backoff.
+//! The `Retry-After` header is honoured.
+//!
+//! - `timeout`: timeout in seconds. It should be positive.
+//! - `retries`: number of retries. Default: no retry.
+//! - `retry_on`: comma separated list of the statuses to retry. Default: `"429,502,503,504"`.
+//! - `retry_non_idempotent`: also retry the `POST` and `PATCH` requests. By default, only the idempotent methods are retried.
+//!
+//! `timeout`, `retries`, `retry_on` and `retry_non_idempotent` can be set for all the endpoints in the `ApiRun` attributes,
+//! and for each endpoint. The users can override `timeout` and `retries` with the `--timeout` and `--retries`
+//! arguments or in the settings.
+//! ```rust
+//! # use serde::{Deserialize, Serialize};
+//! # use crud_api::Api;
+//! #[derive(Api, Debug, Default, Deserialize, Serialize)]
+//! #[api(
+//!   endpoint(
+//!     route = "/posts",
+//!     cli_route = "/post",
+//!     multiple_results,
+//!     timeout = 60,
+//!     retries = 3,
+//!     retry_on = "502,503,429",
+//!   ))]
+//! struct Post {
+//!   id: u32,
+//!   title: String,
+//! }
+//! ```
+//!
+//! ## Authentification
+//!
+//! The authentification is implemented by the `Auth` struct in scope of `ApiRun`. Pick one of these crates:
+//! - [`crud-auth-no-auth`](../crud_auth_no_auth): no authentification.
+//! - [`crud-auth-bearer`](../crud_auth_bearer): bearer token.
+//! - [`crud-auth-basic`](../crud_auth_basic): HTTP Basic authentification. The password is prompted if needed.
+//! - [`crud-auth-api-key`](../crud_auth_api_key): API key in a header or in a query parameter.
+//! - [`crud-auth-oauth2`](../crud_auth_oauth2): OAuth2 client credentials, authorization code and device flows.
+//!
+//! The `auth` block of `ApiRun` sets the defaults of the authentification settings:
+//! ```rust
+//! # use crud_api::ApiRun;
+//! # use crud_auth::CrudAuth;
+//! # use crud_auth_no_auth::Auth;
+//! # use miette::{IntoDiagnostic, Result};
+//! #[derive(ApiRun)]
+//! #[api(
+//!   infos(base_url = "https://api.example.com"),
+//!   auth(api_key_header = "X-Api-Token")
+//! )]
+//! struct ExampleApi;
+//! ```
+//!
+//! The `login` block generates a `login` command. It prompts the user name and the password,
+//! sends them to the login endpoint and saves the token found at `token_field` in the response
+//! for the current profile. The token is saved by the authentification: `crud-auth-bearer`
+//! or `crud-auth-api-key`, the other ones refuse the login. The password is redacted from
+//! `--dry-run` and `-vvv`, and `result_ok_status` sets the expected status of the response
+//! (default: `OK`). `logout` removes the token and `whoami` prints the response of the
+//! `identity_route` endpoint:
+//! ```rust
+//! # use crud_api::ApiRun;
+//! # use crud_auth::CrudAuth;
+//! # use crud_auth_bearer::Auth;
+//! # use miette::{IntoDiagnostic, Result};
+//! #[derive(ApiRun)]
+//! #[api(
+//!   infos(base_url = "https://api.example.com"),
+//!   login(route = "/auth/login", token_field = "data.token", identity_route = "/me")
+//! )]
+//! struct ExampleApi;
+//! ```
+//!
 //! ## Output Customization
 //!
 //! ### Tables
@@ -175,29 +295,85 @@ use async_trait::async_trait;
 use clap::{ArgMatches, Command, Id};
 pub use crud_api_derive::*;
 use crud_pretty_struct::PrettyPrint;
-#[cfg(any(feature = "json", feature = "toml", feature = "yaml", feature = "csv"))]
-use crud_tidy_viewer::{display_table, TableConfig};
+#[cfg(any(
+  feature = "json",
+  feature = "toml",
+  feature = "yaml",
+  feature = "csv",
+  feature = "template"
+))]
+use crud_tidy_viewer::{
+  write_ascii_table, write_html_table, write_markdown_table, write_table, TableConfig,
+};
 use formats::OutputFormat;
 #[doc(hidden)]
 pub use formats::{
   clap_match_input_from_file, clap_match_output_format, clap_match_template, clap_output_format_decl,
+  clap_output_template_decl, format_date,
 };
 use miette::{IntoDiagnostic, Result};
+#[doc(hidden)]
+pub use pagination::clap_match_pagination;
+#[cfg(any(
+  feature = "json",
+  feature = "toml",
+  feature = "yaml",
+  feature = "csv",
+  feature = "template"
+))]
+#[doc(hidden)]
+pub use rows::Rows;
+#[doc(hidden)]
+pub use select::{clap_match_selection, Selection};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{fmt::Debug, marker::PhantomData};
+pub use upload::UploadFile;
 
 extern crate crud_api_derive;
 #[doc(hidden)]
+pub mod body;
+#[doc(hidden)]
+pub mod cache;
+#[doc(hidden)]
 pub mod cli;
+mod client;
 #[doc(hidden)]
 pub mod completions;
 #[doc(hidden)]
+pub mod config_command;
+#[doc(hidden)]
+pub mod debug;
+#[doc(hidden)]
+pub mod decode;
+mod download;
+#[doc(hidden)]
 pub mod error;
 mod formats;
 #[doc(hidden)]
 pub mod http;
 #[doc(hidden)]
+pub mod login;
+#[doc(hidden)]
+pub mod pagination;
+mod progress;
+mod proxy;
+#[doc(hidden)]
+pub mod retry;
+#[cfg(any(
+  feature = "json",
+  feature = "toml",
+  feature = "yaml",
+  feature = "csv",
+  feature = "template"
+))]
+mod rows;
+mod select;
+#[doc(hidden)]
 pub mod settings;
+#[cfg(feature = "template")]
+mod template;
+mod tls;
+mod upload;
 
 #[doc(hidden)]
 pub struct ApiInputOptions {
@@ -211,6 +387,13 @@ pub trait ApiInput {
   fn from_clap_matches(matches: &ArgMatches) -> Result<Self>
   where
     Self: Sized;
+  /// The `UploadFile` fields.
+  fn file_fields() -> Vec<&'static str>
+  where
+    Self: Sized,
+  {
+    vec![]
+  }
 }
 
 #[doc(hidden)]
@@ -225,7 +408,9 @@ pub trait Query {
   where
     P: Send + Serialize + Debug,
     T: TryInto<R, Error = String> + DeserializeOwned + Send,
-    R: Send + DeserializeOwned + Debug + Default,
+    // The pages are concatenated as JSON values: the transformed results of each page are
+    // serialized before being deserialized as the whole list.
+    R: Send + Serialize + DeserializeOwned + Debug + Default,
     Q: Send + Serialize + Debug;
   async fn stream<P, Q>(
     &self,
@@ -244,15 +429,29 @@ pub trait Api {
   fn to_table(&self) -> Result<Vec<String>>;
   fn to_output(&self) -> Result<String>;
 
-  #[cfg(any(feature = "json", feature = "toml", feature = "yaml", feature = "csv"))]
-  fn output(&self, format: Option<OutputFormat>) -> Result<()>
+  #[cfg(any(
+    feature = "json",
+    feature = "toml",
+    feature = "yaml",
+    feature = "csv",
+    feature = "template"
+  ))]
+  fn output(&self, format: Option<OutputFormat>, selection: &Selection) -> Result<()>
   where
-    Self: Serialize + Debug,
+    Self: Sized + Serialize + Debug,
   {
+    if !selection.is_empty() {
+      return output_selection(std::slice::from_ref(self), format, selection, true);
+    }
     let out = match format {
       Some(format) => match format {
         #[cfg(feature = "json")]
         OutputFormat::Json => Some(serde_json::to_string_pretty(self).into_diagnostic()?),
+        #[cfg(feature = "json")]
+        OutputFormat::Ndjson => Some(format!(
+          "{}\n",
+          serde_json::to_string(self).into_diagnostic()?
+        )),
         #[cfg(feature = "toml")]
         OutputFormat::Toml => Some(toml::to_string(self).into_diagnostic()?),
         #[cfg(feature = "yaml")]
@@ -274,6 +473,18 @@ pub trait Api {
           wtr.flush().into_diagnostic()?;
           None
         }
+        #[cfg(feature = "template")]
+        OutputFormat::Template(template) => {
+          print!(
+            "{}",
+            template::render(&template, std::slice::from_ref(self))?
+          );
+          None
+        }
+        format @ (OutputFormat::Markdown | OutputFormat::Html | OutputFormat::AsciiTable) => {
+          print_table(&results_table(std::slice::from_ref(self))?, Some(&format))?;
+          None
+        }
       },
       None => Some(self.to_output()?),
     };
@@ -288,24 +499,45 @@ pub trait Api {
     not(feature = "json"),
     not(feature = "toml"),
     not(feature = "yaml"),
-    not(feature = "csv")
+    not(feature = "csv"),
+    not(feature = "template")
   ))]
-  fn output(&self, _format: Option<OutputFormat>) -> Result<()>
+  fn output(&self, _format: Option<OutputFormat>, _selection: &Selection) -> Result<()>
   where
-    Self: Serialize + Debug,
+    Self: Sized + Serialize + Debug,
   {
     Ok(())
   }
 
-  #[cfg(any(feature = "json", feature = "toml", feature = "yaml", feature = "csv"))]
-  fn output_multiple(results: &Vec<Self>, format: Option<OutputFormat>) -> Result<()>
+  #[cfg(any(
+    feature = "json",
+    feature = "toml",
+    feature = "yaml",
+    feature = "csv",
+    feature = "template"
+  ))]
+  fn output_multiple(
+    results: &[Self],
+    format: Option<OutputFormat>,
+    selection: &Selection,
+  ) -> Result<()>
   where
     Self: Sized + Serialize + Debug,
   {
+    if !selection.is_empty() {
+      return output_selection(results, format, selection, false);
+    }
     let out = match format {
       Some(format) => match format {
         #[cfg(feature = "json")]
         OutputFormat::Json => Some(serde_json::to_string_pretty(results).into_diagnostic()?),
+        #[cfg(feature = "json")]
+        OutputFormat::Ndjson => {
+          for result in results {
+            println!("{}", serde_json::to_string(result).into_diagnostic()?);
+          }
+          None
+        }
         #[cfg(feature = "toml")]
         OutputFormat::Toml => Some(toml::to_string(results).into_diagnostic()?),
         #[cfg(feature = "yaml")]
@@ -331,17 +563,21 @@ pub trait Api {
           wtr.flush().into_diagnostic()?;
           None
         }
+        #[cfg(feature = "template")]
+        OutputFormat::Template(template) => {
+          print!("{}", template::render(&template, results)?);
+          None
+        }
+        format @ (OutputFormat::Markdown | OutputFormat::Html | OutputFormat::AsciiTable) => {
+          if !results.is_empty() {
+            print_table(&results_table(results)?, Some(&format))?;
+          }
+          None
+        }
       },
       None => {
         if !results.is_empty() {
-          let mut table = vec![results.iter().next().unwrap().to_table_header()];
-          table.append(
-            &mut results
-              .iter()
-              .map(|row| row.to_table().expect("Formating data table"))
-              .collect(),
-          ); // FIXME: replace expect by something better from miette
-          display_table(&table, TableConfig::default());
+          print_table(&results_table(results)?, None)?;
         }
         None
       }
@@ -356,9 +592,14 @@ pub trait Api {
     not(feature = "json"),
     not(feature = "toml"),
     not(feature = "yaml"),
-    not(feature = "csv")
+    not(feature = "csv"),
+    not(feature = "template")
   ))]
-  fn output_multiple(_results: &Vec<Self>, _format: Option<OutputFormat>) -> Result<()>
+  fn output_multiple(
+    _results: &[Self],
+    _format: Option<OutputFormat>,
+    _selection: &Selection,
+  ) -> Result<()>
   where
     Self: Sized + Serialize + Debug,
   {
@@ -366,6 +607,248 @@ pub trait Api {
   }
 }
 
+/// Print the results selected by `--filter`, `--sort` and `--fields`. A `single` result is
+/// printed as an object, or not at all when it's filtered out.
+#[cfg(any(
+  feature = "json",
+  feature = "toml",
+  feature = "yaml",
+  feature = "csv",
+  feature = "template"
+))]
+fn output_selection<T: Api + Serialize + Debug>(
+  results: &[T],
+  format: Option<OutputFormat>,
+  selection: &Selection,
+  single: bool,
+) -> Result<()> {
+  let selected = selection.select(results)?;
+  if single && selected.is_empty() {
+    return Ok(());
+  }
+  let items = selection.items(results, &selected);
+  let value = if single {
+    selection.query(&items[0])?
+  } else {
+    selection.query(&items)?
+  };
+  if let Some(value) = value {
+    return output_value(&value, format);
+  }
+  let out = match format {
+    Some(format) => match format {
+      #[cfg(feature = "json")]
+      OutputFormat::Json if single => {
+        Some(serde_json::to_string_pretty(&items[0]).into_diagnostic()?)
+      }
+      #[cfg(feature = "json")]
+      OutputFormat::Json => Some(serde_json::to_string_pretty(&items).into_diagnostic()?),
+      #[cfg(feature = "json")]
+      OutputFormat::Ndjson => {
+        for item in &items {
+          println!("{}", serde_json::to_string(item).into_diagnostic()?);
+        }
+        None
+      }
+      #[cfg(feature = "toml")]
+      OutputFormat::Toml if single => Some(toml::to_string(&items[0]).into_diagnostic()?),
+      #[cfg(feature = "toml")]
+      OutputFormat::Toml => Some(toml::to_string(&items).into_diagnostic()?),
+      #[cfg(feature = "yaml")]
+      OutputFormat::Yaml if single => Some(serde_yaml::to_string(&items[0]).into_diagnostic()?),
+      #[cfg(feature = "yaml")]
+      OutputFormat::Yaml => Some(serde_yaml::to_string(&items).into_diagnostic()?),
+      #[cfg(feature = "csv")]
+      OutputFormat::Csv => {
+        select::write_csv(
+          &mut csv::Writer::from_writer(std::io::stdout()),
+          &items,
+          true,
+        )?;
+        None
+      }
+      #[cfg(feature = "csv")]
+      OutputFormat::Tsv => {
+        let mut wtr = csv::WriterBuilder::new()
+          .delimiter(b'\t')
+          .quote_style(csv::QuoteStyle::NonNumeric)
+          .from_writer(std::io::stdout());
+        select::write_csv(&mut wtr, &items, true)?;
+        None
+      }
+      #[cfg(feature = "template")]
+      OutputFormat::Template(template) => {
+        print!("{}", template::render(&template, &items)?);
+        None
+      }
+      format @ (OutputFormat::Markdown | OutputFormat::Html | OutputFormat::AsciiTable) => {
+        print_table(
+          &selection_table(results, selection, &selected)?,
+          Some(&format),
+        )?;
+        None
+      }
+    },
+    None if single && !selection.has_fields() => Some(results[selected[0].0].to_output()?),
+    None => {
+      if !selected.is_empty() {
+        print_table(&selection_table(results, selection, &selected)?, None)?;
+      }
+      None
+    }
+  };
+  match out {
+    Some(out) if single => print!("{out}"),
+    Some(out) => println!("{out}"),
+    None => {}
+  }
+  Ok(())
+}
+
+/// Print the JSON value of a `--query`. The arrays and objects are printed as tables.
+#[cfg(any(
+  feature = "json",
+  feature = "toml",
+  feature = "yaml",
+  feature = "csv",
+  feature = "template"
+))]
+fn output_value(value: &serde_json::Value, format: Option<OutputFormat>) -> Result<()> {
+  let out = match format {
+    Some(format) => match format {
+      #[cfg(feature = "json")]
+      OutputFormat::Json => Some(serde_json::to_string_pretty(value).into_diagnostic()?),
+      #[cfg(feature = "json")]
+      OutputFormat::Ndjson => {
+        for value in match value {
+          serde_json::Value::Array(values) => values.iter().collect(),
+          value => vec![value],
+        } {
+          println!("{}", serde_json::to_string(value).into_diagnostic()?);
+        }
+        None
+      }
+      #[cfg(feature = "toml")]
+      OutputFormat::Toml => Some(toml::to_string(value).into_diagnostic()?),
+      #[cfg(feature = "yaml")]
+      OutputFormat::Yaml => Some(serde_yaml::to_string(value).into_diagnostic()?),
+      #[cfg(feature = "csv")]
+      OutputFormat::Csv => {
+        select::write_value_csv(&mut csv::Writer::from_writer(std::io::stdout()), value)?;
+        None
+      }
+      #[cfg(feature = "csv")]
+      OutputFormat::Tsv => {
+        let mut wtr = csv::WriterBuilder::new()
+          .delimiter(b'\t')
+          .quote_style(csv::QuoteStyle::NonNumeric)
+          .from_writer(std::io::stdout());
+        select::write_value_csv(&mut wtr, value)?;
+        None
+      }
+      #[cfg(feature = "template")]
+      OutputFormat::Template(template) => {
+        let rendered = match value {
+          serde_json::Value::Array(values) => template::render(&template, values)?,
+          value => template::render(&template, std::slice::from_ref(value))?,
+        };
+        print!("{rendered}");
+        None
+      }
+      format @ (OutputFormat::Markdown | OutputFormat::Html | OutputFormat::AsciiTable) => {
+        let table = select::value_table(value);
+        if table.len() > 1 {
+          print_table(&table, Some(&format))?;
+        }
+        None
+      }
+    },
+    None => match value {
+      serde_json::Value::Array(_) | serde_json::Value::Object(_) => {
+        let table = select::value_table(value);
+        if table.len() > 1 {
+          print_table(&table, None)?;
+        }
+        None
+      }
+      serde_json::Value::String(text) => Some(text.clone()),
+      value => Some(value.to_string()),
+    },
+  };
+  if let Some(out) = out {
+    println!("{out}");
+  }
+  Ok(())
+}
+
+/// The table of the results, with their header.
+#[cfg(any(
+  feature = "json",
+  feature = "toml",
+  feature = "yaml",
+  feature = "csv",
+  feature = "template"
+))]
+fn results_table<T: Api>(results: &[T]) -> Result<Vec<Vec<String>>> {
+  let mut table = vec![results
+    .first()
+    .map(Api::to_table_header)
+    .unwrap_or_default()];
+  for result in results {
+    table.push(result.to_table()?);
+  }
+  Ok(table)
+}
+
+/// The table of the selected results, with the columns of `--fields`.
+#[cfg(any(
+  feature = "json",
+  feature = "toml",
+  feature = "yaml",
+  feature = "csv",
+  feature = "template"
+))]
+fn selection_table<T: Api>(
+  results: &[T],
+  selection: &Selection,
+  selected: &[(usize, serde_json::Value)],
+) -> Result<Vec<Vec<String>>> {
+  if selection.has_fields() {
+    return Ok(selection.table(selected));
+  }
+  let mut table = vec![selected
+    .first()
+    .map(|(first, _)| results[*first].to_table_header())
+    .unwrap_or_default()];
+  for (i, _) in selected {
+    table.push(results[*i].to_table()?);
+  }
+  Ok(table)
+}
+
+/// Print a table with its header: a Markdown, HTML or ASCII table, or the colored table of
+/// the terminal.
+#[cfg(any(
+  feature = "json",
+  feature = "toml",
+  feature = "yaml",
+  feature = "csv",
+  feature = "template"
+))]
+fn print_table(table: &[Vec<String>], format: Option<&OutputFormat>) -> Result<()> {
+  let mut out = std::io::stdout().lock();
+  let written = match format {
+    Some(OutputFormat::Markdown) => write_markdown_table(&mut out, table),
+    Some(OutputFormat::Html) => write_html_table(&mut out, table),
+    Some(OutputFormat::AsciiTable) => write_ascii_table(&mut out, table),
+    _ => write_table(&mut out, table, TableConfig::default()),
+  };
+  match written {
+    Err(error) if error.kind() != std::io::ErrorKind::BrokenPipe => Err(error).into_diagnostic(),
+    _ => Ok(()),
+  }
+}
+
 /// An empty response. Use it in `result_struct`
 #[derive(Debug, Default, Deserialize, Serialize, PrettyPrint)]
 pub struct EmptyResponse {}
@@ -383,6 +866,25 @@ impl Api for EmptyResponse {
   }
 }
 
+/// A plain text response. Use it in `result_struct` with `response_format = "text"`.
+impl Api for String {
+  fn to_table_header(&self) -> Vec<String> {
+    vec![]
+  }
+
+  fn to_table(&self) -> Result<Vec<String>> {
+    Ok(vec![self.clone()])
+  }
+
+  fn to_output(&self) -> Result<String> {
+    Ok(if self.ends_with('\n') {
+      self.clone()
+    } else {
+      format!("{self}\n")
+    })
+  }
+}
+
 #[derive(Deserialize)]
 pub struct DummyTryFrom;
 
@@ -400,6 +902,20 @@ impl<T> TryFrom<DummyTryFrom> for Vec<T> {
   }
 }
 
+impl TryFrom<DummyTryFrom> for String {
+  type Error = String;
+  fn try_from(_value: DummyTryFrom) -> std::result::Result<Self, Self::Error> {
+    Err(String::new())
+  }
+}
+
+impl TryFrom<DummyTryFrom> for serde_json::Value {
+  type Error = String;
+  fn try_from(_value: DummyTryFrom) -> std::result::Result<Self, Self::Error> {
+    Err(String::new())
+  }
+}
+
 #[cfg(test)]
 mod tests {
   #[test]
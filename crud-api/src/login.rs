@@ -0,0 +1,160 @@
+use crate::{
+  cli::read_secret,
+  http::{HTTPApi, Header, HttpClient},
+  pagination::json_pointer,
+  DummyTryFrom, Query,
+};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use crud_auth::{prompt_secret, CrudAuth};
+use hyper::{Method, StatusCode};
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+use serde_json::{Map, Value};
+use std::{collections::HashMap, io::stdout, marker::PhantomData};
+
+/// The login endpoint declared by the `login` block of `ApiRun`.
+pub struct Login<'a> {
+  pub route: &'a str,
+  pub method: &'a str,
+  pub user_field: &'a str,
+  pub password_field: &'a str,
+  pub token_field: &'a str,
+  /// Expected status of the login response.
+  pub status: StatusCode,
+  pub identity_route: Option<&'a str>,
+}
+
+/// Add the `login`, `logout` and `whoami` commands.
+///
+/// The commands already added by the authentification are completed.
+pub fn clap_login(mut app: Command, login: &Login) -> Command {
+  let username = Arg::new("username")
+    .long("username")
+    .action(ArgAction::Set)
+    .help("User name. Prompted if omitted");
+  app = if app.find_subcommand("login").is_some() {
+    app.mut_subcommand("login", |command| command.arg(username))
+  } else {
+    app.subcommand(
+      Command::new("login")
+        .about("Log in and save the credentials")
+        .arg(username),
+    )
+  };
+  if app.find_subcommand("logout").is_none() {
+    app = app.subcommand(Command::new("logout").about("Remove the saved credentials"));
+  }
+  if login.identity_route.is_some() {
+    app = app.subcommand(Command::new("whoami").about("Show the logged user"));
+  }
+  app
+}
+
+/// Send the user name and the password to the login endpoint and save the token of
+/// the response with [CrudAuth::store_token].
+///
+/// The password is read from the standard input if it's not a terminal. It's redacted from the
+/// printed requests.
+pub async fn login(
+  client: &HttpClient,
+  base_url: &str,
+  login: &Login<'_>,
+  matches: &ArgMatches,
+  auth: &(dyn CrudAuth + Send + Sync),
+  headers: &Vec<Header<'_>>,
+) -> Result<()> {
+  if !auth.stores_token() {
+    return Err(miette!(
+      help =
+        "Use a token authentification (crud-auth-bearer or crud-auth-api-key) with the `login` block",
+      "The authentification can't save the token of the login endpoint"
+    ));
+  }
+  let password = read_secret()?;
+  let user = match matches.get_one::<String>("username") {
+    Some(user) => user.clone(),
+    None => prompt_user()?,
+  };
+  let password = match password {
+    Some(password) => password,
+    None => prompt_secret(&format!("Password for {user}"))?,
+  };
+  let payload = Value::Object(Map::from_iter([
+    (login.user_field.to_string(), Value::String(user)),
+    (login.password_field.to_string(), Value::String(password)),
+  ]));
+  let response: Value = HTTPApi::new(
+    client,
+    format!("{base_url}{}", login.route),
+    Method::from_bytes(login.method.as_bytes()).into_diagnostic()?,
+    login.status,
+    &HashMap::new(),
+    None,
+    headers,
+  )
+  .secret_fields(&[login.password_field])
+  .query(Some(payload), None::<()>, None::<PhantomData<DummyTryFrom>>)
+  .await?;
+  let token = token(&response, login.token_field)?;
+  auth.store_token(token).await
+}
+
+/// Print the response of the identity endpoint.
+pub async fn whoami(
+  client: &HttpClient,
+  base_url: &str,
+  login: &Login<'_>,
+  auth: &(dyn CrudAuth + Send + Sync),
+  headers: &Vec<Header<'_>>,
+) -> Result<()> {
+  let route = login
+    .identity_route
+    .ok_or_else(|| miette!("No identity endpoint"))?;
+  let identity: Value = HTTPApi::new(
+    client,
+    format!("{base_url}{route}"),
+    Method::GET,
+    StatusCode::OK,
+    &HashMap::new(),
+    Some(auth),
+    headers,
+  )
+  .query(None::<()>, None::<()>, None::<PhantomData<DummyTryFrom>>)
+  .await?;
+  serde_json::to_writer_pretty(stdout(), &identity).into_diagnostic()?;
+  println!();
+  Ok(())
+}
+
+fn prompt_user() -> Result<String> {
+  let term = console::Term::stderr();
+  term.write_str("User: ").into_diagnostic()?;
+  term
+    .read_line()
+    .into_diagnostic()
+    .wrap_err("Can't read the user")
+}
+
+/// The token at the dotted `path` of the login response.
+fn token(response: &Value, path: &str) -> Result<String> {
+  match response.pointer(&json_pointer(path)) {
+    Some(Value::String(token)) => Ok(token.clone()),
+    _ => Err(miette!(
+      help = "Check the `token_field` of the login block",
+      "No token `{path}` in the login response"
+    )),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::token;
+  use serde_json::json;
+
+  #[test]
+  fn test_token() {
+    let response = json!({"data": {"token": "abc", "expires": 3600}});
+    assert_eq!(token(&response, "data.token").unwrap(), "abc");
+    assert!(token(&response, "data.expires").is_err());
+    assert!(token(&response, "token").is_err());
+  }
+}
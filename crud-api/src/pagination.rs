@@ -0,0 +1,313 @@
+use clap::ArgMatches;
+use hyper::{header::LINK, HeaderMap};
+use miette::{IntoDiagnostic, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// How the next page is found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaginationKind {
+  /// Increment the page number until a page is empty or incomplete.
+  Page,
+  /// Follow the `Link: <...>; rel="next"` header.
+  Link,
+  /// Send the cursor found in the response body.
+  Cursor,
+}
+
+/// Pagination of an endpoint. See `crud_api_endpoint::Pagination`.
+#[derive(Debug, Clone)]
+pub struct Pagination<'a> {
+  pub kind: PaginationKind,
+  pub page_param: &'a str,
+  pub per_page_param: &'a str,
+  pub cursor_param: &'a str,
+  pub cursor_path: &'a str,
+  pub items_path: &'a str,
+}
+
+/// Maximum number of pages fetched by `--all`.
+pub(crate) const MAX_PAGES: usize = 1000;
+
+/// Pagination arguments given in the command line.
+#[derive(Debug, Clone, Default)]
+pub struct PageArgs {
+  /// Fetch all the pages.
+  pub all: bool,
+  /// The page to fetch.
+  pub page: Option<u64>,
+  /// Number of results per page.
+  pub per_page: Option<u64>,
+  /// Stop after this number of results. The pages are fetched until it's reached.
+  pub max_items: Option<usize>,
+}
+
+pub fn clap_match_pagination(argmatches: &ArgMatches) -> PageArgs {
+  PageArgs {
+    all: argmatches
+      .try_get_one::<bool>("pagination_all")
+      .ok()
+      .flatten()
+      .cloned()
+      .unwrap_or_default(),
+    page: argmatches
+      .try_get_one::<u64>("pagination_page")
+      .ok()
+      .flatten()
+      .cloned(),
+    per_page: argmatches
+      .try_get_one::<u64>("pagination_per_page")
+      .ok()
+      .flatten()
+      .cloned(),
+    max_items: argmatches
+      .try_get_one::<usize>("pagination_max_items")
+      .ok()
+      .flatten()
+      .cloned(),
+  }
+}
+
+impl Pagination<'_> {
+  /// URI of the first page.
+  pub(crate) fn first_page(&self, uri: &str, args: &PageArgs) -> Result<String> {
+    let mut uri = uri.to_string();
+    if let Some(per_page) = args.per_page {
+      uri = set_query_param(&uri, self.per_page_param, &per_page.to_string())?;
+    }
+    if self.kind != PaginationKind::Cursor {
+      if let Some(page) = args.page {
+        uri = set_query_param(&uri, self.page_param, &page.to_string())?;
+      }
+    }
+    Ok(uri)
+  }
+
+  /// The results of a page: the value at `items_path`.
+  pub(crate) fn results(&self, page: &Value) -> Value {
+    if self.items_path.is_empty() {
+      page.clone()
+    } else {
+      page
+        .pointer(&json_pointer(self.items_path))
+        .cloned()
+        .unwrap_or(Value::Null)
+    }
+  }
+
+  /// The list of the results of a page.
+  pub(crate) fn items(&self, results: Value) -> Result<Vec<Value>> {
+    match results {
+      Value::Array(items) => Ok(items),
+      Value::Null => Ok(vec![]),
+      _ => Err(miette::miette!(
+        "The results at '{}' are not a list",
+        self.items_path
+      )),
+    }
+  }
+
+  /// URI of the next page or `None` if `uri` is the last page.
+  pub(crate) fn next_page(
+    &self,
+    uri: &str,
+    args: &PageArgs,
+    headers: &HeaderMap,
+    page: &Value,
+    count: usize,
+  ) -> Result<Option<String>> {
+    match self.kind {
+      PaginationKind::Page => {
+        if count == 0
+          || args
+            .per_page
+            .is_some_and(|per_page| (count as u64) < per_page)
+        {
+          Ok(None)
+        } else {
+          let current = query_param(uri, self.page_param)
+            .and_then(|page| page.parse::<u64>().ok())
+            .unwrap_or(1);
+          Ok(Some(set_query_param(
+            uri,
+            self.page_param,
+            &(current + 1).to_string(),
+          )?))
+        }
+      }
+      PaginationKind::Link => Ok(
+        headers
+          .get_all(LINK)
+          .iter()
+          .filter_map(|link| link.to_str().ok())
+          .find_map(next_link)
+          .map(|next| absolute_uri(uri, &next)),
+      ),
+      PaginationKind::Cursor => match page.pointer(&json_pointer(self.cursor_path)) {
+        Some(Value::String(cursor)) if !cursor.is_empty() => {
+          Ok(Some(set_query_param(uri, self.cursor_param, cursor)?))
+        }
+        Some(Value::Number(cursor)) => Ok(Some(set_query_param(
+          uri,
+          self.cursor_param,
+          &cursor.to_string(),
+        )?)),
+        _ => Ok(None),
+      },
+    }
+  }
+}
+
+/// Convert a dotted path (`meta.next`) to a JSON pointer (`/meta/next`).
+pub(crate) fn json_pointer(path: &str) -> String {
+  path
+    .split('.')
+    .fold(String::new(), |pointer, field| pointer + "/" + field)
+}
+
+/// Find the `rel="next"` target in a `Link` header. The targets and the quoted parameters
+/// can contain commas and semicolons, and `rel` can hold several relations.
+fn next_link(header: &str) -> Option<String> {
+  split_unquoted(header, ',').into_iter().find_map(|link| {
+    let mut parts = split_unquoted(link, ';').into_iter();
+    let target = parts.next()?.trim().strip_prefix('<')?.strip_suffix('>')?;
+    parts
+      .any(|param| match param.split_once('=') {
+        Some((name, value)) if name.trim().eq_ignore_ascii_case("rel") => value
+          .trim()
+          .trim_matches('"')
+          .split_ascii_whitespace()
+          .any(|rel| rel.eq_ignore_ascii_case("next")),
+        _ => false,
+      })
+      .then(|| target.to_string())
+  })
+}
+
+/// Split `text` on the `separator` outside of the `<...>` targets and the quoted strings.
+fn split_unquoted(text: &str, separator: char) -> Vec<&str> {
+  let mut parts = vec![];
+  let (mut start, mut in_target, mut in_quotes) = (0, false, false);
+  for (i, c) in text.char_indices() {
+    match c {
+      '<' if !in_quotes => in_target = true,
+      '>' if !in_quotes => in_target = false,
+      '"' if !in_target => in_quotes = !in_quotes,
+      c if c == separator && !in_target && !in_quotes => {
+        parts.push(&text[start..i]);
+        start = i + c.len_utf8();
+      }
+      _ => {}
+    }
+  }
+  parts.push(&text[start..]);
+  parts
+}
+
+/// Resolve a link relatively to `uri`.
+fn absolute_uri(uri: &str, link: &str) -> String {
+  if link.contains("://") {
+    link.to_string()
+  } else if let Some(path) = link.strip_prefix('/') {
+    let origin_end = uri
+      .find("://")
+      .and_then(|scheme_end| {
+        uri[scheme_end + 3..]
+          .find('/')
+          .map(|path_start| scheme_end + 3 + path_start)
+      })
+      .unwrap_or(uri.len());
+    format!("{}/{}", &uri[..origin_end], path)
+  } else {
+    let base = uri.split('?').next().unwrap_or(uri);
+    let dir_end = base.rfind('/').map(|i| i + 1).unwrap_or(base.len());
+    format!("{}{}", &base[..dir_end], link)
+  }
+}
+
+fn query_param(uri: &str, key: &str) -> Option<String> {
+  let (_, query) = uri.split_once('?')?;
+  serde_qs::from_str::<HashMap<String, String>>(query)
+    .ok()?
+    .remove(key)
+}
+
+/// Set (or replace) a query parameter of `uri`.
+fn set_query_param(uri: &str, key: &str, value: &str) -> Result<String> {
+  let (path, query) = uri.split_once('?').unwrap_or((uri, ""));
+  let mut params: Vec<&str> = query
+    .split('&')
+    .filter(|param| !param.is_empty() && param.split('=').next() != Some(key))
+    .collect();
+  let param = serde_qs::to_string(&HashMap::from([(key, value)])).into_diagnostic()?;
+  params.push(&param);
+  Ok(format!("{}?{}", path, params.join("&")))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{absolute_uri, json_pointer, next_link, query_param, set_query_param};
+
+  #[test]
+  fn test_json_pointer() {
+    assert_eq!(json_pointer("next"), "/next");
+    assert_eq!(json_pointer("meta.next_cursor"), "/meta/next_cursor");
+  }
+
+  #[test]
+  fn test_next_link() {
+    assert_eq!(
+      next_link(
+        "<https://gitlab.com/api/v4/issues?page=1>; rel=\"prev\", <https://gitlab.com/api/v4/issues?page=3>; rel=\"next\""
+      ),
+      Some("https://gitlab.com/api/v4/issues?page=3".to_string())
+    );
+    assert_eq!(
+      next_link("<https://gitlab.com/api/v4/issues?page=1>; rel=\"first\""),
+      None
+    );
+    assert_eq!(
+      next_link(
+        "<https://host/issues?labels=bug,ui&page=1>; title=\"first; page, 1\"; rel=\"prev\", \
+         <https://host/issues?labels=bug,ui&page=2>; REL=\"last next\""
+      ),
+      Some("https://host/issues?labels=bug,ui&page=2".to_string())
+    );
+    assert_eq!(
+      next_link("<https://host/issues?page=2>; rel=nextpage"),
+      None
+    );
+  }
+
+  #[test]
+  fn test_absolute_uri() {
+    assert_eq!(
+      absolute_uri("https://host/api/issues?page=1", "https://other/x"),
+      "https://other/x"
+    );
+    assert_eq!(
+      absolute_uri("https://host/api/issues?page=1", "/api/issues?page=2"),
+      "https://host/api/issues?page=2"
+    );
+    assert_eq!(
+      absolute_uri("https://host/api/issues?page=1", "issues?page=2"),
+      "https://host/api/issues?page=2"
+    );
+  }
+
+  #[test]
+  fn test_set_query_param() {
+    assert_eq!(
+      set_query_param("http://host/issues", "page", "2").unwrap(),
+      "http://host/issues?page=2"
+    );
+    assert_eq!(
+      set_query_param("http://host/issues?state=opened&page=1", "page", "2").unwrap(),
+      "http://host/issues?state=opened&page=2"
+    );
+    assert_eq!(
+      query_param("http://host/issues?state=opened&page=4", "page"),
+      Some("4".to_string())
+    );
+  }
+}
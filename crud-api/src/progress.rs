@@ -0,0 +1,40 @@
+use indicatif::{ProgressBar, ProgressFinish, ProgressStyle};
+use miette::{IntoDiagnostic, Result};
+use std::time::Duration;
+
+/// Progress of a transfer. It's a spinner when the length of the transfer is unknown.
+///
+/// The progress is cleared when the bar is dropped.
+pub(crate) fn progress_bar(len: Option<u64>, message: &'static str) -> Result<ProgressBar> {
+  let bar = match len {
+    Some(len) => ProgressBar::new(len).with_style(
+      ProgressStyle::default_bar()
+        .template("{msg} [{bar:30.blue}] {bytes:>12}/{total_bytes} @ {bytes_per_sec:15} (eta {eta})")
+        .into_diagnostic()?
+        .progress_chars("=> "),
+    ),
+    None => {
+      let bar = ProgressBar::new_spinner().with_style(
+        ProgressStyle::default_spinner()
+          .template("{spinner:.blue} {msg} {bytes:>12} @ {bytes_per_sec:15} ({elapsed})")
+          .into_diagnostic()?
+          .tick_strings(&[
+            "▹▹▹▹▹",
+            "▸▹▹▹▹",
+            "▹▸▹▹▹",
+            "▹▹▸▹▹",
+            "▹▹▹▸▹",
+            "▹▹▹▹▸",
+            "▪▪▪▪▪",
+          ]),
+      );
+      bar.enable_steady_tick(Duration::from_millis(150));
+      bar
+    }
+  };
+  Ok(
+    bar
+      .with_message(message)
+      .with_finish(ProgressFinish::AndClear),
+  )
+}
@@ -0,0 +1,202 @@
+use crate::settings::get_settings;
+use clap::ArgMatches;
+use config::Config;
+use hyper::{header::RETRY_AFTER, HeaderMap, Method, StatusCode};
+use miette::{IntoDiagnostic, Result, WrapErr};
+use std::{
+  collections::hash_map::RandomState,
+  hash::{BuildHasher, Hasher},
+  num::NonZeroU64,
+  time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Timeout of a request when none is configured.
+const DEFAULT_TIMEOUT: u64 = 30;
+/// Statuses retried when `retry_on` is not configured.
+const DEFAULT_RETRY_ON: [u16; 4] = [429, 502, 503, 504];
+/// First delay of the exponential backoff.
+const BACKOFF_BASE: Duration = Duration::from_millis(500);
+/// Maximum delay between two attempts.
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/// Timeout and retries of the requests.
+///
+/// The unset values are taken from a less specific policy with [RetryPolicy::or]:
+/// command line arguments and settings, then the endpoint, then the application.
+#[derive(Debug, Clone, Default)]
+pub struct RetryPolicy {
+  /// Timeout of a request, in seconds.
+  pub timeout: Option<u64>,
+  /// Number of retries after the first attempt.
+  pub retries: Option<u32>,
+  /// HTTP statuses that are retried.
+  pub retry_on: Option<Vec<u16>>,
+  /// Also retry the non idempotent methods (`POST`, `PATCH`).
+  pub retry_non_idempotent: Option<bool>,
+}
+
+impl RetryPolicy {
+  /// Read the `timeout` and `retries` settings and arguments.
+  pub(crate) fn from_settings(settings: &Config, matches: &ArgMatches) -> Result<RetryPolicy> {
+    Ok(RetryPolicy {
+      timeout: get_settings(settings, matches, "timeout")
+        .ok()
+        .map(|timeout| timeout.parse::<NonZeroU64>())
+        .transpose()
+        .into_diagnostic()
+        .wrap_err("The timeout should be a positive number of seconds")?
+        .map(NonZeroU64::get),
+      retries: get_settings(settings, matches, "retries")
+        .ok()
+        .map(|retries| retries.parse())
+        .transpose()
+        .into_diagnostic()
+        .wrap_err("The retries should be a number")?,
+      ..Default::default()
+    })
+  }
+
+  /// Complete the unset values with `other`.
+  pub(crate) fn or(&self, other: &RetryPolicy) -> RetryPolicy {
+    RetryPolicy {
+      timeout: self.timeout.or(other.timeout),
+      retries: self.retries.or(other.retries),
+      retry_on: self.retry_on.clone().or_else(|| other.retry_on.clone()),
+      retry_non_idempotent: self.retry_non_idempotent.or(other.retry_non_idempotent),
+    }
+  }
+
+  pub(crate) fn timeout(&self) -> Duration {
+    Duration::from_secs(self.timeout.unwrap_or(DEFAULT_TIMEOUT))
+  }
+
+  /// Number of retries allowed for `method`.
+  pub(crate) fn retries(&self, method: &Method) -> u32 {
+    if method.is_idempotent() || self.retry_non_idempotent.unwrap_or_default() {
+      self.retries.unwrap_or_default()
+    } else {
+      0
+    }
+  }
+
+  pub(crate) fn should_retry(&self, status: StatusCode) -> bool {
+    match &self.retry_on {
+      Some(retry_on) => retry_on.contains(&status.as_u16()),
+      None => DEFAULT_RETRY_ON.contains(&status.as_u16()),
+    }
+  }
+
+  /// Delay before the attempt number `attempt` (starting at 1).
+  ///
+  /// The `Retry-After` header is honoured, else the delay grows exponentially with some jitter.
+  pub(crate) fn delay(&self, attempt: u32, headers: Option<&HeaderMap>) -> Duration {
+    if let Some(retry_after) = headers
+      .and_then(|headers| headers.get(RETRY_AFTER))
+      .and_then(|value| value.to_str().ok())
+      .and_then(retry_after)
+    {
+      return retry_after.min(BACKOFF_MAX);
+    }
+    let backoff = BACKOFF_BASE
+      .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+      .min(BACKOFF_MAX);
+    // Equal jitter: half of the delay is fixed, the other half is random.
+    let jitter = RandomState::new().build_hasher().finish() % (backoff.as_millis() as u64 / 2 + 1);
+    backoff / 2 + Duration::from_millis(jitter)
+  }
+}
+
+/// Parse a `Retry-After` value: a number of seconds or an HTTP date.
+fn retry_after(value: &str) -> Option<Duration> {
+  if let Ok(seconds) = value.trim().parse::<u64>() {
+    return Some(Duration::from_secs(seconds));
+  }
+  let date = http_date(value)?;
+  let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+  Some(Duration::from_secs(date.saturating_sub(now)))
+}
+
+/// Parse an HTTP date (`Sun, 06 Nov 1994 08:49:37 GMT`) as seconds since the epoch.
+fn http_date(value: &str) -> Option<u64> {
+  let mut parts = value.split_whitespace().skip(1);
+  let day: u64 = parts.next()?.parse().ok()?;
+  let month = parts.next()?;
+  let month = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+  ]
+  .iter()
+  .position(|name| *name == month)? as u64
+    + 1;
+  let year: u64 = parts.next()?.parse().ok()?;
+  let mut time = parts.next()?.split(':').map(|n| n.parse::<u64>().ok());
+  let (hour, minute, second) = (time.next()??, time.next()??, time.next()??);
+
+  // Days since the epoch of a date of the proleptic Gregorian calendar.
+  let (y, m) = if month <= 2 {
+    (year - 1, month + 9)
+  } else {
+    (year, month - 3)
+  };
+  let era = y / 400;
+  let year_of_era = y % 400;
+  let day_of_year = (153 * m + 2) / 5 + day - 1;
+  let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+  let days = (era * 146097 + day_of_era).checked_sub(719468)?;
+  Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{http_date, RetryPolicy};
+  use clap::{Arg, Command};
+  use config::Config;
+  use hyper::{header::RETRY_AFTER, HeaderMap, Method, StatusCode};
+  use std::time::Duration;
+
+  #[test]
+  fn test_http_date() {
+    assert_eq!(http_date("Sun, 06 Nov 1994 08:49:37 GMT"), Some(784111777));
+    assert_eq!(http_date("Thu, 01 Jan 1970 00:00:00 GMT"), Some(0));
+    assert_eq!(http_date("tomorrow"), None);
+  }
+
+  #[test]
+  fn test_from_settings() {
+    let command =
+      Command::new("test").args(["profile", "timeout", "retries"].map(|id| Arg::new(id).long(id)));
+    let policy = |args: &[&str]| {
+      RetryPolicy::from_settings(&Config::default(), &command.clone().get_matches_from(args))
+    };
+    let timeout = policy(&["test", "--timeout", "5", "--retries", "2"]).unwrap();
+    assert_eq!(timeout.timeout(), Duration::from_secs(5));
+    assert_eq!(timeout.retries(&Method::GET), 2);
+    assert_eq!(policy(&["test"]).unwrap().timeout, None);
+    assert!(policy(&["test", "--timeout", "0"]).is_err());
+    assert!(policy(&["test", "--timeout", "soon"]).is_err());
+  }
+
+  #[test]
+  fn test_retry_policy() {
+    let policy = RetryPolicy {
+      retries: Some(3),
+      retry_on: Some(vec![503]),
+      ..Default::default()
+    }
+    .or(&RetryPolicy {
+      timeout: Some(5),
+      retries: Some(1),
+      ..Default::default()
+    });
+    assert_eq!(policy.timeout(), Duration::from_secs(5));
+    assert_eq!(policy.retries(&Method::GET), 3);
+    assert_eq!(policy.retries(&Method::POST), 0);
+    assert!(policy.should_retry(StatusCode::SERVICE_UNAVAILABLE));
+    assert!(!policy.should_retry(StatusCode::BAD_GATEWAY));
+
+    let mut headers = HeaderMap::new();
+    headers.insert(RETRY_AFTER, "7".parse().unwrap());
+    assert_eq!(policy.delay(1, Some(&headers)), Duration::from_secs(7));
+    let delay = policy.delay(3, None);
+    assert!(delay >= Duration::from_secs(1) && delay <= Duration::from_secs(2));
+  }
+}
@@ -0,0 +1,99 @@
+//! Output of the results of the `multiple_results` endpoints while they're received.
+
+#[cfg(feature = "template")]
+use crate::template::Template;
+use crate::{formats::OutputFormat, Api, Selection};
+use miette::Result;
+use serde::Serialize;
+use std::fmt::Debug;
+#[cfg(feature = "csv")]
+use std::io::Stdout;
+
+/// Print the results of a `multiple_results` endpoint.
+///
+/// The results are printed as soon as they're received when the output is line oriented
+/// (`ndjson`, `csv`, `tsv` or a template) and the results are not sorted or queried. Else
+/// they're printed at the end, with `Api::output_multiple`.
+pub struct Rows<R> {
+  format: Option<OutputFormat>,
+  selection: Selection,
+  streamed: bool,
+  results: Vec<R>,
+  #[cfg(feature = "csv")]
+  csv: Option<csv::Writer<Stdout>>,
+  /// The `--output-template`, compiled once for all the results.
+  #[cfg(feature = "template")]
+  template: Option<Template>,
+}
+
+impl<R: Api + Serialize + Debug> Rows<R> {
+  pub fn new(format: Option<OutputFormat>, selection: Selection) -> Result<Rows<R>> {
+    let streamed =
+      format.as_ref().is_some_and(OutputFormat::is_line_oriented) && !selection.needs_all_results();
+    #[cfg(feature = "template")]
+    let template = match &format {
+      Some(OutputFormat::Template(template)) if streamed => Some(Template::new(template)?),
+      _ => None,
+    };
+    Ok(Rows {
+      format,
+      selection,
+      streamed,
+      results: vec![],
+      #[cfg(feature = "csv")]
+      csv: None,
+      #[cfg(feature = "template")]
+      template,
+    })
+  }
+
+  /// A result is received.
+  pub fn push(&mut self, result: R) -> Result<()> {
+    if !self.streamed {
+      self.results.push(result);
+      return Ok(());
+    }
+    let results = [result];
+    let selected = self.selection.select(&results)?;
+    let items = self.selection.items(&results, &selected);
+    if items.is_empty() {
+      return Ok(());
+    }
+    match &self.format {
+      #[cfg(feature = "json")]
+      Some(OutputFormat::Ndjson) => {
+        use miette::IntoDiagnostic;
+        println!("{}", serde_json::to_string(&items[0]).into_diagnostic()?);
+      }
+      #[cfg(feature = "csv")]
+      Some(format @ (OutputFormat::Csv | OutputFormat::Tsv)) => {
+        let header = self.csv.is_none();
+        let writer = self.csv.get_or_insert_with(|| match format {
+          OutputFormat::Tsv => csv::WriterBuilder::new()
+            .delimiter(b'\t')
+            .quote_style(csv::QuoteStyle::NonNumeric)
+            .from_writer(std::io::stdout()),
+          _ => csv::Writer::from_writer(std::io::stdout()),
+        });
+        crate::select::write_csv(writer, &items, header)?;
+      }
+      #[cfg(feature = "template")]
+      Some(OutputFormat::Template(_)) => {
+        if let Some(template) = &self.template {
+          print!("{}", template.render(&items)?);
+        }
+      }
+      _ => {}
+    }
+    Ok(())
+  }
+
+  /// All the results are received.
+  pub fn finish(self) -> Result<()> {
+    if self.streamed {
+      Ok(())
+    } else {
+      R::output_multiple(&self.results, self.format, &self.selection)
+    }
+  }
+}
@@ -0,0 +1,603 @@
+//! Client-side selection of the results: `--filter`, `--sort` and `--fields`, then the
+//! `--query` JMESPath expression.
+//!
+//! The results are evaluated as their JSON serialization. A field is designated by its name,
+//! or by a dotted path for the nested fields (`author.name`, `labels.0`). The result of a
+//! query is not a typed result anymore: it's output as a JSON value.
+
+use clap::ArgMatches;
+use miette::{miette, IntoDiagnostic, Result};
+use serde::{ser::SerializeMap, Serialize, Serializer};
+use serde_json::Value;
+use std::{cmp::Ordering, str::FromStr};
+
+/// The selection arguments of a command.
+#[derive(Debug, Default, Clone)]
+pub struct Selection {
+  fields: Vec<String>,
+  filters: Vec<Filter>,
+  sort: Vec<SortKey>,
+  query: Option<String>,
+}
+
+pub fn clap_match_selection(argmatches: &ArgMatches) -> Result<Selection> {
+  let list = |name| {
+    argmatches
+      .try_get_one::<String>(name)
+      .ok()
+      .flatten()
+      .map(|list| {
+        list
+          .split(',')
+          .map(str::trim)
+          .filter(|item| !item.is_empty())
+          .map(ToString::to_string)
+          .collect::<Vec<_>>()
+      })
+      .unwrap_or_default()
+  };
+  let query = argmatches
+    .try_get_one::<String>("output_query")
+    .ok()
+    .flatten()
+    .cloned();
+  if let Some(query) = &query {
+    compile(query)?;
+  }
+  Ok(Selection {
+    fields: list("select_fields"),
+    filters: argmatches
+      .try_get_many::<String>("select_filter")
+      .ok()
+      .flatten()
+      .into_iter()
+      .flatten()
+      .map(|filter| filter.parse())
+      .collect::<Result<_>>()?,
+    sort: list("select_sort")
+      .iter()
+      .map(|key| match key.strip_prefix('-') {
+        Some(path) => SortKey {
+          path: path.to_string(),
+          descending: true,
+        },
+        None => SortKey {
+          path: key.strip_prefix('+').unwrap_or(key).to_string(),
+          descending: false,
+        },
+      })
+      .collect(),
+    query,
+  })
+}
+
+fn compile(query: &str) -> Result<jmespath::Expression<'static>> {
+  jmespath::compile(query).map_err(|error| {
+    miette!(
+      help = "See https://jmespath.org/specification.html for the syntax of the queries",
+      "Invalid query: {error}"
+    )
+  })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operator {
+  Eq,
+  Ne,
+  Lt,
+  Le,
+  Gt,
+  Ge,
+  /// The field contains the value, case-insensitively. An array contains an element that
+  /// contains the value.
+  Contains,
+}
+
+/// The operators, the longest first.
+const OPERATORS: [(&str, Operator); 8] = [
+  ("==", Operator::Eq),
+  ("!=", Operator::Ne),
+  ("<=", Operator::Le),
+  (">=", Operator::Ge),
+  ("~=", Operator::Contains),
+  ("<", Operator::Lt),
+  (">", Operator::Gt),
+  ("=", Operator::Eq),
+];
+
+/// A `--filter` expression: `<field><operator><value>`.
+#[derive(Debug, Clone)]
+struct Filter {
+  path: String,
+  operator: Operator,
+  value: String,
+}
+
+impl FromStr for Filter {
+  type Err = miette::Report;
+
+  fn from_str(s: &str) -> Result<Self> {
+    s.char_indices()
+      .find_map(|(i, _)| {
+        OPERATORS
+          .iter()
+          .find(|(operator, _)| s[i..].starts_with(operator))
+          .map(|(operator, op)| Filter {
+            path: s[..i].trim().to_string(),
+            operator: *op,
+            value: s[i + operator.len()..].trim().to_string(),
+          })
+      })
+      .filter(|filter| !filter.path.is_empty())
+      .ok_or_else(|| {
+        miette!(
+          help = "The operators are ==, !=, <, <=, >, >= and ~= (contains). Example: 'state==opened'",
+          "Invalid filter: {s}"
+        )
+      })
+  }
+}
+
+impl Filter {
+  fn matches(&self, value: &Value) -> bool {
+    let field = lookup(value, &self.path).unwrap_or(&Value::Null);
+    let ordering = compare_filter(field, &self.value);
+    match self.operator {
+      Operator::Eq => ordering == Some(Ordering::Equal),
+      Operator::Ne => ordering != Some(Ordering::Equal),
+      Operator::Lt => ordering == Some(Ordering::Less),
+      Operator::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+      Operator::Gt => ordering == Some(Ordering::Greater),
+      Operator::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+      Operator::Contains => {
+        let value = self.value.to_lowercase();
+        match field {
+          Value::Array(items) => items
+            .iter()
+            .any(|item| text(item).to_lowercase().contains(&value)),
+          field => text(field).to_lowercase().contains(&value),
+        }
+      }
+    }
+  }
+}
+
+/// Compare a field with the value of a filter: numerically when the field is a number.
+fn compare_filter(field: &Value, value: &str) -> Option<Ordering> {
+  match field {
+    Value::Number(number) => number.as_f64()?.partial_cmp(&value.parse::<f64>().ok()?),
+    Value::Array(_) | Value::Object(_) => None,
+    field => Some(text(field).as_str().cmp(value)),
+  }
+}
+
+/// A `--sort` key: `<field>`, or `-<field>` for the descending order.
+#[derive(Debug, Clone)]
+struct SortKey {
+  path: String,
+  descending: bool,
+}
+
+impl SortKey {
+  fn compare(&self, a: &Value, b: &Value) -> Ordering {
+    let ordering = compare_values(lookup(a, &self.path), lookup(b, &self.path));
+    if self.descending {
+      ordering.reverse()
+    } else {
+      ordering
+    }
+  }
+}
+
+/// Order of the values: the missing and null values first, then the booleans, the numbers,
+/// the strings, the arrays and the objects.
+fn compare_values(a: Option<&Value>, b: Option<&Value>) -> Ordering {
+  fn rank(value: Option<&Value>) -> u8 {
+    match value {
+      None | Some(Value::Null) => 0,
+      Some(Value::Bool(_)) => 1,
+      Some(Value::Number(_)) => 2,
+      Some(Value::String(_)) => 3,
+      Some(Value::Array(_)) => 4,
+      Some(Value::Object(_)) => 5,
+    }
+  }
+  match (a, b) {
+    (Some(Value::Bool(a)), Some(Value::Bool(b))) => a.cmp(b),
+    (Some(Value::Number(a)), Some(Value::Number(b))) => a
+      .as_f64()
+      .partial_cmp(&b.as_f64())
+      .unwrap_or(Ordering::Equal),
+    (Some(Value::String(a)), Some(Value::String(b))) => a.cmp(b),
+    (Some(a), Some(b)) if rank(Some(a)) == rank(Some(b)) => a.to_string().cmp(&b.to_string()),
+    (a, b) => rank(a).cmp(&rank(b)),
+  }
+}
+
+/// The field at a dotted path.
+fn lookup<'v>(value: &'v Value, path: &str) -> Option<&'v Value> {
+  path.split('.').try_fold(value, |value, key| match value {
+    Value::Object(fields) => fields.get(key),
+    Value::Array(items) => items.get(key.parse::<usize>().ok()?),
+    _ => None,
+  })
+}
+
+/// A value as text: the strings are not quoted.
+fn text(value: &Value) -> String {
+  match value {
+    Value::String(text) => text.clone(),
+    value => value.to_string(),
+  }
+}
+
+/// A selected result.
+pub(crate) enum Item<'r, T> {
+  /// The result itself: it's serialized as usual.
+  Result(&'r T),
+  /// The `--fields` of the result, in their order.
+  Projection(Vec<(&'r str, Option<&'r Value>)>),
+}
+
+impl<T: Serialize> Serialize for Item<'_, T> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    match self {
+      Item::Result(result) => result.serialize(serializer),
+      Item::Projection(fields) => {
+        let mut map = serializer.serialize_map(None)?;
+        for (name, value) in fields {
+          match value {
+            // A null is serialized as `None`: TOML skips it.
+            None | Some(Value::Null) => map.serialize_entry(name, &None::<()>)?,
+            Some(value) => map.serialize_entry(name, value)?,
+          }
+        }
+        map.end()
+      }
+    }
+  }
+}
+
+impl Selection {
+  pub(crate) fn is_empty(&self) -> bool {
+    self.fields.is_empty() && self.filters.is_empty() && self.sort.is_empty() && self.query.is_none()
+  }
+
+  /// Apply the `--query` to the selected results.
+  pub(crate) fn query<T: Serialize>(&self, data: &T) -> Result<Option<Value>> {
+    let Some(query) = &self.query else {
+      return Ok(None);
+    };
+    let result = compile(query)?
+      .search(data)
+      .map_err(|error| miette!("Query failed: {error}"))?;
+    serde_json::to_value(&*result).into_diagnostic().map(Some)
+  }
+
+  /// The `--sort` and the `--query` apply to all the results at once.
+  pub(crate) fn needs_all_results(&self) -> bool {
+    !self.sort.is_empty() || self.query.is_some()
+  }
+
+  pub(crate) fn has_fields(&self) -> bool {
+    !self.fields.is_empty()
+  }
+
+  /// Filter and sort the results. Returns the indices of the selected results with their
+  /// JSON value.
+  pub(crate) fn select<T: Serialize>(&self, results: &[T]) -> Result<Vec<(usize, Value)>> {
+    let mut selected = results
+      .iter()
+      .enumerate()
+      .map(|(i, result)| Ok((i, serde_json::to_value(result).into_diagnostic()?)))
+      .collect::<Result<Vec<_>>>()?;
+    selected.retain(|(_, value)| self.filters.iter().all(|filter| filter.matches(value)));
+    selected.sort_by(|(_, a), (_, b)| {
+      self
+        .sort
+        .iter()
+        .map(|key| key.compare(a, b))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+    });
+    Ok(selected)
+  }
+
+  /// The selected results, projected on the `--fields`.
+  pub(crate) fn items<'r, T>(
+    &'r self,
+    results: &'r [T],
+    selected: &'r [(usize, Value)],
+  ) -> Vec<Item<'r, T>> {
+    selected
+      .iter()
+      .map(|(i, value)| {
+        if self.fields.is_empty() {
+          Item::Result(&results[*i])
+        } else {
+          Item::Projection(
+            self
+              .fields
+              .iter()
+              .map(|field| (field.as_str(), lookup(value, field)))
+              .collect(),
+          )
+        }
+      })
+      .collect()
+  }
+
+  /// The table of the `--fields` of the selected results, with their header. The fields are
+  /// looked up in the JSON values, like the other outputs: the serde names and the dotted
+  /// paths work, and so do the fields that are skipped in the tables.
+  pub(crate) fn table(&self, selected: &[(usize, Value)]) -> Vec<Vec<String>> {
+    let mut table = vec![self.fields.clone()];
+    for (_, value) in selected {
+      table.push(
+        self
+          .fields
+          .iter()
+          .map(|field| match lookup(value, field) {
+            None | Some(Value::Null) => String::new(),
+            Some(value) => text(value),
+          })
+          .collect(),
+      );
+    }
+    table
+  }
+}
+
+/// The rows of a JSON value, with a header. The rows of an array of objects are the
+/// values of their fields. The nested arrays and objects are written in JSON.
+pub(crate) fn value_table(value: &Value) -> Vec<Vec<String>> {
+  let rows = match value {
+    Value::Array(rows) => rows.iter().collect(),
+    value => vec![value],
+  };
+  let mut header: Vec<String> = vec![];
+  for row in &rows {
+    match row {
+      Value::Object(fields) => {
+        for name in fields.keys() {
+          if !header.contains(name) {
+            header.push(name.clone());
+          }
+        }
+      }
+      Value::Array(items) => {
+        for i in header.len()..items.len() {
+          header.push(i.to_string());
+        }
+      }
+      _ if header.is_empty() => header.push("value".to_string()),
+      _ => {}
+    }
+  }
+  let cell = |value: Option<&Value>| match value {
+    None | Some(Value::Null) => String::new(),
+    Some(value) => text(value),
+  };
+  let mut table = vec![header.clone()];
+  for row in rows {
+    let mut cells: Vec<String> = match row {
+      Value::Object(fields) => header.iter().map(|name| cell(fields.get(name))).collect(),
+      Value::Array(items) => (0..header.len()).map(|i| cell(items.get(i))).collect(),
+      value => vec![cell(Some(value))],
+    };
+    // The values that are not objects nor arrays (ie. null) are in the first column.
+    cells.resize(header.len(), String::new());
+    table.push(cells);
+  }
+  table
+}
+
+/// Write the selected results in CSV. The projections have a header of the `--fields`,
+/// unless it's already written.
+#[cfg(feature = "csv")]
+pub(crate) fn write_csv<T: Serialize, W: std::io::Write>(
+  writer: &mut csv::Writer<W>,
+  items: &[Item<T>],
+  header: bool,
+) -> Result<()> {
+  for (i, item) in items.iter().enumerate() {
+    match item {
+      Item::Result(result) => writer.serialize(result).into_diagnostic()?,
+      Item::Projection(fields) => {
+        if i == 0 && header {
+          writer
+            .write_record(fields.iter().map(|(name, _)| name))
+            .into_diagnostic()?;
+        }
+        writer
+          .write_record(fields.iter().map(|(_, value)| match value {
+            None | Some(Value::Null) => String::new(),
+            Some(value) => text(value),
+          }))
+          .into_diagnostic()?;
+      }
+    }
+  }
+  writer.flush().into_diagnostic()
+}
+
+/// Write a JSON value in CSV.
+#[cfg(feature = "csv")]
+pub(crate) fn write_value_csv<W: std::io::Write>(
+  writer: &mut csv::Writer<W>,
+  value: &Value,
+) -> Result<()> {
+  for row in value_table(value) {
+    writer.write_record(row).into_diagnostic()?;
+  }
+  writer.flush().into_diagnostic()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{value_table, Filter, Item, Selection, SortKey};
+  use serde::Serialize;
+  use serde_json::json;
+
+  #[derive(Serialize)]
+  struct Issue {
+    id: u32,
+    title: String,
+    state: String,
+    labels: Vec<String>,
+    author: Option<String>,
+  }
+
+  fn issues() -> Vec<Issue> {
+    [
+      (1, "Crash on start", "opened", vec!["bug"], Some("alice")),
+      (2, "Add dark mode", "closed", vec!["feature", "ui"], None),
+      (
+        10,
+        "Slow start",
+        "opened",
+        vec!["Bug", "performance"],
+        Some("bob"),
+      ),
+    ]
+    .into_iter()
+    .map(|(id, title, state, labels, author)| Issue {
+      id,
+      title: title.to_string(),
+      state: state.to_string(),
+      labels: labels.into_iter().map(ToString::to_string).collect(),
+      author: author.map(ToString::to_string),
+    })
+    .collect()
+  }
+
+  fn ids(selection: &Selection) -> Vec<u32> {
+    selection
+      .select(&issues())
+      .unwrap()
+      .iter()
+      .map(|(_, value)| value["id"].as_u64().unwrap() as u32)
+      .collect()
+  }
+
+  fn filter(filters: &[&str]) -> Selection {
+    Selection {
+      filters: filters
+        .iter()
+        .map(|filter| filter.parse().unwrap())
+        .collect(),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn test_filter() {
+    assert_eq!(ids(&filter(&["state==opened"])), vec![1, 10]);
+    assert_eq!(ids(&filter(&["state != opened"])), vec![2]);
+    assert_eq!(ids(&filter(&["id>2"])), vec![10]);
+    assert_eq!(ids(&filter(&["id<=2", "state=opened"])), vec![1]);
+    assert_eq!(ids(&filter(&["labels~=bug"])), vec![1, 10]);
+    assert_eq!(ids(&filter(&["title~=START"])), vec![1, 10]);
+    assert_eq!(ids(&filter(&["author==null"])), vec![2]);
+    assert_eq!(ids(&filter(&["labels.1==ui"])), vec![2]);
+    assert!("state".parse::<Filter>().is_err());
+    assert!("==opened".parse::<Filter>().is_err());
+  }
+
+  #[test]
+  fn test_sort() {
+    let sort = |keys: &[(&str, bool)]| Selection {
+      sort: keys
+        .iter()
+        .map(|(path, descending)| SortKey {
+          path: path.to_string(),
+          descending: *descending,
+        })
+        .collect(),
+      ..Default::default()
+    };
+    assert_eq!(ids(&sort(&[("id", true)])), vec![10, 2, 1]);
+    assert_eq!(ids(&sort(&[("title", false)])), vec![2, 1, 10]);
+    assert_eq!(
+      ids(&sort(&[("state", false), ("id", true)])),
+      vec![2, 10, 1]
+    );
+    // The missing values first.
+    assert_eq!(ids(&sort(&[("author", false)])), vec![2, 1, 10]);
+  }
+
+  #[test]
+  fn test_fields() {
+    let selection = Selection {
+      fields: vec!["title".to_string(), "id".to_string(), "missing".to_string()],
+      ..filter(&["id==2"])
+    };
+    let issues = issues();
+    let selected = selection.select(&issues).unwrap();
+    let items = selection.items(&issues, &selected);
+    assert!(matches!(items[0], Item::Projection(_)));
+    assert_eq!(
+      serde_json::to_string(&items).unwrap(),
+      r#"[{"title":"Add dark mode","id":2,"missing":null}]"#
+    );
+    assert_eq!(
+      selection.table(&selected),
+      vec![
+        vec!["title", "id", "missing"],
+        vec!["Add dark mode", "2", ""]
+      ]
+    );
+    let selection = Selection {
+      fields: vec![
+        "state".to_string(),
+        "labels.0".to_string(),
+        "author".to_string(),
+      ],
+      ..Default::default()
+    };
+    let selected = selection.select(&issues).unwrap();
+    assert_eq!(
+      selection.table(&selected[..2]),
+      vec![
+        vec!["state", "labels.0", "author"],
+        vec!["opened", "bug", "alice"],
+        vec!["closed", "feature", ""]
+      ]
+    );
+  }
+
+  #[test]
+  fn test_query() {
+    let selection = Selection {
+      query: Some("[?state=='opened'].{id: id, first_label: labels[0]}".to_string()),
+      ..Default::default()
+    };
+    let value = selection.query(&issues()).unwrap().unwrap();
+    assert_eq!(
+      value,
+      json!([{"first_label": "bug", "id": 1}, {"first_label": "Bug", "id": 10}])
+    );
+    assert_eq!(
+      value_table(&value),
+      vec![
+        vec!["first_label", "id"],
+        vec!["bug", "1"],
+        vec!["Bug", "10"]
+      ]
+    );
+    assert_eq!(
+      value_table(&json!(["a", null])),
+      vec![vec!["value"], vec!["a"], vec![""]]
+    );
+    assert_eq!(
+      value_table(&json!([{"login": "ann", "id": 3}, null, {"login": "bob"}])),
+      vec![
+        vec!["id", "login"],
+        vec!["3", "ann"],
+        vec!["", ""],
+        vec!["", "bob"]
+      ]
+    );
+    assert!(Selection::default().query(&issues()).unwrap().is_none());
+  }
+}
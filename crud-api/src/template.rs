@@ -0,0 +1,166 @@
+//! `--output-template` output: each result is rendered with a Handlebars template.
+//!
+//! The helpers format the values like the tables and the pretty structures:
+//! - `{{date created_at "%F %T"}}` formats a date (default: `%F %T`),
+//! - `{{timestamp created_at}}` formats a UNIX timestamp,
+//! - `{{duration elapsed}}` formats a duration in seconds,
+//! - `{{bytes size}}` formats a size in bytes,
+//! - `{{check enabled}}` formats a boolean as ✔ or ✘.
+
+use crate::formats::format_date;
+use crud_pretty_struct::formatters::{
+  bool_check_formatter, byte_formatter, duration_formatter, timestamp_formatter,
+};
+use handlebars::{
+  no_escape, Context, Handlebars, Helper, HelperDef, HelperResult, Output, RenderContext,
+  RenderErrorReason,
+};
+use miette::{miette, Result};
+use serde::Serialize;
+use serde_json::Value;
+
+type Formatter = fn(&dyn ToString, bool) -> Result<(String, bool)>;
+
+/// A compiled `--output-template`.
+pub(crate) struct Template {
+  registry: Handlebars<'static>,
+}
+
+impl Template {
+  pub(crate) fn new(template: &str) -> Result<Template> {
+    let mut registry = Handlebars::new();
+    registry.register_escape_fn(no_escape);
+    registry.register_helper("date", Box::new(date));
+    registry.register_helper("timestamp", formatter("timestamp", timestamp_formatter));
+    registry.register_helper("duration", formatter("duration", duration_formatter));
+    registry.register_helper("bytes", formatter("bytes", byte_formatter));
+    registry.register_helper("check", formatter("check", bool_check_formatter));
+    registry
+      .register_template_string("output", template)
+      .map_err(|error| miette!("Invalid template: {error}"))?;
+    Ok(Template { registry })
+  }
+
+  /// Render the results. A line return is added after each result when the template doesn't
+  /// end with one.
+  pub(crate) fn render<T: Serialize>(&self, results: &[T]) -> Result<String> {
+    let mut out = String::new();
+    for result in results {
+      let rendered = self
+        .registry
+        .render("output", result)
+        .map_err(|error| miette!("Can't render the template: {error}"))?;
+      out.push_str(&rendered);
+      if !rendered.ends_with('\n') {
+        out.push('\n');
+      }
+    }
+    Ok(out)
+  }
+}
+
+/// Render the results with the template.
+pub(crate) fn render<T: Serialize>(template: &str, results: &[T]) -> Result<String> {
+  Template::new(template)?.render(results)
+}
+
+/// The `--output-template` argument: the `\t` and `\n` escapes are replaced.
+pub(crate) fn unescape(template: &str) -> String {
+  template.replace("\\t", "\t").replace("\\n", "\n")
+}
+
+/// A value as text: the strings are not quoted.
+fn text(value: &Value) -> String {
+  match value {
+    Value::String(text) => text.clone(),
+    value => value.to_string(),
+  }
+}
+
+/// The `date` helper. The date is formatted like the `date` format of the table fields.
+fn date(
+  h: &Helper,
+  _: &Handlebars,
+  _: &Context,
+  _: &mut RenderContext,
+  out: &mut dyn Output,
+) -> HelperResult {
+  let value = h
+    .param(0)
+    .ok_or(RenderErrorReason::ParamNotFoundForIndex("date", 0))?
+    .value();
+  let format = match h.param(1).map(|format| format.value()) {
+    Some(Value::String(format)) => format.as_str(),
+    Some(_) => return Err(RenderErrorReason::InvalidParamType("string").into()),
+    None => "%F %T",
+  };
+  if value.is_null() {
+    return Ok(());
+  }
+  let date = format_date(&text(value), format)
+    .map_err(|error| RenderErrorReason::Other(format!("{error}: {}", error.root_cause())))?;
+  out.write(&date)?;
+  Ok(())
+}
+
+/// A helper from a formatter of the pretty structures.
+fn formatter(name: &'static str, formatter: Formatter) -> Box<dyn HelperDef + Send + Sync> {
+  Box::new(
+    move |h: &Helper,
+          _: &Handlebars,
+          _: &Context,
+          _: &mut RenderContext,
+          out: &mut dyn Output|
+          -> HelperResult {
+      let value = h
+        .param(0)
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex(name, 0))?
+        .value();
+      if value.is_null() {
+        return Ok(());
+      }
+      let (formatted, _) = formatter(&text(value), false)
+        .map_err(|error| RenderErrorReason::Other(error.to_string()))?;
+      out.write(&formatted)?;
+      Ok(())
+    },
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{render, unescape, Template};
+  use crate::formats::format_date;
+  use serde_json::json;
+
+  #[test]
+  fn test_render() {
+    let results = [
+      json!({"id": 1, "title": "Crash <on> start", "state": "opened", "updated_at": "2024-03-01T10:20:30Z", "size": 2048, "draft": true}),
+      json!({"id": 2, "title": "Add dark mode", "state": "closed", "updated_at": null, "size": 10, "draft": false}),
+    ];
+    assert_eq!(
+      render(&unescape("{{id}}\\t{{title}} ({{state}})"), &results).unwrap(),
+      "1\tCrash <on> start (opened)\n2\tAdd dark mode (closed)\n"
+    );
+    assert_eq!(
+      render(
+        "{{id}}: {{date updated_at \"%d/%m/%Y\"}} {{bytes size}} {{check draft}}\n",
+        &results
+      )
+      .unwrap(),
+      "1: 01/03/2024 2.0 KiB ✔\n2:  10 B ✘\n"
+    );
+    let template = Template::new("{{id}}").unwrap();
+    assert_eq!(template.render(&results[..1]).unwrap(), "1\n");
+    assert_eq!(template.render(&results[1..]).unwrap(), "2\n");
+    assert!(Template::new("{{id").is_err());
+    assert!(render("{{id", &results).is_err());
+    assert!(render("{{date title}}", &results).is_err());
+    // The same format as the date fields of the tables.
+    assert_eq!(
+      format_date("2024-03-01T10:20:30Z", "%F %T").unwrap(),
+      "2024-03-01 10:20:30"
+    );
+  }
+}
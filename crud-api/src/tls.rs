@@ -0,0 +1,226 @@
+use crate::settings::{get_flag_settings, get_settings};
+use clap::ArgMatches;
+use config::Config;
+use log::{debug, warn};
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+use owo_colors::OwoColorize;
+use pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use std::sync::Arc;
+use tokio_rustls::rustls::{
+  client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+  ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+
+/// Build the TLS configuration from the settings:
+///
+/// - `ca_cert`: PEM bundle of additional certificate authorities.
+/// - `system_certs`: trust the certificates of the system store.
+/// - `client_cert` and `client_key`: PEM certificate chain and private key for mutual TLS.
+/// - `insecure`: don't verify the server certificate.
+pub(crate) fn client_config(settings: &Config, matches: &ArgMatches) -> Result<ClientConfig> {
+  let mut root_cert_store = RootCertStore::empty();
+  root_cert_store.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+
+  if get_flag_settings(settings, matches, "system_certs").unwrap_or_default() {
+    let native = rustls_native_certs::load_native_certs();
+    for error in &native.errors {
+      warn!("Can't load a system certificate: {error}");
+    }
+    let (added, ignored) = root_cert_store.add_parsable_certificates(native.certs);
+    debug!("System certificates: {added} added, {ignored} ignored");
+  }
+
+  if let Ok(ca_cert) = get_settings(settings, matches, "ca_cert") {
+    for cert in read_certificates(&ca_cert)? {
+      root_cert_store
+        .add(cert)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Invalid certificate in '{ca_cert}'"))?;
+    }
+  }
+
+  let builder = match insecure_verifier(settings, matches) {
+    Some(verifier) => ClientConfig::builder()
+      .dangerous()
+      .with_custom_certificate_verifier(verifier),
+    None => ClientConfig::builder().with_root_certificates(root_cert_store),
+  };
+
+  let client_cert = get_settings(settings, matches, "client_cert").ok();
+  let client_key = get_settings(settings, matches, "client_key").ok();
+  match (client_cert, client_key) {
+    (Some(cert), Some(key)) => {
+      let key = PrivateKeyDer::from_pem_file(&key)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Can't read the private key '{key}'"))?;
+      builder
+        .with_client_auth_cert(read_certificates(&cert)?, key)
+        .into_diagnostic()
+        .wrap_err("Invalid client certificate")
+    }
+    (None, None) => Ok(builder.with_no_client_auth()),
+    _ => Err(miette!(
+      help = "Set both `client_cert` and `client_key`.",
+      "The client certificate needs a private key"
+    )),
+  }
+}
+
+/// The verifier accepting any server certificate when `insecure` is set. The warning is
+/// printed whatever the log level.
+fn insecure_verifier(settings: &Config, matches: &ArgMatches) -> Option<Arc<dyn ServerCertVerifier>> {
+  if !get_flag_settings(settings, matches, "insecure").unwrap_or_default() {
+    return None;
+  }
+  eprintln!(
+    "{}",
+    "Warning: the server certificates are not verified (insecure).".yellow()
+  );
+  Some(Arc::new(NoVerifier))
+}
+
+fn read_certificates(path: &str) -> Result<Vec<CertificateDer<'static>>> {
+  let certs = CertificateDer::pem_file_iter(path)
+    .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+    .into_diagnostic()
+    .wrap_err_with(|| format!("Can't read the certificates '{path}'"))?;
+  if certs.is_empty() {
+    Err(miette!("No certificate found in '{path}'"))
+  } else {
+    Ok(certs)
+  }
+}
+
+/// Accept any server certificate (`--insecure`).
+#[derive(Debug)]
+struct NoVerifier;
+
+impl ServerCertVerifier for NoVerifier {
+  fn verify_server_cert(
+    &self,
+    _end_entity: &CertificateDer<'_>,
+    _intermediates: &[CertificateDer<'_>],
+    _server_name: &ServerName<'_>,
+    _ocsp_response: &[u8],
+    _now: UnixTime,
+  ) -> Result<ServerCertVerified, tokio_rustls::rustls::Error> {
+    Ok(ServerCertVerified::assertion())
+  }
+
+  fn verify_tls12_signature(
+    &self,
+    _message: &[u8],
+    _cert: &CertificateDer<'_>,
+    _dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+    Ok(HandshakeSignatureValid::assertion())
+  }
+
+  fn verify_tls13_signature(
+    &self,
+    _message: &[u8],
+    _cert: &CertificateDer<'_>,
+    _dss: &DigitallySignedStruct,
+  ) -> Result<HandshakeSignatureValid, tokio_rustls::rustls::Error> {
+    Ok(HandshakeSignatureValid::assertion())
+  }
+
+  fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+    vec![
+      SignatureScheme::RSA_PKCS1_SHA256,
+      SignatureScheme::RSA_PKCS1_SHA384,
+      SignatureScheme::RSA_PKCS1_SHA512,
+      SignatureScheme::ECDSA_NISTP256_SHA256,
+      SignatureScheme::ECDSA_NISTP384_SHA384,
+      SignatureScheme::ECDSA_NISTP521_SHA512,
+      SignatureScheme::RSA_PSS_SHA256,
+      SignatureScheme::RSA_PSS_SHA384,
+      SignatureScheme::RSA_PSS_SHA512,
+      SignatureScheme::ED25519,
+    ]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{client_config, insecure_verifier};
+  use clap::{Arg, ArgAction, ArgMatches, Command};
+  use config::Config;
+  use std::fs;
+
+  fn matches(args: &[&str]) -> ArgMatches {
+    Command::new("test")
+      .args(["profile", "ca_cert", "client_cert", "client_key"].map(|id| Arg::new(id).long(id)))
+      .args(["system_certs", "insecure"].map(|id| Arg::new(id).long(id).action(ArgAction::SetTrue)))
+      .get_matches_from([&["test"], args].concat())
+  }
+
+  /// The error of the TLS configuration.
+  fn error(args: &[&str]) -> String {
+    client_config(&Config::default(), &matches(args))
+      .unwrap_err()
+      .to_string()
+  }
+
+  #[test]
+  fn test_certificate_errors() {
+    let dir = std::env::temp_dir().join(format!("crud-api-tls-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let empty = dir.join("empty.pem");
+    fs::write(&empty, "").unwrap();
+    let invalid = dir.join("invalid.pem");
+    fs::write(
+      &invalid,
+      "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n",
+    )
+    .unwrap();
+    let missing = dir.join("missing.pem");
+    let (empty, invalid, missing) = (
+      empty.to_str().unwrap(),
+      invalid.to_str().unwrap(),
+      missing.to_str().unwrap(),
+    );
+
+    assert_eq!(
+      error(&["--ca_cert", missing]),
+      format!("Can't read the certificates '{missing}'")
+    );
+    assert_eq!(
+      error(&["--ca_cert", empty]),
+      format!("No certificate found in '{empty}'")
+    );
+    assert_eq!(
+      error(&["--ca_cert", invalid]),
+      format!("Invalid certificate in '{invalid}'")
+    );
+    assert_eq!(
+      error(&["--client_cert", invalid]),
+      "The client certificate needs a private key"
+    );
+    assert_eq!(
+      error(&["--client_cert", invalid, "--client_key", missing]),
+      format!("Can't read the private key '{missing}'")
+    );
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn test_insecure() {
+    let settings = Config::default();
+    assert!(insecure_verifier(&settings, &matches(&[])).is_none());
+    assert_eq!(
+      format!(
+        "{:?}",
+        insecure_verifier(&settings, &matches(&["--insecure"]))
+      ),
+      "Some(NoVerifier)"
+    );
+    let settings = Config::builder()
+      .set_override("insecure", true)
+      .unwrap()
+      .build()
+      .unwrap();
+    assert!(insecure_verifier(&settings, &matches(&[])).is_some());
+    assert!(client_config(&settings, &matches(&[])).is_ok());
+  }
+}
@@ -1,62 +1,64 @@
-use crate::{ApiInput, ApiInputOptions};
-use base64::{engine::general_purpose, write::EncoderStringWriter};
-use clap::{ArgMatches, Command};
-pub use crud_api_derive::*;
-use miette::Result;
-use serde::{ser, Deserialize, Serialize};
-use std::{
-  fmt::Debug,
-  fs::File,
-  io::{copy, stdin},
-  marker::Sized,
-  str::FromStr,
-};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Debug, path::Path, str::FromStr};
 
-/// Upload a stream as Base64.
+/// A file to upload.
 ///
-/// In your `ApiInput`s, declare a field with type `UploadBase64`.
-/// This field accepts a file name or `-` for _stdin_. It will be encoded as base64 and transported in the JSON payload.
-#[derive(Debug, Default, Clone, Deserialize)]
-pub struct UploadBase64 {
-  file: String,
+/// In your `ApiInput`s, declare a field with type `UploadFile`. This field accepts a file
+/// name or `-` for _stdin_. The endpoint needs the `multipart` `payload_encoding`: the
+/// file is streamed as a file part of the form, with a progress bar. The other encodings
+/// are refused by the derive.
+///
+/// ```rust
+/// # use crud_api::{ApiInput, UploadFile};
+/// # use serde::{Deserialize, Serialize};
+/// #[derive(Debug, Default, ApiInput, Serialize, Deserialize)]
+/// struct Avatar {
+///   #[api(no_short, help = "Image of the avatar")]
+///   image: UploadFile,
+///   #[api(no_short)]
+///   description: Option<String>,
+/// }
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct UploadFile {
+  path: String,
 }
 
-impl ApiInput for UploadBase64 {
-  fn clap(_app: Command, _options: Option<ApiInputOptions>) -> Command {
-    todo!()
-  }
-
-  fn from_clap_matches(_matches: &ArgMatches) -> Result<Self>
-  where
-    Self: Sized,
-  {
-    todo!()
+impl UploadFile {
+  /// The path of the file, or `-` for stdin.
+  pub fn path(&self) -> &str {
+    &self.path
   }
 }
 
-impl Serialize for UploadBase64 {
-  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-  where
-    S: serde::Serializer,
-  {
-    let mut buf = String::new();
-    let mut enc = EncoderStringWriter::from_consumer(&mut buf, &general_purpose::STANDARD);
-    if self.file == "-" {
-      copy(&mut stdin(), &mut enc).map_err(ser::Error::custom)?;
-    } else {
-      let mut file = File::open(&self.file).map_err(ser::Error::custom)?;
-      copy(&mut file, &mut enc).map_err(ser::Error::custom)?;
+impl FromStr for UploadFile {
+  type Err = String;
+
+  /// The file should exist.
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    if s != "-" && !Path::new(s).is_file() {
+      return Err(format!("No such file: {s}"));
     }
-    enc.into_inner();
-    serializer.serialize_str(&buf)
+    Ok(UploadFile {
+      path: s.to_string(),
+    })
   }
 }
 
-impl FromStr for UploadBase64 {
-  type Err = miette::Error;
-  fn from_str(s: &str) -> Result<Self, Self::Err> {
-    Ok(UploadBase64 {
-      file: s.to_string(),
-    })
+#[cfg(test)]
+mod tests {
+  use super::UploadFile;
+  use std::str::FromStr;
+
+  #[test]
+  fn test_from_str() {
+    assert_eq!(UploadFile::from_str("-").unwrap().path(), "-");
+    assert_eq!(
+      UploadFile::from_str("Cargo.toml").unwrap().path(),
+      "Cargo.toml"
+    );
+    assert!(UploadFile::from_str("no/such/file").is_err());
+    assert!(UploadFile::from_str("src").is_err());
   }
 }
@@ -0,0 +1,239 @@
+//! ## Crud API key authentification.
+//!
+//! Authentification trait implementation for [crud-api](../crud-api):
+//! the API key is sent in a header (`X-API-Key` by default) or in a query parameter.
+//!
+//! The header or the query parameter is set by the `auth` block of `ApiRun`:
+//! ```rust
+//! # use crud_api::ApiRun;
+//! # use crud_auth::CrudAuth;
+//! # use crud_auth_api_key::Auth;
+//! # use miette::IntoDiagnostic;
+//! #[derive(ApiRun)]
+//! #[api(
+//!   infos(base_url = "https://api.example.com"),
+//!   auth(api_key_query = "apikey")
+//! )]
+//! struct ExampleApi;
+//! ```
+//!
+//! ### Configuration
+//!
+//! | configuration  | cli     | description                                         |
+//! |----------------|---------|-----------------------------------------------------|
+//! | api_key        | api-key | API key                                             |
+//! | api_key_header |         | Header of the API key. Default: `X-API-Key`         |
+//! | api_key_query  |         | Query parameter of the API key, instead of a header |
+//!
+//! The `login` command saves the API key in the credential store instead of the settings.
+//! `logout` removes it.
+//!
+
+use async_trait::async_trait;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use config::Config;
+use crud_auth::{
+  append_query, credentials::Credentials, login_commands, prompt_secret, setting, CrudAuth, Request,
+};
+use http::header::{HeaderName, HeaderValue};
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+use owo_colors::OwoColorize;
+use std::sync::Mutex;
+
+const API_KEY_ARG: &str = "api_key";
+const API_KEY_SETTING: &str = "api_key";
+const HEADER_SETTING: &str = "api_key_header";
+const QUERY_SETTING: &str = "api_key_query";
+const DEFAULT_HEADER: &str = "X-API-Key";
+
+/// Where the API key is sent.
+#[derive(Debug)]
+enum Location {
+  Header(String),
+  Query(String),
+}
+
+impl Default for Location {
+  fn default() -> Self {
+    Location::Header(DEFAULT_HEADER.to_string())
+  }
+}
+
+#[derive(Default, Debug)]
+pub struct Auth {
+  key: Mutex<Option<String>>,
+  location: Location,
+  credentials: Option<Credentials>,
+}
+
+#[async_trait]
+impl CrudAuth for Auth {
+  fn clap_auth(&self, app: Command) -> Command {
+    login_commands(app).arg(
+      Arg::new(API_KEY_ARG)
+        .long("api-key")
+        .action(ArgAction::Set)
+        .help("API key")
+        .help_heading("Configuration"),
+    )
+  }
+
+  fn clap_matches(&mut self, matches: &ArgMatches, _app: &mut Command, settings: &Config) {
+    *self.key.get_mut().unwrap() = setting(matches, settings, API_KEY_ARG, API_KEY_SETTING);
+    self.credentials = Credentials::new(settings, matches).ok();
+    // An empty setting overrides the default set by `ApiRun`.
+    let location = |key| setting(matches, settings, key, key).filter(|name| !name.is_empty());
+    self.location = if let Some(query) = location(QUERY_SETTING) {
+      Location::Query(query)
+    } else if let Some(header) = location(HEADER_SETTING) {
+      Location::Header(header)
+    } else {
+      Location::default()
+    };
+  }
+
+  async fn prepare(&self) -> Result<()> {
+    if self.key.lock().unwrap().is_some() {
+      return Ok(());
+    }
+    if let Some(credentials) = &self.credentials {
+      let key = credentials.get(API_KEY_SETTING).await?;
+      *self.key.lock().unwrap() = key;
+    }
+    Ok(())
+  }
+
+  async fn authorize(&self, request: &mut Request<()>) -> Result<()> {
+    let Some(key) = self.key.lock().unwrap().clone() else {
+      return Ok(());
+    };
+    match &self.location {
+      Location::Header(header) => {
+        request.headers_mut().insert(
+          HeaderName::from_bytes(header.as_bytes())
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Invalid API key header '{header}'"))?,
+          HeaderValue::from_str(&key)
+            .into_diagnostic()
+            .wrap_err("Invalid API key")?,
+        );
+        Ok(())
+      }
+      Location::Query(param) => append_query(request, param, &key),
+    }
+  }
+
+  async fn login(&self, secret: Option<String>) -> Result<()> {
+    let key = match secret {
+      Some(key) => key,
+      None => prompt_secret("API key")?,
+    };
+    self.credentials()?.store(API_KEY_SETTING, &key).await
+  }
+
+  fn stores_token(&self) -> bool {
+    true
+  }
+
+  async fn store_token(&self, token: String) -> Result<()> {
+    self.credentials()?.store(API_KEY_SETTING, &token).await
+  }
+
+  async fn logout(&self) -> Result<()> {
+    self.credentials()?.erase(API_KEY_SETTING).await
+  }
+
+  fn error_help_message(&self) -> String {
+    format!(
+      "Use or check the `{}` argument, or `{}`.",
+      "--api-key".yellow(),
+      "login".yellow()
+    )
+  }
+}
+
+impl Auth {
+  fn credentials(&self) -> Result<&Credentials> {
+    self
+      .credentials
+      .as_ref()
+      .ok_or_else(|| miette!("No credential store"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Auth;
+  use clap::{Arg, Command};
+  use config::Config;
+  use crud_auth::{CrudAuth, Request};
+
+  /// The request authorized with the arguments and the settings.
+  async fn authorize(args: &[&str], settings: &[(&str, &str)]) -> Request<()> {
+    let settings = settings
+      .iter()
+      .fold(Config::builder(), |builder, (key, value)| {
+        builder.set_override(*key, *value).unwrap()
+      })
+      .build()
+      .unwrap();
+    let mut auth = Auth::default();
+    let mut app = auth.clap_auth(Command::new("test").arg(Arg::new("profile").long("profile")));
+    let matches = app.clone().get_matches_from(args);
+    auth.clap_matches(&matches, &mut app, &settings);
+    auth.prepare().await.unwrap();
+    let mut request = Request::get("https://api.local/posts?userId=1")
+      .body(())
+      .unwrap();
+    auth.authorize(&mut request).await.unwrap();
+    request
+  }
+
+  #[tokio::test]
+  async fn test_header() {
+    let request = authorize(&["test", "--api-key", "key"], &[]).await;
+    assert_eq!(request.headers()["X-API-Key"], "key");
+    assert_eq!(request.uri().query(), Some("userId=1"));
+
+    let request = authorize(
+      &["test", "--profile", "prod"],
+      &[
+        ("profile.prod.api_key", "prod"),
+        ("api_key_header", "Token"),
+      ],
+    )
+    .await;
+    assert_eq!(request.headers()["Token"], "prod");
+    assert!(request.headers().get("X-API-Key").is_none());
+  }
+
+  #[tokio::test]
+  async fn test_query() {
+    let request = authorize(
+      &["test"],
+      &[("api_key", "a&b"), ("api_key_query", "apikey")],
+    )
+    .await;
+    assert_eq!(request.uri().query(), Some("userId=1&apikey=a%26b"));
+    assert!(request.headers().is_empty());
+
+    // An empty setting overrides the query parameter set by `ApiRun`.
+    let request = authorize(
+      &["test", "--profile", "prod"],
+      &[
+        ("api_key", "key"),
+        ("api_key_query", "apikey"),
+        ("profile.prod.api_key_query", ""),
+      ],
+    )
+    .await;
+    assert_eq!(request.headers()["X-API-Key"], "key");
+  }
+
+  #[tokio::test]
+  async fn test_no_key() {
+    let request = authorize(&["test"], &[]).await;
+    assert!(request.headers().is_empty());
+    assert_eq!(request.uri().query(), Some("userId=1"));
+  }
+}
@@ -0,0 +1,207 @@
+//! ## Crud basic authentification.
+//!
+//! Authentification trait implementation for [crud-api](../crud-api):
+//! HTTP Basic authentification. The password is prompted when it's not configured.
+//!
+//! ### Configuration
+//!
+//! | configuration | cli      | description |
+//! |---------------|----------|-------------|
+//! | auth_user     | user     | User name   |
+//! | auth_password | password | Password    |
+//!
+//! The `login` command saves the user and the password in the credential store instead of the
+//! settings. `logout` removes them.
+//!
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use config::Config;
+use crud_auth::{
+  credentials::Credentials, login_commands, prompt_secret, setting, CrudAuth, Request,
+};
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+use owo_colors::OwoColorize;
+use std::sync::Mutex;
+
+#[derive(Default, Debug)]
+pub struct Auth {
+  user: Mutex<Option<String>>,
+  password: Mutex<Option<String>>,
+  credentials: Option<Credentials>,
+}
+
+const USER_ARG: &str = "auth_user";
+const USER_SETTING: &str = "auth_user";
+const PASSWORD_ARG: &str = "auth_password";
+const PASSWORD_SETTING: &str = "auth_password";
+
+#[async_trait]
+impl CrudAuth for Auth {
+  fn clap_auth(&self, app: Command) -> Command {
+    login_commands(app)
+      .arg(
+        Arg::new(USER_ARG)
+          .long("user")
+          .action(ArgAction::Set)
+          .help("User name")
+          .help_heading("Configuration"),
+      )
+      .arg(
+        Arg::new(PASSWORD_ARG)
+          .long("password")
+          .action(ArgAction::Set)
+          .help("Password. Prompted if omitted")
+          .help_heading("Configuration"),
+      )
+  }
+
+  fn clap_matches(&mut self, matches: &ArgMatches, _app: &mut Command, settings: &Config) {
+    *self.user.get_mut().unwrap() = setting(matches, settings, USER_ARG, USER_SETTING);
+    *self.password.get_mut().unwrap() = setting(matches, settings, PASSWORD_ARG, PASSWORD_SETTING);
+    self.credentials = Credentials::new(settings, matches).ok();
+  }
+
+  async fn prepare(&self) -> Result<()> {
+    let missing = self.user.lock().unwrap().is_none();
+    if let (true, Some(credentials)) = (missing, &self.credentials) {
+      let user = credentials.get(USER_SETTING).await?;
+      *self.user.lock().unwrap() = user;
+    }
+    let Some(user) = self.user.lock().unwrap().clone() else {
+      return Ok(());
+    };
+    // The lock isn't held while the password is read or prompted.
+    if self.password.lock().unwrap().is_none() {
+      let password = match &self.credentials {
+        Some(credentials) => credentials.get(PASSWORD_SETTING).await?,
+        None => None,
+      };
+      let password = match password {
+        Some(password) => password,
+        None => prompt_secret(&format!("Password for {user}"))?,
+      };
+      *self.password.lock().unwrap() = Some(password);
+    }
+    Ok(())
+  }
+
+  async fn authorize(&self, request: &mut Request<()>) -> Result<()> {
+    if let Some(user) = self.user.lock().unwrap().as_ref() {
+      let password = self.password.lock().unwrap().clone().unwrap_or_default();
+      let credentials = STANDARD.encode(format!("{user}:{password}"));
+      request.headers_mut().insert(
+        "Authorization",
+        format!("Basic {credentials}").parse().into_diagnostic()?,
+      );
+    }
+    Ok(())
+  }
+
+  async fn login(&self, secret: Option<String>) -> Result<()> {
+    let credentials = self
+      .credentials
+      .as_ref()
+      .ok_or_else(|| miette!("No credential store"))?;
+    let user = match self.user.lock().unwrap().clone() {
+      Some(user) => user,
+      None => {
+        let term = console::Term::stderr();
+        term.write_str("User: ").into_diagnostic()?;
+        term
+          .read_line()
+          .into_diagnostic()
+          .wrap_err("Can't read the user")?
+      }
+    };
+    let password = match secret {
+      Some(password) => password,
+      None => prompt_secret(&format!("Password for {user}"))?,
+    };
+    credentials.store(USER_SETTING, &user).await?;
+    credentials.store(PASSWORD_SETTING, &password).await
+  }
+
+  async fn logout(&self) -> Result<()> {
+    let credentials = self
+      .credentials
+      .as_ref()
+      .ok_or_else(|| miette!("No credential store"))?;
+    credentials.erase(USER_SETTING).await?;
+    credentials.erase(PASSWORD_SETTING).await
+  }
+
+  fn error_help_message(&self) -> String {
+    format!(
+      "Use or check the `{}` and `{}` arguments, or `{}`.",
+      "--user".yellow(),
+      "--password".yellow(),
+      "login".yellow()
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Auth;
+  use clap::{Arg, Command};
+  use config::Config;
+  use crud_auth::{CrudAuth, Request};
+
+  fn auth(args: &[&str], settings: &Config) -> Auth {
+    let mut auth = Auth::default();
+    let mut app = auth.clap_auth(Command::new("test").arg(Arg::new("profile").long("profile")));
+    let matches = app.clone().get_matches_from(args);
+    auth.clap_matches(&matches, &mut app, settings);
+    auth
+  }
+
+  async fn authorization(auth: &Auth) -> Option<String> {
+    auth.prepare().await.unwrap();
+    let mut request = Request::get("https://api.local/posts").body(()).unwrap();
+    auth.authorize(&mut request).await.unwrap();
+    request
+      .headers()
+      .get("Authorization")
+      .map(|value| value.to_str().unwrap().to_string())
+  }
+
+  #[tokio::test]
+  async fn test_authorize() {
+    let settings = Config::builder()
+      .set_override("auth_user", "bob")
+      .unwrap()
+      .set_override("profile.prod.auth_password", "secret")
+      .unwrap()
+      .build()
+      .unwrap();
+    // "alice:secret"
+    assert_eq!(
+      authorization(&auth(
+        &["test", "--profile", "prod", "--user", "alice"],
+        &settings
+      ))
+      .await
+      .as_deref(),
+      Some("Basic YWxpY2U6c2VjcmV0")
+    );
+    // "bob:pass"
+    assert_eq!(
+      authorization(&auth(&["test", "--password", "pass"], &settings))
+        .await
+        .as_deref(),
+      Some("Basic Ym9iOnBhc3M=")
+    );
+    assert_eq!(
+      authorization(&auth(&["test"], &Config::default())).await,
+      None
+    );
+  }
+
+  #[test]
+  fn test_no_short_args() {
+    let app = Auth::default().clap_auth(Command::new("test"));
+    assert!(app.get_arguments().all(|arg| arg.get_short().is_none()));
+  }
+}
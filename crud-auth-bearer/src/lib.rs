@@ -8,24 +8,33 @@
 //! |---------------|------------|---------------------|
 //! | auth_token    | auth-token | Authorization token |
 //!
+//! The `login` command saves the token in the credential store instead of the settings.
+//! `logout` removes it. A token of the settings takes precedence over the saved one.
 //!
 
+use async_trait::async_trait;
 use clap::{Arg, ArgAction, ArgMatches, Command};
 use config::Config;
-use crud_auth::CrudAuth;
+use crud_auth::{
+  credentials::Credentials, login_commands, prompt_secret, setting, CrudAuth, Request,
+};
+use miette::{miette, IntoDiagnostic, Result};
 use owo_colors::OwoColorize;
+use std::sync::Mutex;
 
 #[derive(Default, Debug)]
 pub struct Auth {
-  header: (String, String),
+  token: Mutex<Option<String>>,
+  credentials: Option<Credentials>,
 }
 
 const AUTH_TOKEN_ARG: &str = "auth_token";
 const AUTH_TOKEN_SETTING: &str = "auth_token";
 
+#[async_trait]
 impl CrudAuth for Auth {
   fn clap_auth(&self, app: Command) -> Command {
-    app.arg(
+    login_commands(app).arg(
       Arg::new(AUTH_TOKEN_ARG)
         .short('t')
         .long("auth-token")
@@ -36,48 +45,120 @@ impl CrudAuth for Auth {
   }
 
   fn clap_matches(&mut self, matches: &ArgMatches, _app: &mut Command, settings: &Config) {
-    if let Some(token) = matches.get_one::<String>(AUTH_TOKEN_ARG).cloned() {
-      self.header = ("Authorization".to_string(), "Bearer ".to_string() + &token);
-    } else if let Some(profile) = matches.get_one::<String>("profile") {
-      if let Ok(token) = settings.get_string(&format!("profile.{profile}.{}", AUTH_TOKEN_SETTING)) {
-        self.header = ("Authorization".to_string(), "Bearer ".to_string() + &token);
-      } else if let Ok(token) = settings.get_string(AUTH_TOKEN_SETTING) {
-        self.header = ("Authorization".to_string(), "Bearer ".to_string() + &token);
-      }
-    } else if let Ok(token) = settings.get_string(AUTH_TOKEN_SETTING) {
-      self.header = ("Authorization".to_string(), "Bearer ".to_string() + &token);
+    *self.token.get_mut().unwrap() = setting(matches, settings, AUTH_TOKEN_ARG, AUTH_TOKEN_SETTING);
+    self.credentials = Credentials::new(settings, matches).ok();
+  }
+
+  async fn prepare(&self) -> Result<()> {
+    if self.token.lock().unwrap().is_some() {
+      return Ok(());
+    }
+    if let Some(credentials) = &self.credentials {
+      let token = credentials.get(AUTH_TOKEN_SETTING).await?;
+      *self.token.lock().unwrap() = token;
+    }
+    Ok(())
+  }
+
+  async fn authorize(&self, request: &mut Request<()>) -> Result<()> {
+    if let Some(token) = self.token.lock().unwrap().as_ref() {
+      request.headers_mut().insert(
+        "Authorization",
+        format!("Bearer {token}").parse().into_diagnostic()?,
+      );
     }
+    Ok(())
+  }
+
+  async fn login(&self, secret: Option<String>) -> Result<()> {
+    let credentials = self.credentials()?;
+    let token = match secret {
+      Some(token) => token,
+      None => prompt_secret("Token")?,
+    };
+    credentials.store(AUTH_TOKEN_SETTING, &token).await
   }
 
-  fn auth_header(&self) -> (String, String) {
-    self.header.clone()
+  fn stores_token(&self) -> bool {
+    true
+  }
+
+  async fn store_token(&self, token: String) -> Result<()> {
+    self.credentials()?.store(AUTH_TOKEN_SETTING, &token).await
+  }
+
+  async fn logout(&self) -> Result<()> {
+    self.credentials()?.erase(AUTH_TOKEN_SETTING).await
   }
 
   fn error_help_message(&self) -> String {
-    format!("Use or check the `{}` argument.", "--auth-token".yellow())
+    format!(
+      "Use or check the `{}` argument, or `{}`.",
+      "--auth-token".yellow(),
+      "login".yellow()
+    )
   }
 }
 
-#[cfg(feature = "save_token")]
 impl Auth {
-  /// Save the token in the configuration file.
-  pub fn save_token(token: &str, settings: &Config) -> miette::Result<()> {
-    use miette::IntoDiagnostic;
-    use std::{fs, path::Path};
-    use toml_edit::{value, Document};
-
-    let config_path = settings
-      .get_string("configuration_path")
-      .into_diagnostic()?;
-    let config_str = fs::read_to_string(&config_path).unwrap_or_default();
+  fn credentials(&self) -> Result<&Credentials> {
+    self
+      .credentials
+      .as_ref()
+      .ok_or_else(|| miette!("No credential store"))
+  }
+}
 
-    let mut doc = config_str.parse::<Document>().into_diagnostic()?;
-    doc[AUTH_TOKEN_SETTING] = value(token);
+#[cfg(test)]
+mod tests {
+  use super::Auth;
+  use clap::{Arg, Command};
+  use config::Config;
+  use crud_auth::{CrudAuth, Request};
 
-    let path = Path::new(&config_path).parent().unwrap();
-    fs::create_dir_all(path).into_diagnostic()?;
-    fs::write(config_path, doc.to_string()).into_diagnostic()?;
+  async fn authorization(args: &[&str], settings: &Config) -> Option<String> {
+    let mut auth = Auth::default();
+    let mut app = auth.clap_auth(Command::new("test").arg(Arg::new("profile").long("profile")));
+    let matches = app.clone().get_matches_from(args);
+    auth.clap_matches(&matches, &mut app, settings);
+    let mut request = Request::get("https://api.local/posts").body(()).unwrap();
+    auth.authorize(&mut request).await.unwrap();
+    request
+      .headers()
+      .get("Authorization")
+      .map(|value| value.to_str().unwrap().to_string())
+  }
 
-    Ok(())
+  #[tokio::test]
+  async fn test_token_setting() {
+    let settings = Config::builder()
+      .set_override("auth_token", "default")
+      .unwrap()
+      .set_override("profile.prod.auth_token", "prod")
+      .unwrap()
+      .build()
+      .unwrap();
+    assert_eq!(
+      authorization(&["test"], &settings).await.as_deref(),
+      Some("Bearer default")
+    );
+    assert_eq!(
+      authorization(&["test", "--profile", "prod"], &settings)
+        .await
+        .as_deref(),
+      Some("Bearer prod")
+    );
+    assert_eq!(
+      authorization(&["test", "--profile", "dev"], &settings)
+        .await
+        .as_deref(),
+      Some("Bearer default")
+    );
+    assert_eq!(
+      authorization(&["test", "--profile", "prod", "-t", "arg"], &settings)
+        .await
+        .as_deref(),
+      Some("Bearer arg")
+    );
   }
 }
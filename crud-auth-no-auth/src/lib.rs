@@ -3,13 +3,16 @@
 //! Authentification trait implementation for [crud-api](../crud-api): no authentification.
 //!
 
+use async_trait::async_trait;
 use clap::{ArgMatches, Command};
 use config::Config;
-use crud_auth::CrudAuth;
+use crud_auth::{CrudAuth, Request};
+use miette::Result;
 
 #[derive(Default, Debug)]
 pub struct Auth {}
 
+#[async_trait]
 impl CrudAuth for Auth {
   fn clap_auth(&self, app: Command) -> Command {
     app
@@ -17,8 +20,8 @@ impl CrudAuth for Auth {
 
   fn clap_matches(&mut self, _matches: &ArgMatches, _app: &mut Command, _settings: &Config) {}
 
-  fn auth_header(&self) -> (String, String) {
-    ("".to_string(), "".to_string())
+  async fn authorize(&self, _request: &mut Request<()>) -> Result<()> {
+    Ok(())
   }
 
   fn error_help_message(&self) -> String {
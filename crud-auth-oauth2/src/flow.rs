@@ -0,0 +1,445 @@
+use crate::{
+  token::{Token, TokenResponse},
+  OAuth2Config,
+};
+use base64::{
+  engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+  Engine,
+};
+use crud_auth::Request;
+use hyper::{
+  header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE},
+  Method, StatusCode,
+};
+use log::{debug, warn};
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, str::FromStr, time::Duration};
+use tokio::{
+  io::{AsyncReadExt, AsyncWriteExt},
+  net::TcpListener,
+};
+
+/// Maximum time to wait for the user to authorize the application.
+const AUTHORIZATION_TIMEOUT: Duration = Duration::from_secs(300);
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+const CALLBACK_PAGE: &str =
+  "<html><body>Authorization complete, you can close this page.</body></html>";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Flow {
+  ClientCredentials,
+  AuthorizationCode,
+  DeviceCode,
+}
+
+impl FromStr for Flow {
+  type Err = miette::Report;
+
+  fn from_str(flow: &str) -> Result<Self> {
+    match flow {
+      "client_credentials" => Ok(Flow::ClientCredentials),
+      "authorization_code" => Ok(Flow::AuthorizationCode),
+      "device_code" => Ok(Flow::DeviceCode),
+      _ => Err(Flow::unknown(flow)),
+    }
+  }
+}
+
+impl Flow {
+  /// The error of an unknown `oauth2_flow` setting.
+  pub(crate) fn unknown(flow: &str) -> miette::Report {
+    miette!(
+      help = "Set `oauth2_flow` to `client_credentials`, `authorization_code` or `device_code`.",
+      "Unknown OAuth2 flow '{flow}' in the `oauth2_flow` setting"
+    )
+  }
+}
+
+/// Error returned by the token endpoint.
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+  error: String,
+  error_description: Option<String>,
+}
+
+impl ErrorResponse {
+  fn report(&self) -> miette::Report {
+    match &self.error_description {
+      Some(description) => miette!("OAuth2 error '{}': {}", self.error, description),
+      None => miette!("OAuth2 error '{}'", self.error),
+    }
+  }
+}
+
+/// Response of the device authorization endpoint.
+#[derive(Debug, Deserialize)]
+struct DeviceResponse {
+  device_code: String,
+  user_code: String,
+  verification_uri: String,
+  verification_uri_complete: Option<String>,
+  expires_in: u64,
+  interval: Option<u64>,
+}
+
+impl OAuth2Config {
+  /// Get a new token: refresh `previous` when possible, else run the flow.
+  pub async fn token(&self, previous: Option<Token>) -> Result<Token> {
+    if let Some(refresh_token) = previous.and_then(|token| token.refresh_token) {
+      match self.refresh(&refresh_token).await {
+        Ok(token) => return Ok(token),
+        Err(e) => warn!("Can't refresh the OAuth2 token: {e}"),
+      }
+    }
+    match self.flow {
+      Flow::ClientCredentials => self.client_credentials().await,
+      Flow::AuthorizationCode => self.authorization_code().await,
+      Flow::DeviceCode => self.device_code().await,
+    }
+  }
+
+  async fn refresh(&self, refresh_token: &str) -> Result<Token> {
+    debug!("Refresh the OAuth2 token");
+    let mut params = self.params("refresh_token");
+    params.insert("refresh_token", refresh_token);
+    let response = self.token_request(params).await?;
+    Ok(Token::from_response(
+      response,
+      Some(refresh_token.to_string()),
+    ))
+  }
+
+  async fn client_credentials(&self) -> Result<Token> {
+    let mut params = self.params("client_credentials");
+    if let Some(scopes) = &self.scopes {
+      params.insert("scope", scopes);
+    }
+    let response = self.token_request(params).await?;
+    Ok(Token::from_response(response, None))
+  }
+
+  async fn authorization_code(&self) -> Result<Token> {
+    let auth_url = self.auth_url.as_ref().ok_or_else(|| {
+      miette!(
+        help = "Configure `oauth2_auth_url`.",
+        "The authorization code flow needs the authorization endpoint"
+      )
+    })?;
+    let listener = TcpListener::bind(("127.0.0.1", self.redirect_port))
+      .await
+      .into_diagnostic()
+      .wrap_err("Can't listen for the OAuth2 redirection")?;
+    let redirect_uri = format!(
+      "http://127.0.0.1:{}/callback",
+      listener.local_addr().into_diagnostic()?.port()
+    );
+    let verifier = random_string(48)?;
+    let state = random_string(16)?;
+
+    let challenge = code_challenge(&verifier);
+    let mut query = BTreeMap::from([
+      ("response_type", "code"),
+      ("client_id", self.client_id.as_str()),
+      ("redirect_uri", redirect_uri.as_str()),
+      ("state", state.as_str()),
+      ("code_challenge", challenge.as_str()),
+      ("code_challenge_method", "S256"),
+    ]);
+    if let Some(scopes) = &self.scopes {
+      query.insert("scope", scopes);
+    }
+    let separator = if auth_url.contains('?') { '&' } else { '?' };
+    let url = format!(
+      "{auth_url}{separator}{}",
+      serde_qs::to_string(&query).into_diagnostic()?
+    );
+    eprintln!("Open this URL to authorize the application:\n{url}");
+    open_browser(&url);
+
+    let callback = tokio::time::timeout(AUTHORIZATION_TIMEOUT, wait_callback(&listener))
+      .await
+      .map_err(|_| miette!("The application was not authorized in time"))??;
+    if let Some(error) = callback.get("error") {
+      return Err(
+        ErrorResponse {
+          error: error.clone(),
+          error_description: callback.get("error_description").cloned(),
+        }
+        .report(),
+      );
+    }
+    if callback.get("state") != Some(&state) {
+      return Err(miette!("The OAuth2 state doesn't match"));
+    }
+    let code = callback
+      .get("code")
+      .ok_or_else(|| miette!("No authorization code in the OAuth2 redirection"))?;
+
+    let mut params = self.params("authorization_code");
+    params.insert("code", code);
+    params.insert("redirect_uri", &redirect_uri);
+    params.insert("code_verifier", &verifier);
+    let response = self.token_request(params).await?;
+    Ok(Token::from_response(response, None))
+  }
+
+  async fn device_code(&self) -> Result<Token> {
+    let device_url = self.device_url.as_ref().ok_or_else(|| {
+      miette!(
+        help = "Configure `oauth2_device_url`.",
+        "The device code flow needs the device authorization endpoint"
+      )
+    })?;
+    let mut params = BTreeMap::from([("client_id", self.client_id.as_str())]);
+    if let Some(scopes) = &self.scopes {
+      params.insert("scope", scopes);
+    }
+    let (status, body) = self.post_form(device_url, &params).await?;
+    if !status.is_success() {
+      return Err(error_response(status, &body));
+    }
+    let device: DeviceResponse = serde_json::from_slice(&body)
+      .into_diagnostic()
+      .wrap_err("Invalid device authorization response")?;
+    match &device.verification_uri_complete {
+      Some(uri) => eprintln!("Open {uri} and check the code {}", device.user_code),
+      None => eprintln!(
+        "Open {} and enter the code {}",
+        device.verification_uri, device.user_code
+      ),
+    }
+
+    let mut params = self.params(DEVICE_CODE_GRANT);
+    params.insert("device_code", &device.device_code);
+    let mut interval = Duration::from_secs(device.interval.unwrap_or(5));
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(device.expires_in);
+    while tokio::time::Instant::now() < deadline {
+      tokio::time::sleep(interval).await;
+      let (status, body) = self.post_form(&self.token_url, &params).await?;
+      if status.is_success() {
+        let response = serde_json::from_slice(&body)
+          .into_diagnostic()
+          .wrap_err("Invalid token response")?;
+        return Ok(Token::from_response(response, None));
+      }
+      match serde_json::from_slice::<ErrorResponse>(&body) {
+        Ok(error) if error.error == "authorization_pending" => (),
+        Ok(error) if error.error == "slow_down" => interval += Duration::from_secs(5),
+        _ => return Err(error_response(status, &body)),
+      }
+    }
+    Err(miette!("The device code has expired"))
+  }
+
+  /// Parameters common to the token requests.
+  fn params<'a>(&'a self, grant_type: &'a str) -> BTreeMap<&'a str, &'a str> {
+    let mut params = BTreeMap::from([("grant_type", grant_type)]);
+    // Public clients identify themselves in the body.
+    if self.client_secret.is_none() {
+      params.insert("client_id", &self.client_id);
+    }
+    params
+  }
+
+  async fn token_request(&self, params: BTreeMap<&str, &str>) -> Result<TokenResponse> {
+    let (status, body) = self.post_form(&self.token_url, &params).await?;
+    if !status.is_success() {
+      return Err(error_response(status, &body));
+    }
+    serde_json::from_slice(&body)
+      .into_diagnostic()
+      .wrap_err("Invalid token response")
+  }
+
+  /// Post a form with the HTTP client of the application.
+  async fn post_form(
+    &self,
+    url: &str,
+    params: &BTreeMap<&str, &str>,
+  ) -> Result<(StatusCode, Vec<u8>)> {
+    let client = self
+      .client
+      .as_ref()
+      .ok_or_else(|| miette!("No HTTP client for the OAuth2 requests"))?;
+    let mut request = Request::builder()
+      .method(Method::POST)
+      .uri(url)
+      .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+      .header(ACCEPT, "application/json");
+    if let Some(secret) = &self.client_secret {
+      let credentials = STANDARD.encode(format!("{}:{}", self.client_id, secret));
+      request = request.header(AUTHORIZATION, format!("Basic {credentials}"));
+    }
+    let body = serde_qs::to_string(params).into_diagnostic()?;
+    let request = request.body(body.into_bytes()).into_diagnostic()?;
+    let response = client
+      .0
+      .send(request)
+      .await
+      .wrap_err_with(|| format!("Can't reach the OAuth2 endpoint {url}"))?;
+    Ok((response.status(), response.into_body()))
+  }
+}
+
+fn error_response(status: StatusCode, body: &[u8]) -> miette::Report {
+  match serde_json::from_slice::<ErrorResponse>(body) {
+    Ok(error) => error.report(),
+    Err(_) => miette!(
+      "The OAuth2 endpoint answered {status}: {}",
+      String::from_utf8_lossy(body)
+    ),
+  }
+}
+
+/// Random string safe in URLs, from `len` random bytes.
+fn random_string(len: usize) -> Result<String> {
+  let mut bytes = vec![0; len];
+  getrandom::getrandom(&mut bytes).map_err(|e| miette!("Can't generate random bytes: {e}"))?;
+  Ok(URL_SAFE_NO_PAD.encode(bytes))
+}
+
+/// PKCE `S256` challenge of `verifier`.
+fn code_challenge(verifier: &str) -> String {
+  URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+fn open_browser(url: &str) {
+  let command = if cfg!(target_os = "macos") {
+    std::process::Command::new("open").arg(url).spawn()
+  } else if cfg!(target_os = "windows") {
+    std::process::Command::new("cmd")
+      .args(["/C", "start", "", url])
+      .spawn()
+  } else {
+    std::process::Command::new("xdg-open").arg(url).spawn()
+  };
+  if let Err(e) = command {
+    debug!("Can't open the browser: {e}");
+  }
+}
+
+/// Wait for the redirection to `/callback` and return its query parameters.
+async fn wait_callback(listener: &TcpListener) -> Result<BTreeMap<String, String>> {
+  loop {
+    let (mut stream, _) = listener.accept().await.into_diagnostic()?;
+    let mut buffer = vec![0; 8192];
+    let len = stream.read(&mut buffer).await.into_diagnostic()?;
+    let request = String::from_utf8_lossy(&buffer[..len]);
+    match callback_query(&request) {
+      Some(query) => {
+        let response = format!(
+          "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{CALLBACK_PAGE}",
+          CALLBACK_PAGE.len()
+        );
+        stream
+          .write_all(response.as_bytes())
+          .await
+          .into_diagnostic()?;
+        return Ok(query);
+      }
+      None => {
+        let _ = stream
+          .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+          .await;
+      }
+    }
+  }
+}
+
+/// Query parameters of a `GET /callback` request.
+fn callback_query(request: &str) -> Option<BTreeMap<String, String>> {
+  let target = request
+    .lines()
+    .next()?
+    .strip_prefix("GET ")?
+    .split(' ')
+    .next()?;
+  let query = match target.split_once('?') {
+    Some(("/callback", query)) => query,
+    _ if target == "/callback" => "",
+    _ => return None,
+  };
+  serde_qs::from_str(query).ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{callback_query, code_challenge, Flow};
+  use crate::{HttpClient, OAuth2Config};
+  use async_trait::async_trait;
+  use crud_auth::{AuthClient, Request, Response};
+  use miette::Result;
+  use std::sync::{Arc, Mutex};
+
+  /// Answers a token and keeps the requests.
+  #[derive(Default)]
+  struct StubClient {
+    requests: Mutex<Vec<Request<Vec<u8>>>>,
+  }
+
+  #[async_trait]
+  impl AuthClient for StubClient {
+    async fn send(&self, request: Request<Vec<u8>>) -> Result<Response<Vec<u8>>> {
+      self.requests.lock().unwrap().push(request);
+      Ok(Response::new(
+        br#"{"access_token":"t0k3n","expires_in":3600}"#.to_vec(),
+      ))
+    }
+  }
+
+  #[tokio::test]
+  async fn test_token_request() {
+    let client = Arc::new(StubClient::default());
+    let config = OAuth2Config {
+      flow: Flow::ClientCredentials,
+      client_id: "cli".to_string(),
+      client_secret: Some("s3cr3t".to_string()),
+      token_url: "https://auth.local/token".to_string(),
+      auth_url: None,
+      device_url: None,
+      scopes: Some("read write".to_string()),
+      redirect_port: 0,
+      client: Some(HttpClient(client.clone())),
+    };
+    let token = config.token(None).await.unwrap();
+    assert_eq!(token.access_token, "t0k3n");
+
+    let requests = client.requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].uri(), "https://auth.local/token");
+    assert_eq!(
+      requests[0].headers()["authorization"],
+      "Basic Y2xpOnMzY3IzdA=="
+    );
+    assert_eq!(
+      String::from_utf8_lossy(requests[0].body()),
+      "grant_type=client_credentials&scope=read+write"
+    );
+  }
+
+  #[test]
+  fn test_code_challenge() {
+    // RFC 7636, Appendix B.
+    assert_eq!(
+      code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+      "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+    );
+  }
+
+  #[test]
+  fn test_callback_query() {
+    let query =
+      callback_query("GET /callback?code=abc&state=xyz HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n").unwrap();
+    assert_eq!(query.get("code").map(String::as_str), Some("abc"));
+    assert_eq!(query.get("state").map(String::as_str), Some("xyz"));
+    assert!(callback_query("GET /favicon.ico HTTP/1.1\r\n\r\n").is_none());
+  }
+
+  #[test]
+  fn test_flow() {
+    assert_eq!("device_code".parse::<Flow>().unwrap(), Flow::DeviceCode);
+    assert!("implicit".parse::<Flow>().is_err());
+  }
+}
@@ -0,0 +1,281 @@
+//! ## Crud OAuth2 authentification.
+//!
+//! Authentification trait implementation for [crud-api](../crud-api) with the OAuth2 flows:
+//!
+//! - `client_credentials`: the application authenticates itself with its client id and secret.
+//! - `authorization_code`: the user authorizes the application in the browser. The code is
+//!   received on a loopback redirect (`http://127.0.0.1:<port>/callback`) and protected by PKCE.
+//! - `device_code`: the user enters a code on a verification page, from any device.
+//!
+//! The tokens are saved per profile in the credential store and refreshed when they expire
+//! or when the API answers `401 Unauthorized`. The `login` command runs the flow and `logout`
+//! removes the tokens.
+//!
+//! ### Configuration
+//!
+//! | configuration        | cli           | description                                                 |
+//! |----------------------|---------------|-------------------------------------------------------------|
+//! | oauth2_flow          |               | `client_credentials`, `authorization_code` or `device_code` |
+//! | oauth2_client_id     | client-id     | Client id                                                   |
+//! | oauth2_client_secret | client-secret | Client secret (optional for the public clients)             |
+//! | oauth2_token_url     |               | Token endpoint                                              |
+//! | oauth2_auth_url      |               | Authorization endpoint (`authorization_code`)               |
+//! | oauth2_device_url    |               | Device authorization endpoint (`device_code`)               |
+//! | oauth2_scopes        |               | Scopes separated by spaces                                  |
+//! | oauth2_redirect_port |               | Port of the loopback redirect. Default: a free port         |
+//!
+//! The settings can be set by profile:
+//! ```toml
+//! [profile.prod]
+//! oauth2_flow = "device_code"
+//! oauth2_client_id = "my-cli"
+//! oauth2_token_url = "https://auth.example.com/oauth/token"
+//! oauth2_device_url = "https://auth.example.com/oauth/device/code"
+//! ```
+//!
+
+mod flow;
+mod token;
+
+use async_trait::async_trait;
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use config::Config;
+use crud_auth::{credentials::Credentials, login_commands, setting, AuthClient, CrudAuth, Request};
+use flow::Flow;
+use log::warn;
+use miette::{miette, IntoDiagnostic, Result};
+use owo_colors::OwoColorize;
+use std::{fmt, sync::Arc};
+use token::Token;
+use tokio::sync::Mutex;
+
+const CLIENT_ID_ARG: &str = "oauth2_client_id";
+const CLIENT_SECRET_ARG: &str = "oauth2_client_secret";
+const TOKEN_CREDENTIAL: &str = "oauth2_token";
+
+/// OAuth2 configuration read from the settings.
+#[derive(Debug, Clone)]
+pub(crate) struct OAuth2Config {
+  pub flow: Flow,
+  pub client_id: String,
+  pub client_secret: Option<String>,
+  pub token_url: String,
+  pub auth_url: Option<String>,
+  pub device_url: Option<String>,
+  pub scopes: Option<String>,
+  pub redirect_port: u16,
+  pub client: Option<HttpClient>,
+}
+
+/// The HTTP client of the application, for the requests to the OAuth2 endpoints.
+#[derive(Clone)]
+pub(crate) struct HttpClient(pub Arc<dyn AuthClient>);
+
+impl fmt::Debug for HttpClient {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("HttpClient")
+  }
+}
+
+#[derive(Default, Debug)]
+pub struct Auth {
+  config: Option<OAuth2Config>,
+  /// The `oauth2_flow` setting when it's unknown.
+  invalid_flow: Option<String>,
+  credentials: Option<Credentials>,
+  token: Mutex<Option<Token>>,
+}
+
+#[async_trait]
+impl CrudAuth for Auth {
+  fn clap_auth(&self, app: Command) -> Command {
+    login_commands(app)
+      .arg(
+        Arg::new(CLIENT_ID_ARG)
+          .long("client-id")
+          .action(ArgAction::Set)
+          .help("OAuth2 client id")
+          .help_heading("Configuration"),
+      )
+      .arg(
+        Arg::new(CLIENT_SECRET_ARG)
+          .long("client-secret")
+          .action(ArgAction::Set)
+          .help("OAuth2 client secret")
+          .help_heading("Configuration"),
+      )
+  }
+
+  fn clap_matches(&mut self, matches: &ArgMatches, _app: &mut Command, settings: &Config) {
+    let setting = |key: &str| setting(matches, settings, key, key);
+    let flow = setting("oauth2_flow").unwrap_or_else(|| "client_credentials".to_string());
+    self.config = None;
+    self.invalid_flow = None;
+    match flow.parse::<Flow>() {
+      Ok(flow) => {
+        if let (Some(client_id), Some(token_url)) =
+          (setting(CLIENT_ID_ARG), setting("oauth2_token_url"))
+        {
+          self.config = Some(OAuth2Config {
+            flow,
+            client_id,
+            client_secret: setting(CLIENT_SECRET_ARG),
+            token_url,
+            auth_url: setting("oauth2_auth_url"),
+            device_url: setting("oauth2_device_url"),
+            scopes: setting("oauth2_scopes"),
+            redirect_port: setting("oauth2_redirect_port")
+              .and_then(|port| port.parse().ok())
+              .unwrap_or_default(),
+            client: None,
+          });
+        }
+      }
+      // Reported when the credentials are needed.
+      Err(_) => self.invalid_flow = Some(flow),
+    }
+    self.credentials = Credentials::new(settings, matches).ok();
+  }
+
+  fn set_client(&mut self, client: Arc<dyn AuthClient>) {
+    if let Some(config) = &mut self.config {
+      config.client = Some(HttpClient(client));
+    }
+  }
+
+  async fn prepare(&self) -> Result<()> {
+    let Some(config) = self.config()? else {
+      return Ok(());
+    };
+    let mut token = self.token.lock().await;
+    if let (None, Some(credentials)) = (token.as_ref(), &self.credentials) {
+      *token = credentials
+        .get(TOKEN_CREDENTIAL)
+        .await?
+        .and_then(|token| serde_json::from_str(&token).ok());
+    }
+    if token.as_ref().is_none_or(Token::is_expired) {
+      *token = Some(self.renew(config, token.take()).await?);
+    }
+    Ok(())
+  }
+
+  async fn authorize(&self, request: &mut Request<()>) -> Result<()> {
+    if let Some(token) = self.token.lock().await.as_ref() {
+      request.headers_mut().insert(
+        "Authorization",
+        format!("Bearer {}", token.access_token)
+          .parse()
+          .into_diagnostic()?,
+      );
+    }
+    Ok(())
+  }
+
+  fn identity(&self) -> Option<String> {
+    // The token changes on each refresh: the user is identified by the client and the profile.
+    let config = self.config.as_ref()?;
+    Some(format!(
+      "{} {} {}",
+      config.token_url,
+      config.client_id,
+      self
+        .credentials
+        .as_ref()
+        .and_then(Credentials::profile)
+        .unwrap_or_default()
+    ))
+  }
+
+  async fn refresh(&self) -> Result<bool> {
+    let Some(config) = self.config()? else {
+      return Ok(false);
+    };
+    let mut token = self.token.lock().await;
+    *token = Some(self.renew(config, token.take()).await?);
+    Ok(true)
+  }
+
+  async fn login(&self, _secret: Option<String>) -> Result<()> {
+    let config = self
+      .config()?
+      .ok_or_else(|| miette!(help = self.error_help_message(), "OAuth2 is not configured"))?;
+    *self.token.lock().await = Some(self.renew(config, None).await?);
+    Ok(())
+  }
+
+  async fn logout(&self) -> Result<()> {
+    *self.token.lock().await = None;
+    self
+      .credentials
+      .as_ref()
+      .ok_or_else(|| miette!("No credential store"))?
+      .erase(TOKEN_CREDENTIAL)
+      .await
+  }
+
+  fn error_help_message(&self) -> String {
+    if self.config.is_none() {
+      format!(
+        "Configure `{}`, `{}` and `{}`.",
+        "oauth2_client_id".yellow(),
+        "oauth2_token_url".yellow(),
+        "oauth2_flow".yellow()
+      )
+    } else {
+      format!(
+        "Check your OAuth2 settings or log in again with `{}`.",
+        "login".yellow()
+      )
+    }
+  }
+}
+
+impl Auth {
+  /// The configuration, if OAuth2 is configured.
+  fn config(&self) -> Result<Option<&OAuth2Config>> {
+    match &self.invalid_flow {
+      Some(flow) => Err(Flow::unknown(flow)),
+      None => Ok(self.config.as_ref()),
+    }
+  }
+
+  /// Get a new token and save it.
+  async fn renew(&self, config: &OAuth2Config, previous: Option<Token>) -> Result<Token> {
+    let token = config.token(previous).await?;
+    if let Some(credentials) = &self.credentials {
+      let json = serde_json::to_string(&token).into_diagnostic()?;
+      if let Err(e) = credentials.store(TOKEN_CREDENTIAL, &json).await {
+        warn!("Can't save the OAuth2 token: {e:?}");
+      }
+    }
+    Ok(token)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::Auth;
+  use clap::Command;
+  use config::Config;
+  use crud_auth::CrudAuth;
+
+  #[tokio::test]
+  async fn test_invalid_flow() {
+    let settings = Config::builder()
+      .set_override("oauth2_flow", "implicit")
+      .unwrap()
+      .build()
+      .unwrap();
+    let mut auth = Auth::default();
+    let mut app = auth.clap_auth(Command::new("test"));
+    let matches = app.clone().get_matches_from(["test"]);
+    auth.clap_matches(&matches, &mut app, &settings);
+    let error = auth.prepare().await.unwrap_err();
+    assert_eq!(
+      error.to_string(),
+      "Unknown OAuth2 flow 'implicit' in the `oauth2_flow` setting"
+    );
+    assert!(auth.login(None).await.is_err());
+  }
+}
@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The token is refreshed this number of seconds before its expiration.
+const EXPIRATION_MARGIN: u64 = 30;
+
+/// Response of the token endpoint.
+#[derive(Debug, Deserialize)]
+pub(crate) struct TokenResponse {
+  pub access_token: String,
+  pub expires_in: Option<u64>,
+  pub refresh_token: Option<String>,
+}
+
+/// A cached token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Token {
+  pub access_token: String,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub refresh_token: Option<String>,
+  /// Expiration as seconds since the epoch.
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub expires_at: Option<u64>,
+}
+
+impl Token {
+  /// Build the token from a response. `previous` refresh token is kept if the
+  /// response doesn't contain a new one.
+  pub fn from_response(response: TokenResponse, previous: Option<String>) -> Token {
+    Token {
+      access_token: response.access_token,
+      refresh_token: response.refresh_token.or(previous),
+      expires_at: response.expires_in.map(|expires_in| now() + expires_in),
+    }
+  }
+
+  pub fn is_expired(&self) -> bool {
+    self
+      .expires_at
+      .is_some_and(|expires_at| expires_at <= now() + EXPIRATION_MARGIN)
+  }
+}
+
+fn now() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|now| now.as_secs())
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{now, Token, TokenResponse};
+
+  #[test]
+  fn test_token_expiration() {
+    let token = Token::from_response(
+      TokenResponse {
+        access_token: "access".into(),
+        expires_in: Some(3600),
+        refresh_token: None,
+      },
+      Some("refresh".into()),
+    );
+    assert_eq!(token.refresh_token, Some("refresh".into()));
+    assert!(!token.is_expired());
+
+    let token = Token {
+      expires_at: Some(now() + 10),
+      ..token
+    };
+    assert!(token.is_expired());
+    let token = Token {
+      expires_at: None,
+      ..token
+    };
+    assert!(!token.is_expired());
+  }
+}
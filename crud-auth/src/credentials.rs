@@ -0,0 +1,372 @@
+//! Storage of the secrets (tokens, passwords...) outside of the settings.
+//!
+//! The store is selected by the settings:
+//! - `credential_helper`: a command called with `get`, `store` or `erase`, like the git credential
+//!   helpers. The request is written on its standard input as `key=value` lines ended by
+//!   an empty line: `application`, `profile` (if any), `key` and, for `store`, `secret`.
+//!   `get` answers with a `secret=<value>` line.
+//! - else the file `credentials.toml` next to the settings, only readable by the user.
+//!   It has the layout of the settings: the secrets of the profiles are in `[profile.<name>]` tables.
+//!
+//! The stores are blocking: [Credentials] calls them on the blocking threads of the runtime.
+
+use clap::ArgMatches;
+use config::Config;
+use miette::{bail, miette, IntoDiagnostic, Result, WrapErr};
+use std::{
+  fmt::Debug,
+  fs,
+  io::Write,
+  path::{Path, PathBuf},
+  process::{Command, Stdio},
+  sync::Arc,
+};
+use tokio::task::spawn_blocking;
+use toml_edit::{value, DocumentMut, Item, Table};
+
+const CREDENTIALS_FILE: &str = "credentials.toml";
+
+/// A backend storing the secrets by profile and key.
+pub trait CredentialStore: Debug + Send + Sync {
+  fn get(&self, profile: Option<&str>, key: &str) -> Result<Option<String>>;
+  fn store(&self, profile: Option<&str>, key: &str, secret: &str) -> Result<()>;
+  fn erase(&self, profile: Option<&str>, key: &str) -> Result<()>;
+}
+
+/// The credentials of the selected profile.
+#[derive(Debug)]
+pub struct Credentials {
+  store: Arc<dyn CredentialStore>,
+  profile: Option<String>,
+}
+
+impl Credentials {
+  /// The credentials of the profile selected by `--profile`, in the store configured by the settings.
+  pub fn new(settings: &Config, matches: &ArgMatches) -> Result<Credentials> {
+    let profile = matches
+      .try_get_one::<String>("profile")
+      .ok()
+      .flatten()
+      .cloned();
+    let helper = profile
+      .as_ref()
+      .and_then(|profile| {
+        settings
+          .get_string(&format!("profile.{profile}.credential_helper"))
+          .ok()
+      })
+      .or_else(|| settings.get_string("credential_helper").ok());
+    let store: Arc<dyn CredentialStore> = match helper {
+      Some(command) => Arc::new(HelperStore {
+        command,
+        application: settings.get_string("application").unwrap_or_default(),
+      }),
+      None => Arc::new(FileStore {
+        path: settings
+          .get_string("configuration_path")
+          .ok()
+          .and_then(|path| {
+            Path::new(&path)
+              .parent()
+              .map(|dir| dir.join(CREDENTIALS_FILE))
+          })
+          .ok_or_else(|| miette!("No settings directory to store the credentials"))?,
+      }),
+    };
+    Ok(Credentials { store, profile })
+  }
+
+  /// The profile of the credentials.
+  pub fn profile(&self) -> Option<&str> {
+    self.profile.as_deref()
+  }
+
+  pub async fn get(&self, key: &str) -> Result<Option<String>> {
+    let key = key.to_string();
+    self
+      .call(move |store, profile| store.get(profile, &key))
+      .await
+  }
+
+  pub async fn store(&self, key: &str, secret: &str) -> Result<()> {
+    let (key, secret) = (key.to_string(), secret.to_string());
+    self
+      .call(move |store, profile| store.store(profile, &key, &secret))
+      .await
+  }
+
+  pub async fn erase(&self, key: &str) -> Result<()> {
+    let key = key.to_string();
+    self
+      .call(move |store, profile| store.erase(profile, &key))
+      .await
+  }
+
+  /// Call the store on a blocking thread.
+  async fn call<T: Send + 'static>(
+    &self,
+    call: impl FnOnce(&dyn CredentialStore, Option<&str>) -> Result<T> + Send + 'static,
+  ) -> Result<T> {
+    let store = self.store.clone();
+    let profile = self.profile.clone();
+    spawn_blocking(move || call(store.as_ref(), profile.as_deref()))
+      .await
+      .into_diagnostic()?
+  }
+}
+
+/// Secrets stored in `credentials.toml`.
+#[derive(Debug)]
+pub struct FileStore {
+  pub path: PathBuf,
+}
+
+impl FileStore {
+  fn read(&self) -> Result<DocumentMut> {
+    fs::read_to_string(&self.path)
+      .unwrap_or_default()
+      .parse::<DocumentMut>()
+      .into_diagnostic()
+      .wrap_err_with(|| format!("Invalid credentials file {}", self.path.display()))
+  }
+
+  fn write(&self, doc: &DocumentMut) -> Result<()> {
+    write_private(&self.path, doc.to_string().as_bytes())
+  }
+}
+
+/// The table of the profile.
+fn profile_table<'a>(doc: &'a mut DocumentMut, profile: Option<&str>) -> Option<&'a mut Table> {
+  match profile {
+    Some(profile) => doc
+      .get_mut("profile")?
+      .as_table_mut()?
+      .get_mut(profile)?
+      .as_table_mut(),
+    None => Some(doc.as_table_mut()),
+  }
+}
+
+impl CredentialStore for FileStore {
+  fn get(&self, profile: Option<&str>, key: &str) -> Result<Option<String>> {
+    let mut doc = self.read()?;
+    Ok(
+      profile_table(&mut doc, profile)
+        .and_then(|table| table.get(key))
+        .and_then(Item::as_str)
+        .map(str::to_string),
+    )
+  }
+
+  fn store(&self, profile: Option<&str>, key: &str, secret: &str) -> Result<()> {
+    let mut doc = self.read()?;
+    match profile {
+      Some(profile) => {
+        let profiles = doc
+          .entry("profile")
+          .or_insert_with(|| {
+            let mut table = Table::new();
+            table.set_implicit(true);
+            Item::Table(table)
+          })
+          .as_table_mut()
+          .ok_or_else(|| miette!("`profile` should be a table"))?;
+        profiles
+          .entry(profile)
+          .or_insert_with(|| Item::Table(Table::new()))
+          .as_table_mut()
+          .ok_or_else(|| miette!("`profile.{profile}` should be a table"))?
+          .insert(key, value(secret));
+      }
+      None => {
+        doc.insert(key, value(secret));
+      }
+    }
+    self.write(&doc)
+  }
+
+  fn erase(&self, profile: Option<&str>, key: &str) -> Result<()> {
+    let mut doc = self.read()?;
+    if let Some(table) = profile_table(&mut doc, profile) {
+      if table.remove(key).is_some() {
+        return self.write(&doc);
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Secrets managed by an external command.
+#[derive(Debug)]
+pub struct HelperStore {
+  pub command: String,
+  /// Name of the application, sent to the helper.
+  pub application: String,
+}
+
+impl HelperStore {
+  fn call(
+    &self,
+    action: &str,
+    profile: Option<&str>,
+    key: &str,
+    secret: Option<&str>,
+  ) -> Result<String> {
+    let mut request = format!("application={}\n", self.application);
+    if let Some(profile) = profile {
+      request.push_str(&format!("profile={profile}\n"));
+    }
+    request.push_str(&format!("key={key}\n"));
+    if let Some(secret) = secret {
+      request.push_str(&format!("secret={secret}\n"));
+    }
+    request.push('\n');
+
+    let command = format!("{} {action}", self.command);
+    let mut child = if cfg!(target_os = "windows") {
+      Command::new("cmd")
+        .args(["/C", &command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+    } else {
+      Command::new("sh")
+        .args(["-c", &command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+    }
+    .into_diagnostic()
+    .wrap_err_with(|| format!("Can't run the credential helper `{}`", self.command))?;
+    if let Some(mut stdin) = child.stdin.take() {
+      stdin.write_all(request.as_bytes()).into_diagnostic()?;
+    }
+    let output = child.wait_with_output().into_diagnostic()?;
+    if !output.status.success() {
+      bail!(
+        "The credential helper `{}` failed: {}",
+        self.command,
+        output.status
+      );
+    }
+    String::from_utf8(output.stdout).into_diagnostic()
+  }
+}
+
+impl CredentialStore for HelperStore {
+  fn get(&self, profile: Option<&str>, key: &str) -> Result<Option<String>> {
+    let output = self.call("get", profile, key, None)?;
+    Ok(
+      output
+        .lines()
+        .find_map(|line| line.strip_prefix("secret="))
+        .map(str::to_string),
+    )
+  }
+
+  fn store(&self, profile: Option<&str>, key: &str, secret: &str) -> Result<()> {
+    self.call("store", profile, key, Some(secret)).map(|_| ())
+  }
+
+  fn erase(&self, profile: Option<&str>, key: &str) -> Result<()> {
+    self.call("erase", profile, key, None).map(|_| ())
+  }
+}
+
+/// Write a file only readable by the user.
+pub fn write_private(path: &Path, contents: &[u8]) -> Result<()> {
+  if let Some(dir) = path.parent() {
+    fs::create_dir_all(dir)
+      .into_diagnostic()
+      .wrap_err_with(|| format!("Can't create the directory {}", dir.display()))?;
+  }
+  let mut options = fs::OpenOptions::new();
+  options.write(true).create(true).truncate(true);
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    options.mode(0o600);
+    // The mode is only applied to the new files.
+    if path.exists() {
+      fs::set_permissions(path, fs::Permissions::from_mode(0o600)).into_diagnostic()?;
+    }
+  }
+  options
+    .open(path)
+    .and_then(|mut file| file.write_all(contents))
+    .into_diagnostic()
+    .wrap_err_with(|| format!("Can't write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{CredentialStore, Credentials, FileStore, HelperStore};
+  use std::{fs, sync::Arc};
+
+  #[test]
+  fn test_file_store() {
+    let dir = std::env::temp_dir().join(format!("crud-auth-credentials-{}", std::process::id()));
+    let store = FileStore {
+      path: dir.join("credentials.toml"),
+    };
+    store.store(None, "auth_token", "default").unwrap();
+    store.store(Some("prod"), "auth_token", "prod").unwrap();
+    assert_eq!(
+      store.get(None, "auth_token").unwrap(),
+      Some("default".to_string())
+    );
+    assert_eq!(
+      store.get(Some("prod"), "auth_token").unwrap(),
+      Some("prod".to_string())
+    );
+    assert_eq!(store.get(Some("dev"), "auth_token").unwrap(), None);
+    assert_eq!(
+      fs::read_to_string(&store.path).unwrap(),
+      "auth_token = \"default\"\n\n[profile.prod]\nauth_token = \"prod\"\n"
+    );
+    #[cfg(unix)]
+    {
+      use std::os::unix::fs::PermissionsExt;
+      let mode = fs::metadata(&store.path).unwrap().permissions().mode();
+      assert_eq!(mode & 0o777, 0o600);
+    }
+
+    store.erase(Some("prod"), "auth_token").unwrap();
+    assert_eq!(store.get(Some("prod"), "auth_token").unwrap(), None);
+    fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn test_helper_store() {
+    let store = HelperStore {
+      command: "read a; read p; read k; echo \"secret=$a,$p,$k\" #".to_string(),
+      application: "app".to_string(),
+    };
+    assert_eq!(
+      store.get(Some("prod"), "auth_token").unwrap(),
+      Some("application=app,profile=prod,key=auth_token".to_string())
+    );
+    let failing = HelperStore {
+      command: "false".to_string(),
+      application: "app".to_string(),
+    };
+    assert!(failing.store(None, "auth_token", "secret").is_err());
+  }
+
+  #[cfg(unix)]
+  #[tokio::test]
+  async fn test_credentials() {
+    let credentials = Credentials {
+      store: Arc::new(HelperStore {
+        command: "read a; read p; read k; sleep 0.1; echo \"secret=$p,$k\" #".to_string(),
+        application: "app".to_string(),
+      }),
+      profile: Some("prod".to_string()),
+    };
+    assert_eq!(
+      credentials.get("auth_token").await.unwrap(),
+      Some("profile=prod,key=auth_token".to_string())
+    );
+  }
+}
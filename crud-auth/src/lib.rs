@@ -2,10 +2,36 @@
 //!
 //! Authentification trait crate for [crud-api](../crud-api).
 //!
+//! For each request, [crud-api](../crud-api):
+//! 1. calls [CrudAuth::prepare] to get or renew the credentials,
+//! 2. calls [CrudAuth::authorize] to add them to the request (headers, query parameters...),
+//! 3. calls [CrudAuth::refresh] if the server answers `401 Unauthorized`. When the credentials
+//!    are refreshed, the request is authorized and sent again, once.
+//!
+//! The implementations can add the `login` and `logout` commands with [login_commands]. They save
+//! the secrets in the [credential store](credentials) instead of the settings.
+//!
+//! The implementations that send their own requests (ie. to get an OAuth2 token) send them with
+//! the [AuthClient] given to [CrudAuth::set_client]: the HTTP client of the application, with its
+//! proxy, TLS and timeout settings.
+//!
+
+pub mod credentials;
 
 use async_trait::async_trait;
 use clap::{ArgMatches, Command};
 use config::Config;
+use http::{uri::PathAndQuery, Uri};
+pub use http::{Request, Response};
+use miette::{miette, IntoDiagnostic, Result, WrapErr};
+use std::{collections::BTreeMap, sync::Arc};
+
+/// The HTTP client of the application, for the requests of the authentication.
+#[async_trait]
+pub trait AuthClient: Send + Sync {
+  /// Send the request and read the response.
+  async fn send(&self, request: Request<Vec<u8>>) -> Result<Response<Vec<u8>>>;
+}
 
 /// Authification module for Crud.
 /// Import an implementation of this trait to manage authification.
@@ -19,18 +45,178 @@ pub trait CrudAuth {
   /// Process the arguments created by [clap_auth] function.
   fn clap_matches(&mut self, args: &ArgMatches, app: &mut Command, settings: &Config);
 
-  /// Create an authentification header as a pair (_name_,_valeur_)
-  fn auth_header(&self) -> (String, String);
+  /// Give the HTTP client of the application. It's called after [CrudAuth::clap_matches].
+  fn set_client(&mut self, _client: Arc<dyn AuthClient>) {}
+
+  /// Get the credentials before sending a request: read a cache, renew an expired token...
+  ///
+  /// It's called before each request and should be cheap when the credentials are ready.
+  async fn prepare(&self) -> Result<()> {
+    Ok(())
+  }
+
+  /// Add the credentials to the request. The body is set after.
+  async fn authorize(&self, request: &mut Request<()>) -> Result<()>;
+
+  /// Identify the user of the credentials in the response cache.
+  ///
+  /// It shouldn't change when the credentials are refreshed. When it's `None`, the user is
+  /// identified by the credentials added by [CrudAuth::authorize].
+  fn identity(&self) -> Option<String> {
+    None
+  }
+
+  /// Renew the credentials after a `401 Unauthorized` response.
+  ///
+  /// Returns `true` if the credentials changed and the request should be sent again.
+  async fn refresh(&self) -> Result<bool> {
+    Ok(false)
+  }
+
+  /// Log in and save the credentials in the [credential store](credentials).
+  ///
+  /// `secret` (a token, a password...) is read from the standard input when it's not a terminal.
+  /// The implementation should prompt it when it's `None`.
+  async fn login(&self, _secret: Option<String>) -> Result<()> {
+    Err(miette!("The authentification doesn't support the login"))
+  }
+
+  /// The authentification can save the token of a login endpoint with
+  /// [CrudAuth::store_token].
+  fn stores_token(&self) -> bool {
+    false
+  }
+
+  /// Save the token received from the login endpoint of the API in the
+  /// [credential store](credentials).
+  async fn store_token(&self, _token: String) -> Result<()> {
+    Err(miette!(
+      "The authentification can't save the token of the login endpoint"
+    ))
+  }
+
+  /// Remove the credentials from the credential store.
+  async fn logout(&self) -> Result<()> {
+    Err(miette!("The authentification doesn't support the logout"))
+  }
 
   /// The helpmessage displayed when the user is unauthorized.
   fn error_help_message(&self) -> String;
 }
 
+/// Add the `login` and `logout` commands. They call [CrudAuth::login] and [CrudAuth::logout].
+pub fn login_commands(app: Command) -> Command {
+  app
+    .subcommand(Command::new("login").about("Log in and save the credentials"))
+    .subcommand(Command::new("logout").about("Remove the saved credentials"))
+}
+
+/// Prompt a secret without echoing it.
+pub fn prompt_secret(prompt: &str) -> Result<String> {
+  let term = console::Term::stderr();
+  term.write_str(&format!("{prompt}: ")).into_diagnostic()?;
+  term
+    .read_secure_line()
+    .into_diagnostic()
+    .wrap_err_with(|| format!("Can't read the {}", prompt.to_lowercase()))
+}
+
+/// Read the argument `arg`, else the setting `key` of the profile selected by `--profile`, else
+/// the setting `key`.
+pub fn setting(matches: &ArgMatches, settings: &Config, arg: &str, key: &str) -> Option<String> {
+  if let Some(value) = matches.try_get_one::<String>(arg).ok().flatten() {
+    return Some(value.clone());
+  }
+  matches
+    .try_get_one::<String>("profile")
+    .ok()
+    .flatten()
+    .and_then(|profile| {
+      settings
+        .get_string(&format!("profile.{profile}.{key}"))
+        .ok()
+    })
+    .or_else(|| settings.get_string(key).ok())
+}
+
+/// Add the query parameter `key=value` to the request.
+pub fn append_query(request: &mut Request<()>, key: &str, value: &str) -> Result<()> {
+  let param = serde_qs::to_string(&BTreeMap::from([(key, value)])).into_diagnostic()?;
+  let uri = request.uri().clone();
+  let path_and_query = match uri.query() {
+    Some(query) => format!("{}?{query}&{param}", uri.path()),
+    None => format!("{}?{param}", uri.path()),
+  };
+  let mut parts = uri.into_parts();
+  parts.path_and_query = Some(path_and_query.parse::<PathAndQuery>().into_diagnostic()?);
+  *request.uri_mut() = Uri::from_parts(parts)
+    .into_diagnostic()
+    .wrap_err("Can't add the credentials to the URL")?;
+  Ok(())
+}
+
 #[cfg(test)]
 mod tests {
+  use super::{append_query, setting, Request};
+  use clap::{Arg, Command};
+  use config::Config;
+
   #[test]
-  fn it_works() {
-    let result = 2 + 2;
-    assert_eq!(result, 4);
+  fn test_setting() {
+    let settings = Config::builder()
+      .set_override("auth_token", "default")
+      .unwrap()
+      .set_override("profile.prod.auth_token", "prod")
+      .unwrap()
+      .build()
+      .unwrap();
+    let command = Command::new("test")
+      .arg(Arg::new("profile").long("profile"))
+      .arg(Arg::new("token").long("token"));
+    let setting = |args: &[&str]| {
+      let matches = command.clone().get_matches_from(args);
+      setting(&matches, &settings, "token", "auth_token")
+    };
+    assert_eq!(setting(&["test"]).as_deref(), Some("default"));
+    assert_eq!(
+      setting(&["test", "--profile", "prod"]).as_deref(),
+      Some("prod")
+    );
+    assert_eq!(
+      setting(&["test", "--profile", "dev"]).as_deref(),
+      Some("default")
+    );
+    assert_eq!(
+      setting(&["test", "--profile", "prod", "--token", "arg"]).as_deref(),
+      Some("arg")
+    );
+    assert_eq!(
+      super::setting(
+        &Command::new("test").get_matches_from(["test"]),
+        &Config::default(),
+        "token",
+        "auth_token"
+      ),
+      None
+    );
+  }
+
+  #[test]
+  fn test_append_query() {
+    let mut request = Request::get("https://api.local/posts?userId=1")
+      .body(())
+      .unwrap();
+    append_query(&mut request, "api_key", "a b&c").unwrap();
+    assert_eq!(
+      request.uri().to_string(),
+      "https://api.local/posts?userId=1&api_key=a+b%26c"
+    );
+
+    let mut request = Request::get("https://api.local/posts").body(()).unwrap();
+    append_query(&mut request, "api_key", "key").unwrap();
+    assert_eq!(
+      request.uri().to_string(),
+      "https://api.local/posts?api_key=key"
+    );
   }
 }
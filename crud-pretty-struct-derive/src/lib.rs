@@ -258,7 +258,7 @@ fn derive_struct(pretty: PrettyStruct) -> TokenStream {
       .unwrap_or_default();
 
   quote!(impl crud_pretty_struct::PrettyPrint for #pretty_ident {
-  fn meta(&self) ->crud_pretty_struct::Meta {
+  fn meta(&self) ->crud_pretty_struct::Meta<'_> {
       crud_pretty_struct::Meta {
     padding: #padding,
     separator: Some(#glyph),
@@ -413,7 +413,7 @@ fn derive_enum(pretty: PrettyStruct) -> TokenStream {
   };
 
   quote!(impl crud_pretty_struct::PrettyPrint for #pretty_ident {
-  fn meta(&self) ->crud_pretty_struct::Meta {
+  fn meta(&self) ->crud_pretty_struct::Meta<'_> {
       crud_pretty_struct::Meta {
     padding: 0,
     separator: None,
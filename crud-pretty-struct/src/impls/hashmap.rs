@@ -6,7 +6,7 @@ use pad::PadStr;
 impl<K: std::fmt::Display + PadStr, V: std::fmt::Display> PrettyPrint
   for std::collections::HashMap<K, V>
 {
-  fn meta(&self) -> Meta {
+  fn meta(&self) -> Meta<'_> {
     Meta {
       padding: 28,
       separator: None,
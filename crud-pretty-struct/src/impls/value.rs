@@ -7,7 +7,7 @@ use std::fmt::Write;
 use unicode_width::UnicodeWidthStr;
 
 impl PrettyPrint for Value {
-  fn meta(&self) -> Meta {
+  fn meta(&self) -> Meta<'_> {
     Meta {
       padding: 0,
       separator: None,
@@ -59,8 +59,7 @@ impl PrettyPrint for Value {
           let padding = 1 + o.keys().map(|k| k.width()).max().unwrap_or_default();
 
           o.iter()
-            .enumerate()
-            .map(|(_i, (k, v))| {
+            .map(|(k, v)| {
               let separator = match v {
                 Value::Array(_) | Value::Object(_) => "-->\n",
                 _ => separator,
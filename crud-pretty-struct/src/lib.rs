@@ -295,7 +295,7 @@ fn label_coloring(label: &str, colored: bool, color: &Option<Color>) -> String {
 }
 
 pub trait PrettyPrint {
-  fn meta(&self) -> Meta;
+  fn meta(&self) -> Meta<'_>;
   fn pretty(&self, colored: bool, prefix: Option<String>, profile: Option<&str>) -> Result<String> {
     let Meta {
       fields,
@@ -713,7 +713,7 @@ mod tests {
   fn empty_struct() {
     struct T1 {}
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 1,
           separator: None,
@@ -735,7 +735,7 @@ mod tests {
     }
 
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -802,7 +802,7 @@ mod tests {
     }
 
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -876,7 +876,7 @@ mod tests {
       cccc: bool,
     }
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -926,7 +926,7 @@ mod tests {
       n: T1,
     }
     impl PrettyPrint for T2 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 2,
           separator: None,
@@ -979,7 +979,7 @@ mod tests {
       cccc: bool,
     }
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: Some("-> "),
@@ -1045,7 +1045,7 @@ mod tests {
       cccc: bool,
     }
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -1111,7 +1111,7 @@ mod tests {
       cccc: bool,
     }
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -1177,7 +1177,7 @@ mod tests {
       cccc: bool,
     }
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -1243,7 +1243,7 @@ mod tests {
     }
 
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -1298,7 +1298,7 @@ mod tests {
     }
 
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -1352,7 +1352,7 @@ mod tests {
     }
 
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -1404,7 +1404,7 @@ mod tests {
     }
 
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -1459,7 +1459,7 @@ mod tests {
       cccc: bool,
     }
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -1509,7 +1509,7 @@ mod tests {
       n: Option<T1>,
     }
     impl PrettyPrint for T2 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 2,
           separator: None,
@@ -1572,7 +1572,7 @@ mod tests {
       cccc: bool,
     }
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -1622,7 +1622,7 @@ mod tests {
       n: Option<T1>,
     }
     impl PrettyPrint for T2 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 2,
           separator: None,
@@ -1682,7 +1682,7 @@ mod tests {
     }
 
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -1729,7 +1729,7 @@ mod tests {
     }
 
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -1776,7 +1776,7 @@ mod tests {
       cccc: bool,
     }
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -1826,7 +1826,7 @@ mod tests {
       n: Vec<T1>,
     }
     impl PrettyPrint for T2 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 2,
           separator: None,
@@ -1879,7 +1879,7 @@ mod tests {
     }
 
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -1945,7 +1945,7 @@ mod tests {
     }
 
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -2011,7 +2011,7 @@ mod tests {
     }
 
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -2077,7 +2077,7 @@ mod tests {
       cccc: bool,
     }
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -2127,7 +2127,7 @@ mod tests {
       n: Option<Vec<T1>>,
     }
     impl PrettyPrint for T2 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 2,
           separator: None,
@@ -2200,7 +2200,7 @@ mod tests {
       cccc: bool,
     }
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -2250,7 +2250,7 @@ mod tests {
       n: Option<Vec<T1>>,
     }
     impl PrettyPrint for T2 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 2,
           separator: None,
@@ -2336,7 +2336,7 @@ mod tests {
     }
 
     impl PrettyPrint for E1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -2372,7 +2372,7 @@ mod tests {
     }
 
     impl PrettyPrint for T1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -2424,7 +2424,7 @@ mod tests {
     }
 
     impl PrettyPrint for E1 {
-      fn meta(&self) -> Meta {
+      fn meta(&self) -> Meta<'_> {
         Meta {
           padding: 5,
           separator: None,
@@ -2,6 +2,8 @@
 //!
 //! Pretty printer for arrays.
 //!
+//! `display_table` prints the table on stdout, `write_table` writes it to any `Write`. `write_markdown_table`, `write_html_table` and `write_ascii_table` write the table without colors, to paste it in tickets and wikis.
+//!
 //! Some parts of the code is copied and refactored from [Tidy-viewer](https://github.com/alexhallam/tv) (released under public domain)
 //!
 //! ## Examples
@@ -23,9 +25,10 @@
 //! ```
 
 mod datatype;
-use calm_io::stdout;
-use calm_io::stdoutln;
+mod markup;
+pub use markup::{write_ascii_table, write_html_table, write_markdown_table};
 use owo_colors::OwoColorize;
+use std::io::{stdout, ErrorKind, Write};
 
 pub struct TableConfig {
   std_color: [u8; 3],
@@ -79,7 +82,21 @@ impl Default for TableConfig {
   }
 }
 
+/// Print the table on stdout. The first row is the header.
 pub fn display_table(rdr: &[Vec<String>], config: TableConfig) {
+  if let Err(error) = write_table(&mut stdout().lock(), rdr, config) {
+    if error.kind() != ErrorKind::BrokenPipe {
+      eprintln!("Can't print the table: {error}");
+    }
+  }
+}
+
+/// Write the table to `out`. The first row is the header.
+pub fn write_table(
+  out: &mut impl Write,
+  rdr: &[Vec<String>],
+  config: TableConfig,
+) -> std::io::Result<()> {
   /*
       This piece of code is copied and refactored from Tidy-viewer (released under public domain)
       Original source: https://github.com/alexhallam/tv
@@ -110,8 +127,8 @@ pub fn display_table(rdr: &[Vec<String>], config: TableConfig) {
   }
 
   if config.debug_mode {
-    println!("{:?}", "v");
-    println!("{v:?}");
+    writeln!(out, "{:?}", "v")?;
+    writeln!(out, "{v:?}")?;
   }
 
   if config.debug_mode {
@@ -120,8 +137,8 @@ pub fn display_table(rdr: &[Vec<String>], config: TableConfig) {
     for column in &v {
       vec_datatypes.push(datatype::get_col_data_type(column))
     }
-    println!("{:?}", "vec_datatypes");
-    println!("{vec_datatypes:?}");
+    writeln!(out, "{:?}", "vec_datatypes")?;
+    writeln!(out, "{vec_datatypes:?}")?;
   }
 
   // vector of formatted values
@@ -138,10 +155,10 @@ pub fn display_table(rdr: &[Vec<String>], config: TableConfig) {
     .collect();
 
   if config.debug_mode {
-    println!("{:?}", "Transposed Vector of Elements");
-    println!("{v:?}");
-    println!("{:?}", "Formatted: Vector of Elements");
-    println!("{vf:?}");
+    writeln!(out, "{:?}", "Transposed Vector of Elements")?;
+    writeln!(out, "{v:?}")?;
+    writeln!(out, "{:?}", "Formatted: Vector of Elements")?;
+    writeln!(out, "{vf:?}")?;
   }
 
   //  println!();
@@ -161,15 +178,10 @@ pub fn display_table(rdr: &[Vec<String>], config: TableConfig) {
   if config.display_meta {
     let meta_text = "tv dim:";
     let div = "x";
-    let _ = match stdout!("{: <6}", "") {
-      Ok(_) => Ok(()),
-      Err(e) => match e.kind() {
-        std::io::ErrorKind::BrokenPipe => Ok(()),
-        _ => Err(e),
-      },
-    };
+    write!(out, "{: <6}", "")?;
     if config.is_tty || config.is_force_color {
-      let _ = match stdoutln!(
+      writeln!(
+        out,
         "{} {} {} {}",
         meta_text.truecolor(
           config.meta_color[0],
@@ -191,34 +203,17 @@ pub fn display_table(rdr: &[Vec<String>], config: TableConfig) {
           config.meta_color[1],
           config.meta_color[2]
         ),
-      ) {
-        Ok(_) => Ok(()),
-        Err(e) => match e.kind() {
-          std::io::ErrorKind::BrokenPipe => Ok(()),
-          _ => Err(e),
-        },
-      };
+      )?;
     } else {
-      let _ = match stdoutln!("{} {} {} {}", meta_text, rows_in_file - 1, div, cols) {
-        Ok(_) => Ok(()),
-        Err(e) => match e.kind() {
-          std::io::ErrorKind::BrokenPipe => Ok(()),
-          _ => Err(e),
-        },
-      };
+      writeln!(out, "{} {} {} {}", meta_text, rows_in_file - 1, div, cols)?;
     }
   }
   // title
   if !datatype::is_na(&config.title_option) {
-    let _ = match stdout!("{: <6}", "") {
-      Ok(_) => Ok(()),
-      Err(e) => match e.kind() {
-        std::io::ErrorKind::BrokenPipe => Ok(()),
-        _ => Err(e),
-      },
-    };
+    write!(out, "{: <6}", "")?;
     if config.is_tty || config.is_force_color {
-      let _ = match stdoutln!(
+      writeln!(
+        out,
         "{}",
         config
           .title_option
@@ -229,39 +224,22 @@ pub fn display_table(rdr: &[Vec<String>], config: TableConfig) {
           )
           .underline()
           .bold()
-      ) {
-        Ok(_) => Ok(()),
-        Err(e) => match e.kind() {
-          std::io::ErrorKind::BrokenPipe => Ok(()),
-          _ => Err(e),
-        },
-      };
+      )?;
     } else {
-      let _ = match stdoutln!("{}", config.title_option) {
-        Ok(_) => Ok(()),
-        Err(e) => match e.kind() {
-          std::io::ErrorKind::BrokenPipe => Ok(()),
-          _ => Err(e),
-        },
-      };
+      writeln!(out, "{}", config.title_option)?;
     }
   }
 
   // header
   if config.line_counter {
-    let _ = match stdout!("{: <6}", "") {
-      Ok(_) => Ok(()),
-      Err(e) => match e.kind() {
-        std::io::ErrorKind::BrokenPipe => Ok(()),
-        _ => Err(e),
-      },
-    };
+    write!(out, "{: <6}", "")?;
   }
   //for col in 0..cols {
   for col in 0..num_cols_to_print {
     let text = vp[0].get(col).unwrap().to_string();
     if config.is_tty || config.is_force_color {
-      let _ = match stdout!(
+      write!(
+        out,
         "{}",
         text
           .truecolor(
@@ -270,21 +248,9 @@ pub fn display_table(rdr: &[Vec<String>], config: TableConfig) {
             config.header_color[2]
           )
           .bold()
-      ) {
-        Ok(_) => Ok(()),
-        Err(e) => match e.kind() {
-          std::io::ErrorKind::BrokenPipe => Ok(()),
-          _ => Err(e),
-        },
-      };
+      )?;
     } else {
-      let _ = match stdout!("{}", text) {
-        Ok(_) => Ok(()),
-        Err(e) => match e.kind() {
-          std::io::ErrorKind::BrokenPipe => Ok(()),
-          _ => Err(e),
-        },
-      };
+      write!(out, "{}", text)?;
     }
   }
   //println!();
@@ -297,121 +263,66 @@ pub fn display_table(rdr: &[Vec<String>], config: TableConfig) {
   //    owned_string.push_str(borrowed_string);
   //    print!("{}",owned_string.truecolor(143, 188, 187).bold());
   //}
-  let _ = match stdoutln!() {
-    Ok(_) => Ok(()),
-    Err(e) => match e.kind() {
-      std::io::ErrorKind::BrokenPipe => Ok(()),
-      _ => Err(e),
-    },
-  };
-  vp.iter()
-    .enumerate()
-    .take(rows)
-    .skip(1)
-    .for_each(|(i, row)| {
-      if config.line_counter {
-        if config.is_tty || config.is_force_color {
-          let _ = match stdout!(
-            "{: <6}",
-            i.truecolor(
-              config.meta_color[0],
-              config.meta_color[1],
-              config.meta_color[2]
+  writeln!(out)?;
+  for (i, row) in vp.iter().enumerate().take(rows).skip(1) {
+    if config.line_counter {
+      if config.is_tty || config.is_force_color {
+        write!(
+          out,
+          "{: <6}",
+          i.truecolor(
+            config.meta_color[0],
+            config.meta_color[1],
+            config.meta_color[2]
+          )
+        )?;
+      } else {
+        write!(out, "{: <6}", i)?;
+      }
+    }
+    for col in row.iter().take(num_cols_to_print) {
+      if config.is_tty || config.is_force_color {
+        write!(
+          out,
+          "{}",
+          if datatype::is_na_string_padded(col) {
+            col.truecolor(config.na_color[0], config.na_color[1], config.na_color[2])
+          } else if datatype::is_number(col) && datatype::is_negative_number(col) {
+            col.truecolor(
+              config.neg_num_color[0],
+              config.neg_num_color[1],
+              config.neg_num_color[2],
             )
-          ) {
-            Ok(_) => Ok(()),
-            Err(e) => match e.kind() {
-              std::io::ErrorKind::BrokenPipe => Ok(()),
-              _ => Err(e),
-            },
-          };
-        } else {
-          let _ = match stdout!("{: <6}", i) {
-            Ok(_) => Ok(()),
-            Err(e) => match e.kind() {
-              std::io::ErrorKind::BrokenPipe => Ok(()),
-              _ => Err(e),
-            },
-          };
-        }
+          } else {
+            col.truecolor(
+              config.std_color[0],
+              config.std_color[1],
+              config.std_color[2],
+            )
+          }
+        )?;
+      } else {
+        write!(out, "{}", col)?;
       }
-      row.iter().take(num_cols_to_print).for_each(|col| {
-        if config.is_tty || config.is_force_color {
-          let _ = match stdout!(
-            "{}",
-            if datatype::is_na_string_padded(col) {
-              col.truecolor(config.na_color[0], config.na_color[1], config.na_color[2])
-            } else if datatype::is_number(col) && datatype::is_negative_number(col) {
-              col.truecolor(
-                config.neg_num_color[0],
-                config.neg_num_color[1],
-                config.neg_num_color[2],
-              )
-            } else {
-              col.truecolor(
-                config.std_color[0],
-                config.std_color[1],
-                config.std_color[2],
-              )
-            }
-          ) {
-            Ok(_) => Ok(()),
-            Err(e) => match e.kind() {
-              std::io::ErrorKind::BrokenPipe => Ok(()),
-              _ => Err(e),
-            },
-          };
-        } else {
-          let _ = match stdout!("{}", col) {
-            Ok(_) => Ok(()),
-            Err(e) => match e.kind() {
-              std::io::ErrorKind::BrokenPipe => Ok(()),
-              _ => Err(e),
-            },
-          };
-        }
-      });
-      let _ = match stdoutln!() {
-        Ok(_) => Ok(()),
-        Err(e) => match e.kind() {
-          std::io::ErrorKind::BrokenPipe => Ok(()),
-          _ => Err(e),
-        },
-      };
-    });
+    }
+    writeln!(out)?;
+  }
 
   // additional row info
   if rows_remaining > 0 {
-    let _ = match stdout!("{: <6}", "") {
-      Ok(_) => Ok(()),
-      Err(e) => match e.kind() {
-        std::io::ErrorKind::BrokenPipe => Ok(()),
-        _ => Err(e),
-      },
-    };
+    write!(out, "{: <6}", "")?;
     if config.is_tty || config.is_force_color {
-      let _ = match stdout!(
+      write!(
+        out,
         "{}",
         row_remaining_text.truecolor(
           config.meta_color[0],
           config.meta_color[1],
           config.meta_color[2]
         )
-      ) {
-        Ok(_) => Ok(()),
-        Err(e) => match e.kind() {
-          std::io::ErrorKind::BrokenPipe => Ok(()),
-          _ => Err(e),
-        },
-      };
+      )?;
     } else {
-      let _ = match stdout!("{}", row_remaining_text) {
-        Ok(_) => Ok(()),
-        Err(e) => match e.kind() {
-          std::io::ErrorKind::BrokenPipe => Ok(()),
-          _ => Err(e),
-        },
-      };
+      write!(out, "{}", row_remaining_text)?;
     }
     let extra_cols_to_mention = num_cols_to_print;
     let remainder_cols = cols - extra_cols_to_mention;
@@ -421,7 +332,8 @@ pub fn display_table(rdr: &[Vec<String>], config: TableConfig) {
       let meta_text_comma = ",";
       let meta_text_colon = ":";
       if config.is_tty || config.is_force_color {
-        let _ = match stdout!(
+        write!(
+          out,
           " {} {} {}{}",
           meta_text_and.truecolor(
             config.meta_color[0],
@@ -443,80 +355,44 @@ pub fn display_table(rdr: &[Vec<String>], config: TableConfig) {
             config.meta_color[1],
             config.meta_color[2]
           )
-        ) {
-          Ok(_) => Ok(()),
-          Err(e) => match e.kind() {
-            std::io::ErrorKind::BrokenPipe => Ok(()),
-            _ => Err(e),
-          },
-        };
+        )?;
       } else {
-        let _ = match stdout!(
+        write!(
+          out,
           " {} {} {}{}",
-          meta_text_and,
-          remainder_cols,
-          meta_text_var,
-          meta_text_colon
-        ) {
-          Ok(_) => Ok(()),
-          Err(e) => match e.kind() {
-            std::io::ErrorKind::BrokenPipe => Ok(()),
-            _ => Err(e),
-          },
-        };
+          meta_text_and, remainder_cols, meta_text_var, meta_text_colon
+        )?;
       }
       for col in extra_cols_to_mention..cols {
         let text = rdr[0].get(col).unwrap();
         if config.is_tty || config.is_force_color {
-          let _ = match stdout!(
+          write!(
+            out,
             " {}",
             text.truecolor(
               config.meta_color[0],
               config.meta_color[1],
               config.meta_color[2]
             )
-          ) {
-            Ok(_) => Ok(()),
-            Err(e) => match e.kind() {
-              std::io::ErrorKind::BrokenPipe => Ok(()),
-              _ => Err(e),
-            },
-          };
+          )?;
         } else {
-          let _ = match stdout!(" {}", text) {
-            Ok(_) => Ok(()),
-            Err(e) => match e.kind() {
-              std::io::ErrorKind::BrokenPipe => Ok(()),
-              _ => Err(e),
-            },
-          };
+          write!(out, " {}", text)?;
         }
 
         // The last column mentioned in foot should not be followed by a comma
         if col + 1 < cols {
           if config.is_tty || config.is_force_color {
-            let _ = match stdout!(
+            write!(
+              out,
               "{}",
               meta_text_comma.truecolor(
                 config.meta_color[0],
                 config.meta_color[1],
                 config.meta_color[2]
               )
-            ) {
-              Ok(_) => Ok(()),
-              Err(e) => match e.kind() {
-                std::io::ErrorKind::BrokenPipe => Ok(()),
-                _ => Err(e),
-              },
-            };
+            )?;
           } else {
-            let _ = match stdout!("{}", meta_text_comma) {
-              Ok(_) => Ok(()),
-              Err(e) => match e.kind() {
-                std::io::ErrorKind::BrokenPipe => Ok(()),
-                _ => Err(e),
-              },
-            };
+            write!(out, "{}", meta_text_comma)?;
           }
         }
       } // end extra cols mentioned in footer
@@ -525,38 +401,22 @@ pub fn display_table(rdr: &[Vec<String>], config: TableConfig) {
 
   // footer
   if !datatype::is_na(&config.footer_option) {
-    let _ = match stdout!("{: <6}", "") {
-      Ok(_) => Ok(()),
-      Err(e) => match e.kind() {
-        std::io::ErrorKind::BrokenPipe => Ok(()),
-        _ => Err(e),
-      },
-    };
+    write!(out, "{: <6}", "")?;
     if config.is_tty || config.is_force_color {
-      let _ = match stdoutln!(
+      writeln!(
+        out,
         "{}",
         config.footer_option.truecolor(
           config.meta_color[0],
           config.meta_color[1],
           config.meta_color[2]
         )
-      ) {
-        Ok(_) => Ok(()),
-        Err(e) => match e.kind() {
-          std::io::ErrorKind::BrokenPipe => Ok(()),
-          _ => Err(e),
-        },
-      };
+      )?;
     } else {
-      let _ = match stdoutln!("{}", config.footer_option) {
-        Ok(_) => Ok(()),
-        Err(e) => match e.kind() {
-          std::io::ErrorKind::BrokenPipe => Ok(()),
-          _ => Err(e),
-        },
-      };
+      writeln!(out, "{}", config.footer_option)?;
     }
   }
+  Ok(())
 }
 
 // how wide will the print be?
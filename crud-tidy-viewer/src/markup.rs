@@ -0,0 +1,230 @@
+//! Tables without colors, to paste in tickets and wikis: Markdown, HTML and ASCII boxes.
+//!
+//! The first row of the table is the header. The numeric columns are aligned on the right.
+
+use crate::datatype::{is_na, is_number};
+use std::io::{Result, Write};
+use unicode_width::UnicodeWidthStr;
+
+/// Write the table as a Markdown (GFM) table.
+pub fn write_markdown_table(out: &mut impl Write, rdr: &[Vec<String>]) -> Result<()> {
+  let table: Vec<Vec<String>> = cells(rdr, |cell| {
+    cell
+      .replace('\\', "\\\\")
+      .replace('|', "\\|")
+      .replace("\r\n", "<br>")
+      .replace('\n', "<br>")
+  });
+  let widths = widths(&table, 3);
+  let numeric = numeric_columns(rdr);
+  let Some((header, rows)) = table.split_first() else {
+    return Ok(());
+  };
+
+  write_row(out, header, &widths, &vec![false; widths.len()])?;
+  let rule: Vec<String> = widths
+    .iter()
+    .zip(&numeric)
+    .map(|(width, numeric)| {
+      if *numeric {
+        format!("{}:", "-".repeat(width - 1))
+      } else {
+        "-".repeat(*width)
+      }
+    })
+    .collect();
+  writeln!(out, "| {} |", rule.join(" | "))?;
+  for row in rows {
+    write_row(out, row, &widths, &numeric)?;
+  }
+  Ok(())
+}
+
+/// Write the table as an HTML `<table>`.
+pub fn write_html_table(out: &mut impl Write, rdr: &[Vec<String>]) -> Result<()> {
+  let table: Vec<Vec<String>> = cells(rdr, |cell| {
+    cell
+      .replace('&', "&amp;")
+      .replace('<', "&lt;")
+      .replace('>', "&gt;")
+      .replace('"', "&quot;")
+      .replace('\n', "<br>")
+  });
+  let numeric = numeric_columns(rdr);
+  let Some((header, rows)) = table.split_first() else {
+    return Ok(());
+  };
+
+  writeln!(out, "<table>")?;
+  writeln!(out, "  <thead>")?;
+  writeln!(
+    out,
+    "    <tr>{}</tr>",
+    header
+      .iter()
+      .map(|cell| format!("<th>{cell}</th>"))
+      .collect::<String>()
+  )?;
+  writeln!(out, "  </thead>")?;
+  writeln!(out, "  <tbody>")?;
+  for row in rows {
+    writeln!(
+      out,
+      "    <tr>{}</tr>",
+      row
+        .iter()
+        .zip(&numeric)
+        .map(|(cell, numeric)| if *numeric {
+          format!("<td align=\"right\">{cell}</td>")
+        } else {
+          format!("<td>{cell}</td>")
+        })
+        .collect::<String>()
+    )?;
+  }
+  writeln!(out, "  </tbody>")?;
+  writeln!(out, "</table>")
+}
+
+/// Write the table in ASCII boxes.
+pub fn write_ascii_table(out: &mut impl Write, rdr: &[Vec<String>]) -> Result<()> {
+  let table: Vec<Vec<String>> = cells(rdr, |cell| cell.replace("\r\n", " ").replace('\n', " "));
+  let widths = widths(&table, 1);
+  let numeric = numeric_columns(rdr);
+  let Some((header, rows)) = table.split_first() else {
+    return Ok(());
+  };
+
+  let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+  let rule = format!("+-{}-+", rule.join("-+-"));
+  writeln!(out, "{rule}")?;
+  write_row(out, header, &widths, &vec![false; widths.len()])?;
+  writeln!(out, "{rule}")?;
+  for row in rows {
+    write_row(out, row, &widths, &numeric)?;
+  }
+  if !rows.is_empty() {
+    writeln!(out, "{rule}")?;
+  }
+  Ok(())
+}
+
+/// The escaped cells of the table.
+fn cells(rdr: &[Vec<String>], escape: impl Fn(&str) -> String) -> Vec<Vec<String>> {
+  rdr
+    .iter()
+    .map(|row| row.iter().map(|cell| escape(cell)).collect())
+    .collect()
+}
+
+/// The width of each column, at least `min`.
+fn widths(table: &[Vec<String>], min: usize) -> Vec<usize> {
+  let mut widths = vec![min; table.first().map_or(0, Vec::len)];
+  for row in table {
+    for (width, cell) in widths.iter_mut().zip(row) {
+      *width = (*width).max(cell.width());
+    }
+  }
+  widths
+}
+
+/// The columns of numbers (the missing values are ignored).
+fn numeric_columns(rdr: &[Vec<String>]) -> Vec<bool> {
+  let cols = rdr.first().map_or(0, Vec::len);
+  (0..cols)
+    .map(|col| {
+      let mut values = rdr
+        .iter()
+        .skip(1)
+        .filter_map(|row| row.get(col))
+        .filter(|cell| !is_na(cell))
+        .peekable();
+      values.peek().is_some() && values.all(|cell| is_number(cell))
+    })
+    .collect()
+}
+
+/// Write a row of padded cells between `|`.
+fn write_row(out: &mut impl Write, row: &[String], widths: &[usize], numeric: &[bool]) -> Result<()> {
+  let cells: Vec<String> = row
+    .iter()
+    .zip(widths.iter().zip(numeric))
+    .map(|(cell, (width, numeric))| {
+      let padding = " ".repeat(width.saturating_sub(cell.width()));
+      if *numeric {
+        format!("{padding}{cell}")
+      } else {
+        format!("{cell}{padding}")
+      }
+    })
+    .collect();
+  writeln!(out, "| {} |", cells.join(" | "))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{write_ascii_table, write_html_table, write_markdown_table};
+
+  fn table() -> Vec<Vec<String>> {
+    [
+      ["id", "title", "size"],
+      ["1", "Crash | <on> start", "2.5"],
+      ["12", "Café", ""],
+    ]
+    .iter()
+    .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+    .collect()
+  }
+
+  fn render(write: fn(&mut Vec<u8>, &[Vec<String>]) -> std::io::Result<()>) -> String {
+    let mut out = vec![];
+    write(&mut out, &table()).unwrap();
+    String::from_utf8(out).unwrap()
+  }
+
+  #[test]
+  fn test_markdown() {
+    assert_eq!(
+      render(write_markdown_table),
+      "\
+| id  | title               | size |
+| --: | ------------------- | ---: |
+|   1 | Crash \\| <on> start |  2.5 |
+|  12 | Café                |      |
+"
+    );
+  }
+
+  #[test]
+  fn test_html() {
+    assert_eq!(
+      render(write_html_table),
+      "\
+<table>
+  <thead>
+    <tr><th>id</th><th>title</th><th>size</th></tr>
+  </thead>
+  <tbody>
+    <tr><td align=\"right\">1</td><td>Crash | &lt;on&gt; start</td><td align=\"right\">2.5</td></tr>
+    <tr><td align=\"right\">12</td><td>Café</td><td align=\"right\"></td></tr>
+  </tbody>
+</table>
+"
+    );
+  }
+
+  #[test]
+  fn test_ascii() {
+    assert_eq!(
+      render(write_ascii_table),
+      "\
++----+--------------------+------+
+| id | title              | size |
++----+--------------------+------+
+|  1 | Crash | <on> start |  2.5 |
+| 12 | Café               |      |
++----+--------------------+------+
+"
+    );
+  }
+}
@@ -29,12 +29,39 @@
 //!
 //! ### Runtime Settings
 //!
-//! File `settings.toml`
+//! The settings are merged from these sources, the last ones override the first ones:
+//! 1. `/etc/<app>/settings.toml` (unix only),
+//! 2. `settings.toml` in the user configuration directory (`config path` prints it),
+//! 3. `.<app>.toml` in the current directory or the nearest parent, which can be committed with a project,
+//! 4. the file given by `--config <path>`,
+//! 5. the environment variables (`<ENV_PREFIX>_BASE_URL`...),
+//! 6. the arguments.
 //!
-//! | option     | description          |                            |
-//! |------------|----------------------|----------------------------|
-//! | base_url   | Base url of the api  |                            |
-//! | auth_token | token send as bearer | read by `crud-auth-bearer` |
+//! `config show --origin` prints the effective settings and where they come from. `config show`
+//! and `config list` redact the secrets (`auth_token`, `auth_password`...) unless `--show-secrets`
+//! is given.
+//!
+//! The settings files accept these options:
+//!
+//! | option            | description                                    |                                                  |
+//! |-------------------|------------------------------------------------|--------------------------------------------------|
+//! | base_url          | Base url of the api                            |                                                  |
+//! | auth_token        | token send as bearer                           | read by `crud-auth-bearer`                       |
+//! | auth_user         | user of the Basic authentification             | read by `crud-auth-basic`, with `auth_password`  |
+//! | api_key           | API key                                        | read by `crud-auth-api-key`                      |
+//! | oauth2_*          | OAuth2 client, endpoints and flow              | read by `crud-auth-oauth2`                       |
+//! | credential_helper | command storing the secrets of `login`         | default: `credentials.toml` next to the settings |
+//! | proxy             | HTTP proxy (`http://host:port`)                | default: `HTTP_PROXY`, `HTTPS_PROXY`, `NO_PROXY` |
+//! | ca_cert           | PEM file of additional certificate authorities |                                                  |
+//! | system_certs      | Trust the system certificates store            | `true` or `false`                                |
+//! | client_cert       | PEM file of the client certificate             | mutual TLS, with `client_key`                    |
+//! | client_key        | PEM file of the client private key             | mutual TLS, with `client_cert`                   |
+//! | insecure          | Don't verify the server certificate            | `true` or `false`                                |
+//! | timeout           | Timeout of the requests in seconds             | default: 30                                      |
+//! | retries           | Number of retries of the failed requests       | default: no retry                                |
+//! | cache             | Cache the responses of all the `GET` endpoints | `true` or `false`, see `cache_ttl` of endpoints  |
+//! | cache_ttl         | Seconds a cached response is used as is        | default: 0, always revalidated                   |
+//! | default_profile   | Profile used when `--profile` is omitted       | set by `config profile use`                      |
 //!
 //! #### Profiles
 //!
@@ -49,6 +76,13 @@
 //! ```
 //!
 //! You call the profiles with the `--profile` argument.
+//!
+//! The `config` command edits the settings and the profiles:
+//! ```shell
+//! $ myapp config profile add p1
+//! $ myapp --profile p1 config set base_url https://p1.example.com
+//! $ myapp config profile use p1
+//! ```
 
 extern crate crud_derive;
 #[doc(hidden)]